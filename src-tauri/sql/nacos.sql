@@ -54,6 +54,7 @@ CREATE TABLE `pipeline` (
   `last_run_time` varchar(255) CHARACTER SET utf8mb4 COLLATE utf8mb4_0900_ai_ci DEFAULT NULL COMMENT '最后运行时间',
   `last_run_id` varchar(255) DEFAULT NULL COMMENT '最后运行ID',
   `status` varchar(255) CHARACTER SET utf8mb4 COLLATE utf8mb4_0900_ai_ci DEFAULT NULL COMMENT '当前运行状态',
+  `options` longtext COMMENT '附加配置, 如下游流水线',
   `create_time` varchar(255) DEFAULT NULL,
   `update_time` varchar(255) DEFAULT NULL,
   PRIMARY KEY (`id`)
@@ -116,12 +117,49 @@ CREATE TABLE `pipeline_runtime` (
   `status` varchar(255) DEFAULT NULL COMMENT '运行状态',
   `start_time` varchar(255) DEFAULT NULL COMMENT '开始时间',
   `duration` varchar(255) DEFAULT NULL COMMENT '运行时长, 单位秒',
+  `duration_ms` bigint DEFAULT NULL COMMENT '运行时长, 单位毫秒',
   `stage_index` int DEFAULT NULL COMMENT 'stage 运行到哪一步, 从 1 开始计算',
   `group_index` int DEFAULT NULL COMMENT 'group 运行到哪一步, 从 0 开始计算',
   `step_index` int DEFAULT NULL COMMENT 'step 运行到哪一步, 从 0 开始计算',
   `finished` varchar(255) CHARACTER SET utf8mb4 COLLATE utf8mb4_0900_ai_ci DEFAULT NULL COMMENT '是否完成',
   `remark` varchar(255) DEFAULT NULL COMMENT '运行备注',
   `log` varchar(500) DEFAULT NULL COMMENT '日志文件地址',
+  `trigger_runtime_id` varchar(255) DEFAULT NULL COMMENT '触发运行的上游运行记录ID',
+  `trigger_pipeline_id` varchar(255) DEFAULT NULL COMMENT '触发运行的上游流水线ID',
+  `parent_runtime_id` varchar(255) DEFAULT NULL COMMENT '矩阵构建的父运行记录ID',
+  `matrix` varchar(500) DEFAULT NULL COMMENT '矩阵构建的变量组合',
+  `is_matrix` varchar(255) DEFAULT NULL COMMENT '是否为矩阵构建的父运行记录',
+  `priority` int DEFAULT 0 COMMENT '运行优先级, 越大越先执行',
+  `interrupted` varchar(255) DEFAULT NULL COMMENT '是否因程序退出而中断',
+  `replay_runtime_id` varchar(255) DEFAULT NULL COMMENT '回放的运行记录ID',
+  `approval_result` varchar(255) DEFAULT NULL COMMENT '审批结果',
+  `approver` varchar(255) DEFAULT NULL COMMENT '审批人',
+  `approval_comment` varchar(1000) DEFAULT NULL COMMENT '审批意见',
+  `approval_deadline` bigint DEFAULT NULL COMMENT '审批截止时间, 单位毫秒, 0 为不超时',
+  `approval_time` varchar(255) DEFAULT NULL COMMENT '审批时间',
+  `commit_sha` varchar(255) DEFAULT NULL COMMENT '构建的 commit SHA',
+  `commit_author` varchar(255) DEFAULT NULL COMMENT '提交人',
+  `commit_message` varchar(1000) DEFAULT NULL COMMENT '提交信息',
+  `create_time` varchar(255) DEFAULT NULL,
+  `update_time` varchar(255) DEFAULT NULL,
+  PRIMARY KEY (`id`) USING BTREE
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_0900_ai_ci;
+
+-- ----------------------------
+-- Table structure for pipeline_runtime_step
+-- ----------------------------
+DROP TABLE IF EXISTS `pipeline_runtime_step`;
+CREATE TABLE `pipeline_runtime_step` (
+  `id` varchar(255) NOT NULL,
+  `runtime_id` varchar(255) DEFAULT NULL COMMENT '运行记录ID',
+  `pipeline_id` varchar(255) DEFAULT NULL COMMENT '流水线ID',
+  `stage_index` int DEFAULT NULL COMMENT 'stage 序号, 从 1 开始计算',
+  `group_index` int DEFAULT NULL COMMENT 'group 序号, 从 0 开始计算',
+  `step_index` int DEFAULT NULL COMMENT 'step 序号, 从 0 开始计算',
+  `module` varchar(255) DEFAULT NULL COMMENT '步骤类型',
+  `label` varchar(255) DEFAULT NULL COMMENT '步骤名称',
+  `status` varchar(255) DEFAULT NULL COMMENT '运行状态',
+  `duration_ms` bigint DEFAULT NULL COMMENT '运行时长, 单位毫秒',
   `create_time` varchar(255) DEFAULT NULL,
   `update_time` varchar(255) DEFAULT NULL,
   PRIMARY KEY (`id`) USING BTREE
@@ -136,6 +174,7 @@ CREATE TABLE `pipeline_runtime_snapshot` (
   `runtime_id` varchar(255) CHARACTER SET utf8mb4 COLLATE utf8mb4_0900_ai_ci DEFAULT NULL COMMENT '运行记录ID',
   `node` varchar(255) DEFAULT NULL COMMENT 'nodeJs 版本号',
   `branch` varchar(255) DEFAULT NULL COMMENT '分支',
+  `revision` varchar(255) DEFAULT NULL COMMENT 'tag 或 commit SHA',
   `make` varchar(255) DEFAULT NULL COMMENT 'Make 命令',
   `command` varchar(255) DEFAULT NULL COMMENT '本机安装的命令',
   `script` varchar(255) DEFAULT NULL COMMENT 'package.json 中的 scripts 命令',
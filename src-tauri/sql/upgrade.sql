@@ -0,0 +1,83 @@
+/*
+ 升级脚本, 为已有数据库补充新增的字段和表, 可重复执行
+
+ Target Server Type    : MySQL
+ Target Server Version : 80039 (8.0.39)
+ File Encoding         : 65001
+*/
+
+SET NAMES utf8mb4;
+SET FOREIGN_KEY_CHECKS = 0;
+
+-- ----------------------------
+-- 字段不存在时添加
+-- ----------------------------
+DROP PROCEDURE IF EXISTS `n_nacos_add_column`;
+DELIMITER ;;
+CREATE PROCEDURE `n_nacos_add_column`(IN table_name_in varchar(255), IN column_name_in varchar(255), IN definition_in varchar(1000))
+BEGIN
+  IF NOT EXISTS (
+    SELECT 1 FROM information_schema.COLUMNS
+    WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = table_name_in AND COLUMN_NAME = column_name_in
+  ) THEN
+    SET @n_nacos_sql = CONCAT('ALTER TABLE `', table_name_in, '` ADD COLUMN `', column_name_in, '` ', definition_in);
+    PREPARE n_nacos_stmt FROM @n_nacos_sql;
+    EXECUTE n_nacos_stmt;
+    DEALLOCATE PREPARE n_nacos_stmt;
+  END IF;
+END;;
+DELIMITER ;
+
+-- ----------------------------
+-- pipeline
+-- ----------------------------
+CALL `n_nacos_add_column`('pipeline', 'options', 'longtext COMMENT ''附加配置, 如下游流水线'' AFTER `status`');
+
+-- ----------------------------
+-- pipeline_runtime
+-- ----------------------------
+CALL `n_nacos_add_column`('pipeline_runtime', 'duration_ms', 'bigint DEFAULT NULL COMMENT ''运行时长, 单位毫秒'' AFTER `duration`');
+CALL `n_nacos_add_column`('pipeline_runtime', 'trigger_runtime_id', 'varchar(255) DEFAULT NULL COMMENT ''触发运行的上游运行记录ID'' AFTER `log`');
+CALL `n_nacos_add_column`('pipeline_runtime', 'trigger_pipeline_id', 'varchar(255) DEFAULT NULL COMMENT ''触发运行的上游流水线ID'' AFTER `trigger_runtime_id`');
+CALL `n_nacos_add_column`('pipeline_runtime', 'parent_runtime_id', 'varchar(255) DEFAULT NULL COMMENT ''矩阵构建的父运行记录ID'' AFTER `trigger_pipeline_id`');
+CALL `n_nacos_add_column`('pipeline_runtime', 'matrix', 'varchar(500) DEFAULT NULL COMMENT ''矩阵构建的变量组合'' AFTER `parent_runtime_id`');
+CALL `n_nacos_add_column`('pipeline_runtime', 'is_matrix', 'varchar(255) DEFAULT NULL COMMENT ''是否为矩阵构建的父运行记录'' AFTER `matrix`');
+CALL `n_nacos_add_column`('pipeline_runtime', 'priority', 'int DEFAULT 0 COMMENT ''运行优先级, 越大越先执行'' AFTER `is_matrix`');
+CALL `n_nacos_add_column`('pipeline_runtime', 'interrupted', 'varchar(255) DEFAULT NULL COMMENT ''是否因程序退出而中断'' AFTER `priority`');
+CALL `n_nacos_add_column`('pipeline_runtime', 'replay_runtime_id', 'varchar(255) DEFAULT NULL COMMENT ''回放的运行记录ID'' AFTER `interrupted`');
+CALL `n_nacos_add_column`('pipeline_runtime', 'approval_result', 'varchar(255) DEFAULT NULL COMMENT ''审批结果'' AFTER `replay_runtime_id`');
+CALL `n_nacos_add_column`('pipeline_runtime', 'approver', 'varchar(255) DEFAULT NULL COMMENT ''审批人'' AFTER `approval_result`');
+CALL `n_nacos_add_column`('pipeline_runtime', 'approval_comment', 'varchar(1000) DEFAULT NULL COMMENT ''审批意见'' AFTER `approver`');
+CALL `n_nacos_add_column`('pipeline_runtime', 'approval_deadline', 'bigint DEFAULT NULL COMMENT ''审批截止时间, 单位毫秒, 0 为不超时'' AFTER `approval_comment`');
+CALL `n_nacos_add_column`('pipeline_runtime', 'approval_time', 'varchar(255) DEFAULT NULL COMMENT ''审批时间'' AFTER `approval_deadline`');
+CALL `n_nacos_add_column`('pipeline_runtime', 'commit_sha', 'varchar(255) DEFAULT NULL COMMENT ''构建的 commit SHA'' AFTER `approval_time`');
+CALL `n_nacos_add_column`('pipeline_runtime', 'commit_author', 'varchar(255) DEFAULT NULL COMMENT ''提交人'' AFTER `commit_sha`');
+CALL `n_nacos_add_column`('pipeline_runtime', 'commit_message', 'varchar(1000) DEFAULT NULL COMMENT ''提交信息'' AFTER `commit_author`');
+
+-- ----------------------------
+-- pipeline_runtime_snapshot
+-- ----------------------------
+CALL `n_nacos_add_column`('pipeline_runtime_snapshot', 'revision', 'varchar(255) DEFAULT NULL COMMENT ''tag 或 commit SHA'' AFTER `branch`');
+
+DROP PROCEDURE IF EXISTS `n_nacos_add_column`;
+
+-- ----------------------------
+-- Table structure for pipeline_runtime_step
+-- ----------------------------
+CREATE TABLE IF NOT EXISTS `pipeline_runtime_step` (
+  `id` varchar(255) NOT NULL,
+  `runtime_id` varchar(255) DEFAULT NULL COMMENT '运行记录ID',
+  `pipeline_id` varchar(255) DEFAULT NULL COMMENT '流水线ID',
+  `stage_index` int DEFAULT NULL COMMENT 'stage 序号, 从 1 开始计算',
+  `group_index` int DEFAULT NULL COMMENT 'group 序号, 从 0 开始计算',
+  `step_index` int DEFAULT NULL COMMENT 'step 序号, 从 0 开始计算',
+  `module` varchar(255) DEFAULT NULL COMMENT '步骤类型',
+  `label` varchar(255) DEFAULT NULL COMMENT '步骤名称',
+  `status` varchar(255) DEFAULT NULL COMMENT '运行状态',
+  `duration_ms` bigint DEFAULT NULL COMMENT '运行时长, 单位毫秒',
+  `create_time` varchar(255) DEFAULT NULL,
+  `update_time` varchar(255) DEFAULT NULL,
+  PRIMARY KEY (`id`) USING BTREE
+) ENGINE=InnoDB DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_0900_ai_ci;
+
+SET FOREIGN_KEY_CHECKS = 1;
@@ -19,6 +19,15 @@ const PIPELINE_EXEC_STEP_RES_EVENT_NAME: &str = "pipeline_exec_step_response";
 /// 流水线运行步骤发送通知事件名称
 const PIPELINE_EXEC_STEP_NOTICE_RES_EVENT_NAME: &str = "pipeline_exec_step_notice";
 
+/// 流水线日志搜索进度事件名称
+const PIPELINE_LOG_SEARCH_EVENT_NAME: &str = "pipeline_log_search_progress";
+
+/// 流水线运行步骤等待审批事件名称
+const PIPELINE_EXEC_STEP_APPROVAL_EVENT_NAME: &str = "pipeline_exec_step_approval";
+
+/// 打开运行详情事件名称, 点击系统通知回到应用时发送
+const PIPELINE_OPEN_RUNTIME_EVENT_NAME: &str = "pipeline_open_runtime";
+
 /// 监控结果事件名称
 const MONITOR_RES_EVENT_NAME: &str = "monitor_response";
 
@@ -99,6 +108,27 @@ impl EventEmitter {
                 Self::emit_response(app, MONITOR_RES_EVENT_NAME, response)
             }
         }
+
+        // log search progress
+        if index == 5 {
+            if let Some(response) = response.clone() {
+                Self::emit_response(app, PIPELINE_LOG_SEARCH_EVENT_NAME, response)
+            }
+        }
+
+        // step approval
+        if index == 6 {
+            if let Some(response) = response.clone() {
+                Self::emit_response(app, PIPELINE_EXEC_STEP_APPROVAL_EVENT_NAME, response)
+            }
+        }
+
+        // open runtime
+        if index == 7 {
+            if let Some(response) = response.clone() {
+                Self::emit_response(app, PIPELINE_OPEN_RUNTIME_EVENT_NAME, response)
+            }
+        }
     }
 
     /// 发送运行结果
@@ -139,4 +169,21 @@ impl EventEmitter {
         info!("send monitor response");
         EventEmitter::emit(app, EventSendParams { response, id: None, msg: String::new() }, 4);
     }
+
+    /// 发送日志搜索进度
+    pub(crate) fn log_search_progress(app: &AppHandle, response: Option<HttpResponse>) {
+        EventEmitter::emit(app, EventSendParams { response, id: None, msg: String::new() }, 5);
+    }
+
+    /// 发送步骤等待审批通知
+    pub(crate) fn log_step_approval(app: &AppHandle, response: Option<HttpResponse>) {
+        info!("send run step approval");
+        EventEmitter::emit(app, EventSendParams { response, id: None, msg: String::new() }, 6);
+    }
+
+    /// 发送打开运行详情
+    pub(crate) fn log_open_runtime(app: &AppHandle, response: Option<HttpResponse>) {
+        info!("send open runtime");
+        EventEmitter::emit(app, EventSendParams { response, id: None, msg: String::new() }, 7);
+    }
 }
@@ -1,14 +1,24 @@
 //! 流水线导出列表
 
 use crate::database::interface::Treat;
-use crate::prepare::HttpResponse;
+use crate::event::EventEmitter;
+use crate::logger::pipeline::{PipelineLogQueryForm, PipelineLogSearchForm, PipelineLogger};
+use crate::prepare::{get_success_response, get_success_response_by_value, HttpResponse};
 use crate::server::pipeline::index::Pipeline;
-use crate::server::pipeline::props::PipelineRuntime;
+use crate::server::pipeline::pool::Pool;
+use crate::server::pipeline::props::{PipelineApprovalForm, PipelineReplayForm, PipelineRunDiffForm, PipelineRuntime, PipelineStatsQueryForm};
+use crate::server::pipeline::runnable::approval::PipelineApproval;
+use crate::server::pipeline::runnable::cache::PipelineCache;
+use crate::server::pipeline::runnable::diff::PipelineRunDiffer;
+use crate::server::pipeline::runnable::replay::PipelineReplay;
+use crate::server::pipeline::runnable::stats::PipelineStatistics;
 use crate::server::pipeline::runnable::PipelineRunnable;
+use crate::server::pipeline::workspace::PipelineWorkspace;
 use crate::task::Task;
 use log::info;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
+use tauri::AppHandle;
 
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct QueryForm {
@@ -115,3 +125,79 @@ pub async fn clear_run_history(id: String, server_id: String) -> Result<HttpResp
     pipeline.server_id = server_id.to_string();
     Task::task_param_future::<Pipeline, _, _>(pipeline, |pipe| async move { Pipeline::clear_run_history(&*pipe).await }).await
 }
+
+/// 清除依赖缓存, id 为空时清除服务器下所有流水线的缓存
+#[tauri::command]
+pub async fn purge_dependency_cache(id: String, server_id: String) -> Result<HttpResponse, String> {
+    Task::task(move || PipelineCache::purge(&server_id, &id)).await
+}
+
+/// 获取工作空间磁盘占用
+#[tauri::command]
+pub async fn get_workspace_usage() -> Result<HttpResponse, String> {
+    Task::task_param_future::<(), _, _>((), |_| async move { PipelineWorkspace::get_usage().await }).await
+}
+
+/// 清理工作空间
+#[tauri::command]
+pub async fn clean_workspace() -> Result<HttpResponse, String> {
+    Task::task_param_future::<(), _, _>((), |_| async move { PipelineWorkspace::clean().await }).await
+}
+
+/// 按步骤、时间范围查询运行日志
+#[tauri::command]
+pub async fn query_runtime_log(id: String, server_id: String, order: u32, form: PipelineLogQueryForm) -> Result<HttpResponse, String> {
+    Task::task(move || get_success_response_by_value(PipelineLogger::query_log(&server_id, &id, order, &form)?)).await
+}
+
+/// 导出纯文本运行日志
+#[tauri::command]
+pub async fn export_runtime_log(id: String, server_id: String, order: u32, file: String) -> Result<HttpResponse, String> {
+    Task::task(move || {
+        PipelineLogger::export_log(&server_id, &id, order, &file)?;
+        Ok(get_success_response(None))
+    })
+    .await
+}
+
+/// 搜索运行日志, 支持关键字及正则, 通过事件返回搜索进度
+#[tauri::command]
+pub async fn search_runtime_log(app: AppHandle, form: PipelineLogSearchForm) -> Result<HttpResponse, String> {
+    Task::task(move || PipelineLogger::search_log(&form, |progress| EventEmitter::log_search_progress(&app, get_success_response_by_value(progress.clone()).ok()))).await
+}
+
+/// 获取运行统计: 成功率、平均及 p95 耗时、最慢步骤、每日趋势
+#[tauri::command]
+pub async fn get_pipeline_stats(form: PipelineStatsQueryForm) -> Result<HttpResponse, String> {
+    Task::task_param_future::<PipelineStatsQueryForm, _, _>(form, |form| async move { PipelineStatistics::get_stats(&*form).await }).await
+}
+
+/// 获取排队中的任务位置及预计等待时长
+#[tauri::command]
+pub async fn get_pipeline_queue() -> Result<HttpResponse, String> {
+    Task::task_param_future::<(), _, _>((), |_| async move { Pool::get_queue().await }).await
+}
+
+/// 对比两次运行记录
+#[tauri::command]
+pub async fn diff_pipeline_runtime(form: PipelineRunDiffForm) -> Result<HttpResponse, String> {
+    Task::task_param_future::<PipelineRunDiffForm, _, _>(form, |form| async move { PipelineRunDiffer::diff(&*form).await }).await
+}
+
+/// 回放运行记录
+#[tauri::command]
+pub async fn replay_pipeline_runtime(form: PipelineReplayForm) -> Result<HttpResponse, String> {
+    Task::task_param_future::<PipelineReplayForm, _, _>(form, |form| async move { PipelineReplay::replay(&*form).await }).await
+}
+
+/// 审批通过, 从审批步骤的下一步继续运行
+#[tauri::command]
+pub async fn approve_pipeline_runtime(app: AppHandle, form: PipelineApprovalForm) -> Result<HttpResponse, String> {
+    Task::task_param_future::<PipelineApprovalForm, _, _>(form, |form| async move { PipelineApproval::approve(&app, &*form).await }).await
+}
+
+/// 审批拒绝, 运行失败
+#[tauri::command]
+pub async fn reject_pipeline_runtime(app: AppHandle, form: PipelineApprovalForm) -> Result<HttpResponse, String> {
+    Task::task_param_future::<PipelineApprovalForm, _, _>(form, |form| async move { PipelineApproval::reject(&app, &*form).await }).await
+}
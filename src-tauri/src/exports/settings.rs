@@ -1,5 +1,6 @@
 //! 导出设置方法
 
+use crate::helper::mail::{MailHandler, MailTestForm};
 use crate::prepare::{get_success_response, HttpResponse};
 use crate::setting::Settings;
 use crate::task::Task;
@@ -16,6 +17,12 @@ pub async fn get_setting() -> Result<HttpResponse, String> {
     Task::task(|| Settings::get()).await
 }
 
+/// 发送测试邮件, 检查 SMTP 配置
+#[tauri::command]
+pub async fn send_test_mail(form: MailTestForm) -> Result<HttpResponse, String> {
+    Task::task_param_future::<MailTestForm, _, _>(form, |form| async move { MailHandler::send_test(&*form).await }).await
+}
+
 /// 隐藏 DOCK 栏
 #[tauri::command]
 pub async fn hide_dock() -> Result<HttpResponse, String> {
@@ -0,0 +1,96 @@
+//! 敏感信息加解密
+
+use crate::error::Error;
+use crate::helper::index::Helper;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64::Engine;
+use handlers::file::FileHandler;
+use std::path::PathBuf;
+
+// 密钥文件
+const KEY_FILE: &str = "secret.key";
+
+// 加密后的前缀
+const ENCRYPT_PREFIX: &str = "enc:";
+
+// nonce 长度
+const NONCE_LEN: usize = 12;
+
+pub struct CryptoHandler;
+
+impl CryptoHandler {
+    /// 加密, 已加密或为空时直接返回
+    pub(crate) fn encrypt(content: &str) -> Result<String, String> {
+        if content.is_empty() || Self::is_encrypted(content) {
+            return Ok(content.to_string());
+        }
+
+        let cipher = Self::get_cipher()?;
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let encrypted = cipher.encrypt(&nonce, content.as_bytes()).map_err(|err| Error::Error(format!("encrypt error: {:#?}", err)).to_string())?;
+
+        let mut data = nonce.to_vec();
+        data.extend(encrypted);
+        Ok(format!("{}{}", ENCRYPT_PREFIX, base64::engine::general_purpose::STANDARD.encode(data)))
+    }
+
+    /// 解密, 未加密时直接返回
+    pub(crate) fn decrypt(content: &str) -> Result<String, String> {
+        if !Self::is_encrypted(content) {
+            return Ok(content.to_string());
+        }
+
+        let data = base64::engine::general_purpose::STANDARD
+            .decode(&content[ENCRYPT_PREFIX.len()..])
+            .map_err(|err| Error::Error(format!("decode encrypted content error: {:#?}", err)).to_string())?;
+        if data.len() <= NONCE_LEN {
+            return Err(Error::convert_string("decrypt error, invalid encrypted content !"));
+        }
+
+        let cipher = Self::get_cipher()?;
+        let (nonce, encrypted) = data.split_at(NONCE_LEN);
+        let decrypted = cipher.decrypt(Nonce::from_slice(nonce), encrypted).map_err(|err| Error::Error(format!("decrypt error: {:#?}", err)).to_string())?;
+        String::from_utf8(decrypted).map_err(|err| Error::Error(err.to_string()).to_string())
+    }
+
+    /// 是否已加密
+    pub(crate) fn is_encrypted(content: &str) -> bool {
+        content.starts_with(ENCRYPT_PREFIX)
+    }
+
+    /// 获取密钥, 不存在时生成并保存到配置目录
+    fn get_cipher() -> Result<Aes256Gcm, String> {
+        let dir = Helper::get_project_config_dir(vec![])?;
+        let dir = match dir {
+            Some(dir) => dir,
+            None => return Err(Error::convert_string("get secret key failed, no config dir found !")),
+        };
+
+        let key_file: PathBuf = dir.join(KEY_FILE);
+        let key_file_path = key_file.to_string_lossy().to_string();
+        if key_file.exists() {
+            let content = FileHandler::read_file_string(&key_file_path).map_err(|err| Error::Error(format!("read secret key error: {:#?}", err)).to_string())?;
+            let key = base64::engine::general_purpose::STANDARD
+                .decode(content.trim())
+                .map_err(|err| Error::Error(format!("decode secret key error: {:#?}", err)).to_string())?;
+            if key.len() != 32 {
+                return Err(Error::convert_string("secret key is invalid !"));
+            }
+
+            return Ok(Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key)));
+        }
+
+        let key = Aes256Gcm::generate_key(OsRng);
+        FileHandler::write_to_file_when_clear(&key_file_path, &base64::engine::general_purpose::STANDARD.encode(key.as_slice())).map_err(|err| Error::Error(format!("write secret key error: {:#?}", err)).to_string())?;
+
+        // 只允许当前用户读取
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&key_file, std::fs::Permissions::from_mode(0o600)).map_err(|err| Error::Error(err.to_string()).to_string())?;
+        }
+
+        Ok(Aes256Gcm::new(&key))
+    }
+}
@@ -2,10 +2,107 @@
 
 pub(crate) mod pull;
 
-use crate::helper::git::pull::{GitConfig, GitHelper};
+use crate::error::Error;
+use crate::helper::git::pull::{GitCommit, GitConfig, GitHelper};
+use crate::helper::index::Helper;
+use base64::Engine;
 use git2::{BranchType, Repository};
-use log::info;
+use handlers::file::FileHandler;
+use lazy_static::lazy_static;
+use log::{error, info};
+use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::sync::Mutex;
+use uuid::Uuid;
+
+// 临时私钥目录
+const KEY_DIR_NAME: &str = "keys";
+
+// 支持 GIT_CONFIG_COUNT 环境变量的最低 git 版本
+const MIN_CONFIG_ENV_VERSION: (u32, u32) = (2, 31);
+
+lazy_static! {
+    static ref GIT_VERSION: Option<(u32, u32)> = GitHandler::get_version();
+}
+
+/// 仓库凭证, 已解密
+#[derive(Default, Clone)]
+pub struct GitCredential {
+    pub(crate) username: String, // HTTPS 用户名
+    pub(crate) token: String,    // HTTPS token
+    pub(crate) ssh_key: String,  // SSH 部署私钥
+}
+
+impl std::fmt::Debug for GitCredential {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mask = |str: &str| if str.is_empty() { String::new() } else { String::from("******") };
+        f.debug_struct("GitCredential").field("username", &self.username).field("token", &mask(&self.token)).field("ssh_key", &mask(&self.ssh_key)).finish()
+    }
+}
+
+/// 执行 git 命令时的环境变量, 使用 SSH 私钥时在执行 git 命令前写入临时私钥文件, 释放时删除
+#[derive(Default)]
+pub struct GitEnvs {
+    envs: Vec<(String, String)>,
+    ssh_key: String,
+    key_file: Mutex<Option<PathBuf>>,
+}
+
+impl GitEnvs {
+    /// 获取环境变量
+    pub(crate) fn get(&self) -> Result<Vec<(String, String)>, String> {
+        let mut envs = self.envs.clone();
+        if self.ssh_key.is_empty() {
+            return Ok(envs);
+        }
+
+        let mut key_file = self.key_file.lock().map_err(|err| Error::Error(err.to_string()).to_string())?;
+        if key_file.is_none() {
+            *key_file = Some(Self::write_key_file(&self.ssh_key)?);
+        }
+
+        if let Some(key_file) = key_file.as_ref() {
+            let ssh_command = format!("ssh -i \"{}\" -o IdentitiesOnly=yes -o StrictHostKeyChecking=accept-new", key_file.to_string_lossy());
+            envs.push((String::from("GIT_SSH_COMMAND"), ssh_command));
+        }
+
+        Ok(envs)
+    }
+
+    /// 写入临时私钥文件
+    fn write_key_file(ssh_key: &str) -> Result<PathBuf, String> {
+        let dir = Helper::get_project_config_dir(vec![String::from(KEY_DIR_NAME)])?;
+        let dir = match dir {
+            Some(dir) => dir,
+            None => return Err(Error::convert_string("get ssh key dir failed !")),
+        };
+
+        let key_file = dir.join(Uuid::new_v4().to_string());
+        let mut content = ssh_key.trim().to_string();
+        content.push('\n');
+        FileHandler::write_to_file_when_clear(&key_file.to_string_lossy().to_string(), &content).map_err(|err| Error::Error(format!("write ssh key error: {:#?}", err)).to_string())?;
+
+        // 私钥只允许当前用户读取, 否则 ssh 会拒绝使用
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&key_file, std::fs::Permissions::from_mode(0o600)).map_err(|err| Error::Error(err.to_string()).to_string())?;
+        }
+
+        Ok(key_file)
+    }
+}
+
+impl Drop for GitEnvs {
+    fn drop(&mut self) {
+        let key_file = self.key_file.get_mut().ok().and_then(|key_file| key_file.take());
+        if let Some(key_file) = key_file {
+            if let Err(err) = std::fs::remove_file(&key_file) {
+                error!("remove ssh key file {:#?} error: {:#?}", key_file, err);
+            }
+        }
+    }
+}
 
 pub struct GitHandler;
 
@@ -41,13 +138,21 @@ impl GitHandler {
     }
 
     /// 判断远程 Git 地址是否可用
-    pub(crate) fn validate_remote_url(url: &str) -> bool {
+    pub(crate) fn validate_remote_url(url: &str, envs: &GitEnvs) -> bool {
         let validate_url = Self::get_url(url);
         if validate_url.is_empty() {
             return false;
         }
 
-        let output = Command::new("git").arg("ls-remote").arg(url).output();
+        let envs = match envs.get() {
+            Ok(envs) => envs,
+            Err(err) => {
+                info!("validate remote url: {} error: {}", url, err);
+                return false;
+            }
+        };
+
+        let output = Command::new("git").arg("ls-remote").arg(url).envs(envs).output();
         return match output {
             Ok(output) => {
                 if output.status.success() {
@@ -72,16 +177,136 @@ impl GitHandler {
 
     /// 判断是否为远程地址
     pub(crate) fn is_remote_url(url: &str) -> bool {
-        return url.starts_with("http://") || url.starts_with("https://") || url.starts_with("ssh://");
+        return url.starts_with("http://") || url.starts_with("https://") || url.starts_with("ssh://") || url.starts_with("git@");
+    }
+
+    /// 判断远程仓库是否存在 tag
+    pub(crate) fn is_remote_tag(url: &str, tag: &str, envs: &GitEnvs) -> bool {
+        if url.is_empty() || tag.is_empty() {
+            return false;
+        }
+
+        let envs = match envs.get() {
+            Ok(envs) => envs,
+            Err(err) => {
+                info!("get remote url tag `{}` error: {}", url, err);
+                return false;
+            }
+        };
+
+        let output = Command::new("git").args(&["ls-remote", "--tags", url, &format!("refs/tags/{}", tag)]).envs(envs).output();
+        return match output {
+            Ok(output) => output.status.success() && !String::from_utf8_lossy(&output.stdout).trim().is_empty(),
+            Err(err) => {
+                info!("get remote url tag `{}` error: {:#?}", url, err);
+                false
+            }
+        };
+    }
+
+    /// 获取当前 HEAD 的提交信息
+    pub(crate) fn get_commit(path: &Path) -> Option<GitCommit> {
+        let repo = match Repository::open(path) {
+            Ok(repo) => repo,
+            Err(err) => {
+                info!("`{:#?}` is not a git project: {:#?}", path, err);
+                return None;
+            }
+        };
+
+        let commit = repo.head().and_then(|head| head.peel_to_commit());
+        return match commit {
+            Ok(commit) => {
+                let author = commit.author();
+                Some(GitCommit {
+                    sha: commit.id().to_string(),
+                    author: format!("{} <{}>", author.name().unwrap_or(""), author.email().unwrap_or("")),
+                    message: commit.summary().unwrap_or("").to_string(),
+                })
+            }
+            Err(err) => {
+                info!("get `{:#?}` head commit error: {:#?}", path, err);
+                None
+            }
+        };
+    }
+
+    /// 获取凭证对应的环境变量, HTTPS 使用仅对仓库地址生效的 http.<url>.extraHeader, SSH 使用临时私钥文件
+    pub(crate) fn get_envs(credential: &GitCredential, url: &str) -> Result<GitEnvs, String> {
+        let mut git_envs = GitEnvs::default();
+
+        // 禁止交互式输入密码
+        git_envs.envs.push((String::from("GIT_TERMINAL_PROMPT"), String::from("0")));
+
+        if !credential.token.is_empty() {
+            // GIT_CONFIG_COUNT 需要 git 2.31 及以上版本
+            match *GIT_VERSION {
+                Some(version) if version >= MIN_CONFIG_ENV_VERSION => {}
+                Some((major, minor)) => {
+                    return Err(Error::convert_string(&format!(
+                        "https token credential requires git {}.{} or later, current version is {}.{} !",
+                        MIN_CONFIG_ENV_VERSION.0, MIN_CONFIG_ENV_VERSION.1, major, minor
+                    )));
+                }
+                None => return Err(Error::convert_string("https token credential requires git, but git is not installed !")),
+            }
+
+            // 只对仓库所在地址发送, 避免子模块、LFS 等其他域名拿到凭证
+            if let Some(origin) = Self::get_http_origin(url) {
+                let username = if credential.username.is_empty() { "git" } else { credential.username.as_str() };
+                let auth = base64::engine::general_purpose::STANDARD.encode(format!("{}:{}", username, &credential.token));
+                git_envs.envs.push((String::from("GIT_CONFIG_COUNT"), String::from("1")));
+                git_envs.envs.push((String::from("GIT_CONFIG_KEY_0"), format!("http.{}.extraHeader", origin)));
+                git_envs.envs.push((String::from("GIT_CONFIG_VALUE_0"), format!("Authorization: Basic {}", auth)));
+            }
+        }
+
+        if !credential.ssh_key.is_empty() {
+            git_envs.ssh_key = credential.ssh_key.clone();
+        }
+
+        Ok(git_envs)
+    }
+
+    /// 获取 HTTP(S) 地址的 `scheme://host[:port]/`, 去掉用户信息
+    fn get_http_origin(url: &str) -> Option<String> {
+        let url = url.trim();
+        let (scheme, rest) = url.split_once("://")?;
+        if scheme != "http" && scheme != "https" {
+            return None;
+        }
+
+        let authority = rest.split('/').next().unwrap_or("");
+        let host = authority.rsplit('@').next().unwrap_or("");
+        if host.is_empty() {
+            return None;
+        }
+
+        Some(format!("{}://{}/", scheme, host))
+    }
+
+    /// 获取 git 版本, 如 `git version 2.39.3 (Apple Git-145)`
+    fn get_version() -> Option<(u32, u32)> {
+        let output = Command::new("git").arg("--version").output().ok()?;
+        if !output.status.success() {
+            return None;
+        }
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let version = stdout.split_whitespace().nth(2)?;
+        let mut parts = version.split('.');
+        let major = parts.next()?.parse::<u32>().ok()?;
+        let minor = parts.next()?.parse::<u32>().ok()?;
+        Some((major, minor))
     }
 
     /// 获取 branch 列表
-    pub(crate) fn get_branch_list(url: &str) -> Vec<String> {
+    pub(crate) fn get_branch_list(url: &str, envs: &GitEnvs) -> Vec<String> {
         let is_remote_url = Self::is_remote_url(url);
         let branches: Vec<String>;
         if is_remote_url {
             // remote
-            branches = Self::get_remote_url(url);
+            branches = Self::get_remote_url(url, envs);
         } else {
             // ls
             branches = Self::get_local_branch(url);
@@ -128,13 +353,21 @@ impl GitHandler {
     }
 
     /// 获取远程分支列表
-    fn get_remote_url(url: &str) -> Vec<String> {
+    fn get_remote_url(url: &str, envs: &GitEnvs) -> Vec<String> {
         let path = Self::get_url(url);
         if path.is_empty() {
             return Vec::new();
         }
 
-        let output = Command::new("git").args(&["ls-remote", "--heads", &path]).output();
+        let envs = match envs.get() {
+            Ok(envs) => envs,
+            Err(err) => {
+                info!("get remote url branches `{}` error: {}", url, err);
+                return Vec::new();
+            }
+        };
+
+        let output = Command::new("git").args(&["ls-remote", "--heads", &path]).envs(envs).output();
         return match output {
             Ok(output) => {
                 if !output.status.success() {
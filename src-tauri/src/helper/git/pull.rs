@@ -1,23 +1,38 @@
 //! Git代码拉取
 
-use crate::helper::git::GitHandler;
+use crate::helper::git::{GitCredential, GitEnvs, GitHandler};
 use crate::helper::index::Helper;
 use handlers::file::FileHandler;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 use std::time::Instant;
 
-#[derive(Debug)]
+#[derive(Default, Debug)]
 pub struct GitConfig {
-    pub(crate) url: String,    // Git 地址
-    pub(crate) branch: String, // Git 分支
-    pub(crate) dir: String,    // 存放地址
+    pub(crate) url: String,               // Git 地址
+    pub(crate) branch: String,            // Git 分支
+    pub(crate) revision: String,          // tag 或 commit SHA, 不为空时优先使用
+    pub(crate) dir: String,               // 存放地址
+    pub(crate) shallow: bool,             // 浅克隆, 只拉取最新一次提交
+    pub(crate) partial: bool,             // 部分克隆, 按需下载文件内容
+    pub(crate) fresh: bool,               // 强制重新克隆, 缓存损坏时使用
+    pub(crate) submodules: bool,          // 递归拉取子模块
+    pub(crate) lfs: bool,                 // 拉取 LFS 文件
+    pub(crate) credential: GitCredential, // 私有仓库凭证
+}
+
+/// 提交信息
+#[derive(Default, Debug, Clone)]
+pub struct GitCommit {
+    pub(crate) sha: String,     // commit SHA
+    pub(crate) author: String,  // 提交人
+    pub(crate) message: String, // 提交信息
 }
 
 pub struct GitHelper;
 
 impl GitHelper {
-    /// 拉取代码
+    /// 拉取代码, 项目目录已存在时增量拉取, 否则重新克隆
     pub(crate) fn pull<F>(config: &GitConfig, func: F) -> Result<bool, String>
     where
         F: Fn(&str) + Send + Sync + 'static,
@@ -28,8 +43,8 @@ impl GitHelper {
             return Err(crate::error::Error::convert_string(error_msg));
         }
 
-        if config.branch.is_empty() {
-            let error_msg = "Git pull failed, `branch` is empty!";
+        if config.branch.is_empty() && config.revision.is_empty() {
+            let error_msg = "Git pull failed, `branch` and `revision` are empty!";
             func(error_msg);
             return Err(crate::error::Error::convert_string(error_msg));
         }
@@ -51,46 +66,258 @@ impl GitHelper {
         let msg = format!("Git pull params:\n {:#?}", &config);
         func(&msg);
 
-        // 判断是否存在目录, 如果目录存在, 则直接删除
         let project_name = GitHandler::get_project_name_by_git(&config.url);
         let mut project_path = PathBuf::from(&config.dir);
         project_path.push(&project_name);
 
-        // 目录存在, 则删除
+        let start_time = Instant::now();
+        let func_cloned = Arc::new(Mutex::new(func));
+
+        // 私有仓库凭证
+        if !config.credential.token.is_empty() {
+            Self::log(&func_cloned, "use https token credential");
+        } else if !config.credential.ssh_key.is_empty() {
+            Self::log(&func_cloned, "use ssh deploy key credential");
+        }
+        let envs = GitHandler::get_envs(&config.credential, &config.url)?;
+
+        // 强制重新克隆或不是 Git 仓库, 则删除
         if project_path.exists() {
-            let msg = format!("workspace exists project: {}, will be deleted !", &project_name);
-            func(&msg);
-            FileHandler::delete_dirs(vec![project_path.as_path().to_string_lossy().to_string()])?;
+            let mut reason = String::new();
+            if config.fresh {
+                reason = String::from("force fresh clone");
+            } else if !project_path.join(".git").exists() {
+                reason = String::from("not a git repository");
+            }
+
+            if !reason.is_empty() {
+                Self::log(&func_cloned, &format!("workspace exists project: {}, {}, will be deleted !", &project_name, reason));
+                FileHandler::delete_dirs(vec![project_path.as_path().to_string_lossy().to_string()])?;
+            }
         }
 
-        // 开始拉取代码
-        let msg = format!("Starting pull {} code, branch {} ...", &project_name, &config.branch);
-        func(&msg);
+        let git_ref = if config.revision.is_empty() { format!("branch {}", &config.branch) } else { format!("revision {}", &config.revision) };
 
-        let start_time = Instant::now();
-        let func_cloned = Arc::new(Mutex::new(func));
-        let func_clone = func_cloned.clone();
+        let mut success = false;
+        if project_path.exists() {
+            // 增量拉取
+            Self::log(&func_cloned, &format!("Starting fetch {} code, {} ...", &project_name, &git_ref));
+            success = Self::fetch(config, &project_path, &envs, &func_cloned);
 
-        let success = Helper::run_command_output_real_time("git", &["clone", "-b", &config.branch, &config.url], &config.dir, move |msg| {
-            let func = func_cloned.lock().unwrap();
-            (*func)(&msg);
-        });
+            // 增量拉取失败, 缓存可能已损坏, 重新克隆
+            if !success {
+                Self::log(&func_cloned, &format!("fetch {} failed, workspace will be deleted and cloned again !", &project_name));
+                FileHandler::delete_dirs(vec![project_path.as_path().to_string_lossy().to_string()])?;
+            }
+        }
 
         if !success {
-            let msg = format!("pull {} error !", &project_name);
-            let func = func_clone.lock().unwrap();
-            (*func)(&msg);
+            // 开始拉取代码
+            Self::log(&func_cloned, &format!("Starting clone {} code, {} ...", &project_name, &git_ref));
+            if config.revision.is_empty() {
+                success = Self::clone(config, &envs, &func_cloned);
+            } else {
+                // 指定 tag 或 commit 时, 初始化空仓库后拉取
+                success = Self::init(config, &project_path, &envs, &func_cloned)? && Self::fetch(config, &project_path, &envs, &func_cloned);
+            }
+        }
+
+        // 子模块
+        if success && config.submodules {
+            success = Self::update_submodules(config, &project_path, &envs, &func_cloned);
+        }
+
+        // LFS
+        if success && config.lfs {
+            success = Self::pull_lfs(&project_path, &envs, &func_cloned);
+        }
+
+        if !success {
+            Self::log(&func_cloned, &format!("pull {} error !", &project_name));
         } else {
-            let msg = format!("pull {} success !", &project_name);
-            let func = func_clone.lock().unwrap();
-            (*func)(&msg);
+            Self::log(&func_cloned, &format!("pull {} success !", &project_name));
         }
 
         let elapsed_time = format!("{:.2?}", start_time.elapsed());
-        let msg = format!("Finished pull {} after {}", &project_name, elapsed_time);
-        let func = func_clone.lock().unwrap();
-        (*func)(&msg);
+        Self::log(&func_cloned, &format!("Finished pull {} after {}", &project_name, elapsed_time));
 
         Ok(success)
     }
+
+    /// 克隆
+    fn clone<F>(config: &GitConfig, envs: &GitEnvs, func: &Arc<Mutex<F>>) -> bool
+    where
+        F: Fn(&str) + Send + Sync + 'static,
+    {
+        let mut args: Vec<&str> = vec!["clone"];
+        if config.shallow {
+            args.extend(["--depth", "1"]);
+        }
+
+        if config.partial {
+            args.push("--filter=blob:none");
+        }
+
+        args.extend(["-b", config.branch.as_str(), config.url.as_str()]);
+        return Self::exec(&args, Path::new(&config.dir), envs, func);
+    }
+
+    /// 初始化空仓库
+    fn init<F>(config: &GitConfig, project_path: &Path, envs: &GitEnvs, func: &Arc<Mutex<F>>) -> Result<bool, String>
+    where
+        F: Fn(&str) + Send + Sync + 'static,
+    {
+        FileHandler::create_dirs(&project_path.to_string_lossy().to_string())?;
+        if !Self::exec(&["init"], project_path, envs, func) {
+            return Ok(false);
+        }
+
+        Ok(Self::exec(&["remote", "add", "origin", &config.url], project_path, envs, func))
+    }
+
+    /// 增量拉取: fetch + reset --hard + clean
+    fn fetch<F>(config: &GitConfig, project_path: &Path, envs: &GitEnvs, func: &Arc<Mutex<F>>) -> bool
+    where
+        F: Fn(&str) + Send + Sync + 'static,
+    {
+        // 地址可能已修改
+        if !Self::exec(&["remote", "set-url", "origin", &config.url], project_path, envs, func) {
+            return false;
+        }
+
+        let target;
+        if config.revision.is_empty() {
+            let refspec = format!("+refs/heads/{}:refs/remotes/origin/{}", &config.branch, &config.branch);
+            if !Self::exec(&Self::get_fetch_args(config, project_path, &[refspec.as_str()]), project_path, envs, func) {
+                return false;
+            }
+
+            target = format!("origin/{}", &config.branch);
+            if !Self::exec(&["checkout", "--force", "-B", &config.branch, &target], project_path, envs, func) {
+                return false;
+            }
+        } else {
+            let revision = &config.revision;
+            let mut fetched = false;
+            if GitHandler::is_remote_tag(&config.url, revision, envs) {
+                // tag
+                let refspec = format!("+refs/tags/{}:refs/tags/{}", revision, revision);
+                fetched = Self::exec(&Self::get_fetch_args(config, project_path, &[refspec.as_str()]), project_path, envs, func);
+            } else if revision.len() == 40 {
+                // 完整的 commit SHA, 直接拉取
+                fetched = Self::exec(&Self::get_fetch_args(config, project_path, &[revision.as_str()]), project_path, envs, func);
+            }
+
+            target = if fetched { String::from("FETCH_HEAD") } else { revision.clone() };
+
+            // 短 SHA 或服务端不允许直接拉取 commit 时, 拉取所有分支后查找
+            if !fetched {
+                Self::log(func, &format!("revision {} not fetched directly, fetch all branches ...", revision));
+                let full_config = GitConfig { partial: config.partial, ..Default::default() };
+                let args = Self::get_fetch_args(&full_config, project_path, &["+refs/heads/*:refs/remotes/origin/*", "+refs/tags/*:refs/tags/*"]);
+                if !Self::exec(&args, project_path, envs, func) {
+                    return false;
+                }
+            }
+
+            if !Self::exec(&["checkout", "--force", "--detach", &target], project_path, envs, func) {
+                return false;
+            }
+        }
+
+        if !Self::exec(&["reset", "--hard", &target], project_path, envs, func) {
+            return false;
+        }
+
+        // 清除未跟踪的文件, 保留 node_modules 加快依赖安装
+        return Self::exec(&["clean", "-ffdx", "-e", "node_modules"], project_path, envs, func);
+    }
+
+    /// 递归拉取子模块
+    fn update_submodules<F>(config: &GitConfig, project_path: &Path, envs: &GitEnvs, func: &Arc<Mutex<F>>) -> bool
+    where
+        F: Fn(&str) + Send + Sync + 'static,
+    {
+        Self::log(func, "Starting update submodules ...");
+        if !Self::exec(&["submodule", "sync", "--recursive"], project_path, envs, func) {
+            return false;
+        }
+
+        let mut args: Vec<&str> = vec!["submodule", "update", "--init", "--recursive", "--force"];
+        if config.shallow {
+            args.extend(["--depth", "1"]);
+        }
+
+        let success = Self::exec(&args, project_path, envs, func);
+        Self::log(func, &format!("update submodules {} !", if success { "success" } else { "error" }));
+        success
+    }
+
+    /// 拉取 LFS 文件
+    fn pull_lfs<F>(project_path: &Path, envs: &GitEnvs, func: &Arc<Mutex<F>>) -> bool
+    where
+        F: Fn(&str) + Send + Sync + 'static,
+    {
+        Self::log(func, "Starting pull lfs objects ...");
+        if !Helper::check_installed_command("git-lfs") {
+            Self::log(func, "os not install `git-lfs` command, pull lfs objects error !");
+            return false;
+        }
+
+        if !Self::exec(&["lfs", "install", "--local"], project_path, envs, func) {
+            return false;
+        }
+
+        let success = Self::exec(&["lfs", "pull"], project_path, envs, func);
+        Self::log(func, &format!("pull lfs objects {} !", if success { "success" } else { "error" }));
+        success
+    }
+
+    /// 获取 fetch 参数
+    fn get_fetch_args<'a>(config: &GitConfig, project_path: &Path, refspecs: &[&'a str]) -> Vec<&'a str> {
+        let mut args: Vec<&str> = vec!["fetch", "--prune", "--force"];
+        if config.shallow {
+            args.extend(["--depth", "1"]);
+        } else if project_path.join(".git").join("shallow").exists() {
+            // 之前为浅克隆, 补全历史
+            args.push("--unshallow");
+        }
+
+        if config.partial {
+            args.push("--filter=blob:none");
+        }
+
+        args.push("origin");
+        args.extend(refspecs);
+        args
+    }
+
+    /// 执行 git 命令
+    fn exec<F>(args: &[&str], dir: &Path, envs: &GitEnvs, func: &Arc<Mutex<F>>) -> bool
+    where
+        F: Fn(&str) + Send + Sync + 'static,
+    {
+        let envs = match envs.get() {
+            Ok(envs) => envs,
+            Err(err) => {
+                Self::log(func, &format!("exec git command error: {}", err));
+                return false;
+            }
+        };
+
+        let func_cloned = func.clone();
+        return Helper::run_command_output_real_time_by_envs("git", args, &dir.to_string_lossy().to_string(), &envs, move |msg| {
+            let func = func_cloned.lock().unwrap();
+            (*func)(&msg);
+        });
+    }
+
+    /// 输出日志
+    fn log<F>(func: &Arc<Mutex<F>>, msg: &str)
+    where
+        F: Fn(&str) + Send + Sync + 'static,
+    {
+        let func = func.lock().unwrap();
+        (*func)(msg);
+    }
 }
@@ -4,12 +4,18 @@ use crate::setting::Settings;
 use crate::PROJECT_NAME;
 use handlers::file::FileHandler;
 use log::{error, info};
+use std::cell::Cell;
 use std::io::{BufRead, BufReader};
 use std::path::PathBuf;
 use std::process::{Child, Command, Stdio};
 use std::sync::{Arc, Mutex};
 use std::{io, thread};
 
+thread_local! {
+    // 当前线程是否在输出标准错误
+    static STDERR_OUTPUT: Cell<bool> = Cell::new(false);
+}
+
 pub struct Helper;
 
 impl Helper {
@@ -100,6 +106,14 @@ impl Helper {
 
     /// 执行命令
     pub(crate) fn exec_command<F>(command: &str, current_dir: &str, func: F) -> bool
+    where
+        F: Fn(&str) + Send + Sync + 'static,
+    {
+        Self::exec_command_by_path(command, current_dir, None, func)
+    }
+
+    /// 执行命令, bin_dir 不为空时添加到 PATH 最前面, 如指定的 node 版本
+    pub(crate) fn exec_command_by_path<F>(command: &str, current_dir: &str, bin_dir: Option<String>, func: F) -> bool
     where
         F: Fn(&str) + Send + Sync + 'static,
     {
@@ -110,6 +124,7 @@ impl Helper {
         }
 
         let _command = command.replace("\n", " && ");
+        let path = Self::get_shell_path_by_bin_dir(bin_dir);
 
         // windows 通过 cmd /C 执行多条命令: cd c:\\usr\\local\\nginx\\sbin/ && nginx
         #[cfg(target_os = "windows")]
@@ -119,7 +134,7 @@ impl Helper {
 
             let mut cmd = Command::new("cmd");
             cmd.args(&["/C", &_command]);
-            if let Some(path) = Self::get_shell_path() {
+            if let Some(path) = path {
                 cmd.env("PATH", path);
             }
             let child = cmd.current_dir(current_dir).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn();
@@ -129,14 +144,14 @@ impl Helper {
         }
 
         // linux|macos 通过 shell -c 执行多条命令: cd /usr/local/nginx/sbin/\n./nginx
-        #[cfg(target_os = "macos")]
+        #[cfg(any(target_os = "macos", target_os = "linux"))]
         {
             let msg = &format!("exec command: {}", _command);
             func(&msg);
 
             let mut cmd = Command::new("sh");
             cmd.arg("-c").arg(command);
-            if let Some(path) = Self::get_shell_path() {
+            if let Some(path) = path {
                 cmd.env("PATH", path);
             }
             let child = cmd.current_dir(current_dir).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn();
@@ -144,20 +159,33 @@ impl Helper {
                 func(&msg);
             });
         }
+    }
 
-        #[cfg(target_os = "linux")]
-        {
-            let msg = &format!("exec command: {}", _command);
-            func(&msg);
-            output = Command::new("sh").arg("-c").arg(command).current_dir(current_dir).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn();
-            return Self::get_exec_command_real_time_output_by_spawn(child, move |msg| {
-                func(&msg);
-            });
-        }
+    /// 获取 PATH, bin_dir 不为空时放在最前面
+    fn get_shell_path_by_bin_dir(bin_dir: Option<String>) -> Option<String> {
+        let path = Self::get_shell_path();
+        let bin_dir = match bin_dir {
+            Some(bin_dir) if !bin_dir.is_empty() => bin_dir,
+            _ => return path,
+        };
+
+        let separator = if cfg!(target_os = "windows") { ";" } else { ":" };
+        return match path {
+            Some(path) => Some(format!("{}{}{}", bin_dir, separator, path)),
+            None => Some(bin_dir),
+        };
     }
 
     /// 实时输出日志
     pub(crate) fn run_command_output_real_time<F>(command: &str, args: &[&str], current_dir: &str, func: F) -> bool
+    where
+        F: Fn(&str) + Send + Sync + 'static,
+    {
+        Self::run_command_output_real_time_by_envs(command, args, current_dir, &Vec::new(), func)
+    }
+
+    /// 实时输出日志, 附加环境变量, 如 git 凭证
+    pub(crate) fn run_command_output_real_time_by_envs<F>(command: &str, args: &[&str], current_dir: &str, envs: &Vec<(String, String)>, func: F) -> bool
     where
         F: Fn(&str) + Send + Sync + 'static,
     {
@@ -172,10 +200,15 @@ impl Helper {
             return false;
         }
 
-        let child = Command::new(command).args(args.iter()).current_dir(current_dir).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn();
+        let child = Command::new(command).args(args.iter()).envs(envs.clone()).current_dir(current_dir).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn();
         return Self::get_exec_command_real_time_output_by_spawn(child, move |msg| func(msg));
     }
 
+    /// 当前输出是否来自标准错误, 在实时输出回调中使用
+    pub(crate) fn is_stderr_output() -> bool {
+        STDERR_OUTPUT.with(|stderr| stderr.get())
+    }
+
     /// 通过 output 实时输出日志
     pub(crate) fn get_exec_command_real_time_output_by_spawn<F>(mut spawn: io::Result<Child>, func: F) -> bool
     where
@@ -231,6 +264,7 @@ impl Helper {
         });
 
         let stderr_thread = thread::spawn(move || {
+            STDERR_OUTPUT.with(|stderr| stderr.set(true));
             for line in stderr_reader.lines() {
                 if let Ok(line) = line {
                     // 标准错误输出通常用于报告警告、信息或调试信息，而不仅仅是错误
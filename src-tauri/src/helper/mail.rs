@@ -0,0 +1,277 @@
+//! 邮件通知, 使用系统设置中的 SMTP 配置发送
+
+use crate::error::Error;
+use crate::helper::crypto::CryptoHandler;
+use crate::prepare::{get_error_response, get_success_response, HttpResponse};
+use crate::robot::webhook::RobotMessage;
+use crate::setting::Settings;
+use lettre::message::header::ContentType;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{Message, SmtpTransport, Transport};
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+// 发送超时时间
+const SMTP_TIMEOUT_SECONDS: u64 = 30;
+
+/// 测试邮件
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct MailTestForm {
+    pub(crate) to: String, // 收件人
+}
+
+/// SMTP 加密方式
+#[derive(Debug, Clone)]
+enum MailSecurity {
+    None,     // 不加密, 用于本地测试服务
+    StartTls, // STARTTLS, 默认 587 端口
+    Tls,      // SSL/TLS, 默认 465 端口
+}
+
+impl MailSecurity {
+    fn get(security: &str) -> MailSecurity {
+        let security = security.trim().to_lowercase();
+        if security == "none" {
+            return MailSecurity::None;
+        }
+
+        if security == "tls" || security == "ssl" {
+            return MailSecurity::Tls;
+        }
+
+        MailSecurity::StartTls
+    }
+
+    fn get_default_port(&self) -> u16 {
+        return match self {
+            MailSecurity::None => 25,
+            MailSecurity::StartTls => 587,
+            MailSecurity::Tls => 465,
+        };
+    }
+}
+
+pub struct MailHandler;
+
+impl MailHandler {
+    /// 发送运行结果邮件
+    pub(crate) async fn send_message(recipients: &Vec<String>, message: &RobotMessage) -> Result<(), String> {
+        Self::send(recipients, &Self::get_subject(message), &Self::get_html(message)).await
+    }
+
+    /// 发送测试邮件
+    pub(crate) async fn send_test(form: &MailTestForm) -> Result<HttpResponse, String> {
+        let recipients = Self::split_recipients(&form.to);
+        if recipients.is_empty() {
+            return Ok(get_error_response("发送测试邮件失败, `to` 不能为空"));
+        }
+
+        let html = String::from("<p>This is a test mail from n-nacos.</p>");
+        match Self::send(&recipients, "n-nacos test mail", &html).await {
+            Ok(_) => Ok(get_success_response(None)),
+            Err(err) => Ok(get_error_response(&format!("发送测试邮件失败, {}", err))),
+        }
+    }
+
+    /// 分割收件人, 支持逗号、分号及换行
+    pub(crate) fn split_recipients(recipients: &str) -> Vec<String> {
+        recipients
+            .split(|c| c == ',' || c == ';' || c == '\n')
+            .map(|recipient| recipient.trim().to_string())
+            .filter(|recipient| !recipient.is_empty())
+            .collect()
+    }
+
+    /// 发送邮件, SMTP 为阻塞调用, 放到阻塞线程中执行
+    async fn send(recipients: &Vec<String>, subject: &str, html: &str) -> Result<(), String> {
+        let settings = Settings::get_settings().unwrap_or(Settings::default());
+        if settings.smtp_host.trim().is_empty() {
+            return Err(Error::convert_string("no smtp host configured !"));
+        }
+
+        let from = if settings.smtp_from.trim().is_empty() { settings.smtp_username.trim() } else { settings.smtp_from.trim() };
+        let message = Self::get_message(from, recipients, subject, html)?;
+        let transport = Self::get_transport(&settings)?;
+
+        info!("send mail `{}` to {:?}", subject, recipients);
+        async_std::task::spawn_blocking(move || transport.send(&message)).await.map_err(|err| Error::Error(err.to_string()).to_string())?;
+        Ok(())
+    }
+
+    /// 根据设置创建 SMTP 连接
+    fn get_transport(settings: &Settings) -> Result<SmtpTransport, String> {
+        let host = settings.smtp_host.trim();
+        let security = MailSecurity::get(&settings.smtp_security);
+        let port = settings.smtp_port.trim().parse::<u16>().unwrap_or(security.get_default_port());
+
+        let builder = match security {
+            MailSecurity::None => SmtpTransport::builder_dangerous(host),
+            MailSecurity::StartTls => SmtpTransport::starttls_relay(host).map_err(|err| Error::Error(err.to_string()).to_string())?,
+            MailSecurity::Tls => SmtpTransport::relay(host).map_err(|err| Error::Error(err.to_string()).to_string())?,
+        };
+
+        let mut builder = builder.port(port).timeout(Some(Duration::from_secs(SMTP_TIMEOUT_SECONDS)));
+        if !settings.smtp_username.trim().is_empty() {
+            let password = CryptoHandler::decrypt(&settings.smtp_password)?;
+            builder = builder.credentials(Credentials::new(settings.smtp_username.trim().to_string(), password));
+        }
+
+        Ok(builder.build())
+    }
+
+    /// 生成邮件
+    fn get_message(from: &str, recipients: &Vec<String>, subject: &str, html: &str) -> Result<Message, String> {
+        let mut builder = Message::builder().from(Self::parse_mailbox(from)?).subject(subject).header(ContentType::TEXT_HTML);
+        for recipient in recipients.iter() {
+            builder = builder.to(Self::parse_mailbox(recipient)?);
+        }
+
+        builder.body(html.to_string()).map_err(|err| Error::Error(err.to_string()).to_string())
+    }
+
+    fn parse_mailbox(address: &str) -> Result<Mailbox, String> {
+        address.parse::<Mailbox>().map_err(|err| Error::Error(format!("invalid mail address `{}`: {}", address, err)).to_string())
+    }
+
+    /// 邮件标题
+    fn get_subject(message: &RobotMessage) -> String {
+        format!("【{}】#{} {}", message.pipeline, message.order, message.status)
+    }
+
+    /// HTML 摘要
+    fn get_html(message: &RobotMessage) -> String {
+        let escape = |str: &str| str.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;");
+        let color = if message.failed_step.is_some() { "#f5222d" } else { "#52c41a" };
+
+        let mut html = format!("<h3>{}</h3>", escape(&format!("【{}】#{}", message.pipeline, message.order)));
+        html.push_str("<table cellpadding=\"4\" style=\"border-collapse: collapse;\">");
+        html.push_str(&format!("<tr><td>状态</td><td style=\"color: {};\">{}</td></tr>", color, escape(&message.status)));
+        html.push_str(&format!("<tr><td>分支</td><td>{}</td></tr>", escape(&message.branch)));
+        html.push_str(&format!("<tr><td>耗时</td><td>{}s</td></tr>", message.duration / 1000));
+        if let Some(failed_step) = &message.failed_step {
+            html.push_str(&format!("<tr><td>失败步骤</td><td>{}</td></tr>", escape(failed_step)));
+        }
+        html.push_str("</table>");
+
+        if !message.log.is_empty() {
+            html.push_str(&format!("<pre style=\"background: #f5f5f5; padding: 8px;\">{}</pre>", escape(&message.log.join("\n"))));
+        }
+
+        html
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread::JoinHandle;
+
+    /// 本地 SMTP 服务, 接收一封邮件后返回收件人及邮件内容
+    fn start_smtp_server() -> (u16, JoinHandle<(Vec<String>, String)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut writer = stream;
+            writer.write_all(b"220 localhost ESMTP\r\n").unwrap();
+
+            let mut recipients: Vec<String> = Vec::new();
+            let mut data = String::new();
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap_or(0) == 0 {
+                    break;
+                }
+
+                let command = line.trim_end().to_string();
+                let upper = command.to_uppercase();
+                if upper.starts_with("EHLO") || upper.starts_with("HELO") {
+                    writer.write_all(b"250 localhost\r\n").unwrap();
+                } else if upper.starts_with("RCPT TO:") {
+                    recipients.push(command[8..].trim().trim_matches(|c| c == '<' || c == '>').to_string());
+                    writer.write_all(b"250 OK\r\n").unwrap();
+                } else if upper == "DATA" {
+                    writer.write_all(b"354 End data with <CR><LF>.<CR><LF>\r\n").unwrap();
+                    loop {
+                        let mut line = String::new();
+                        if reader.read_line(&mut line).unwrap_or(0) == 0 || line == ".\r\n" {
+                            break;
+                        }
+                        data.push_str(&line);
+                    }
+                    writer.write_all(b"250 OK\r\n").unwrap();
+                } else if upper == "QUIT" {
+                    writer.write_all(b"221 Bye\r\n").unwrap();
+                    break;
+                } else {
+                    writer.write_all(b"250 OK\r\n").unwrap();
+                }
+            }
+
+            (recipients, data)
+        });
+
+        (port, handle)
+    }
+
+    #[test]
+    fn test_send_message() {
+        let (port, handle) = start_smtp_server();
+        let mut settings = Settings::default();
+        settings.smtp_host = String::from("127.0.0.1");
+        settings.smtp_port = port.to_string();
+        settings.smtp_security = String::from("none");
+
+        let message = RobotMessage::test_failed();
+        let recipients = MailHandler::split_recipients("a@example.com; b@example.com");
+        let mail = MailHandler::get_message("n-nacos <noreply@example.com>", &recipients, &MailHandler::get_subject(&message), &MailHandler::get_html(&message)).unwrap();
+        assert_eq!(mail.headers().get_raw("Subject"), Some("【demo】#3 Failed"));
+
+        let transport = MailHandler::get_transport(&settings).unwrap();
+        transport.send(&mail).unwrap();
+
+        let (received, data) = handle.join().unwrap();
+        assert_eq!(received, vec![String::from("a@example.com"), String::from("b@example.com")]);
+        assert!(data.contains("From: n-nacos <noreply@example.com>"));
+        assert!(data.contains("To: a@example.com, b@example.com"));
+        assert!(data.contains("Content-Type: text/html; charset=utf-8"));
+    }
+
+    #[test]
+    fn test_get_html() {
+        let mut message = RobotMessage::test_failed();
+        message.failed_step = Some(String::from("<build>"));
+        let html = MailHandler::get_html(&message);
+        assert!(html.starts_with("<h3>【demo】#3</h3>"));
+        assert!(html.contains("<td style=\"color: #f5222d;\">Failed</td>"));
+        assert!(html.contains("<tr><td>耗时</td><td>65s</td></tr>"));
+        assert!(html.contains("<tr><td>失败步骤</td><td>&lt;build&gt;</td></tr>"));
+        assert!(html.contains("error: build failed</pre>"));
+    }
+
+    #[test]
+    fn test_split_recipients() {
+        assert_eq!(
+            MailHandler::split_recipients(" a@example.com, b@example.com;c@example.com\nd@example.com ,, "),
+            vec!["a@example.com", "b@example.com", "c@example.com", "d@example.com"]
+        );
+        assert!(MailHandler::split_recipients(" ; \n ").is_empty());
+    }
+
+    #[test]
+    fn test_mail_security() {
+        assert!(matches!(MailSecurity::get("None"), MailSecurity::None));
+        assert!(matches!(MailSecurity::get(" TLS "), MailSecurity::Tls));
+        assert!(matches!(MailSecurity::get("ssl"), MailSecurity::Tls));
+        assert!(matches!(MailSecurity::get("StartTls"), MailSecurity::StartTls));
+        assert!(matches!(MailSecurity::get(""), MailSecurity::StartTls));
+        assert_eq!(MailSecurity::get("none").get_default_port(), 25);
+        assert_eq!(MailSecurity::get("").get_default_port(), 587);
+        assert_eq!(MailSecurity::get("tls").get_default_port(), 465);
+    }
+}
@@ -1,2 +1,5 @@
+pub(crate) mod crypto;
 pub(crate) mod git;
 pub(crate) mod index;
+pub(crate) mod mail;
+pub(crate) mod node;
@@ -0,0 +1,162 @@
+//! nodeJs 版本
+
+use crate::error::Error;
+use crate::helper::index::Helper;
+use crate::setting::Settings;
+use log::info;
+use std::path::{Path, PathBuf};
+
+pub struct NodeHandler;
+
+impl NodeHandler {
+    /// 根据版本号获取 node bin 目录, 返回 None 时使用系统默认的 node
+    pub(crate) fn get_bin_dir(version: &str) -> Result<Option<String>, String> {
+        let version = Self::format_version(version);
+        if version.is_empty() {
+            return Ok(None);
+        }
+
+        // 系统默认 node 版本满足时直接使用
+        let default_version = Self::format_version(&Helper::get_cmd_version("node"));
+        if !default_version.is_empty() && Self::matched(&version, &default_version) {
+            info!("use default node version: {}", default_version);
+            return Ok(None);
+        }
+
+        let installed = Self::get_installed_versions();
+
+        // 优先完全匹配, 否则取匹配前缀的最高版本, 如 18 -> 18.20.1
+        let mut found: Option<&(String, PathBuf)> = installed.iter().find(|(v, _)| v == &version);
+        if found.is_none() {
+            found = installed.iter().filter(|(v, _)| Self::matched(&version, v)).max_by(|(a, _), (b, _)| Self::get_version_numbers(a).cmp(&Self::get_version_numbers(b)));
+        }
+
+        if let Some((v, dir)) = found {
+            info!("use node version: {}, bin dir: {:#?}", v, dir);
+            return Ok(Some(dir.to_string_lossy().to_string()));
+        }
+
+        let versions: Vec<String> = installed.iter().map(|(v, _)| v.clone()).collect();
+        let msg = if versions.is_empty() {
+            format!("node version `{}` not installed, no versions found in nvm、fnm、volta or toolchains dir !", version)
+        } else {
+            format!("node version `{}` not installed, installed versions: {} !", version, versions.join(", "))
+        };
+        return Err(Error::convert_string(&msg));
+    }
+
+    /// 获取本机已安装的 node 版本, 包括 nvm、fnm、volta 及设置中的 toolchains 目录
+    pub(crate) fn get_installed_versions() -> Vec<(String, PathBuf)> {
+        let mut roots: Vec<PathBuf> = Vec::new();
+
+        // 设置中的 toolchains 目录
+        if let Some(settings) = Settings::get_settings() {
+            if !settings.node_toolchains_dir.is_empty() {
+                roots.push(PathBuf::from(&settings.node_toolchains_dir));
+            }
+        }
+
+        // nvm
+        if let Ok(nvm_dir) = std::env::var("NVM_DIR") {
+            roots.push(PathBuf::from(nvm_dir).join("versions").join("node"));
+        }
+
+        // fnm
+        if let Ok(fnm_dir) = std::env::var("FNM_DIR") {
+            roots.push(PathBuf::from(fnm_dir).join("node-versions"));
+        }
+
+        if let Some(home_dir) = dirs::home_dir() {
+            roots.push(home_dir.join(".nvm").join("versions").join("node"));
+            roots.push(home_dir.join(".fnm").join("node-versions"));
+            roots.push(home_dir.join(".local").join("share").join("fnm").join("node-versions"));
+            roots.push(home_dir.join(".volta").join("tools").join("image").join("node"));
+        }
+
+        if let Some(data_dir) = dirs::data_dir() {
+            roots.push(data_dir.join("fnm").join("node-versions"));
+        }
+
+        let mut versions: Vec<(String, PathBuf)> = Vec::new();
+        for dir in roots.iter() {
+            let entries = match std::fs::read_dir(dir) {
+                Ok(entries) => entries,
+                Err(_) => continue,
+            };
+
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if !path.is_dir() {
+                    continue;
+                }
+
+                let version = Self::format_version(&entry.file_name().to_string_lossy().to_string());
+                if Self::get_version_numbers(&version).is_empty() {
+                    continue;
+                }
+
+                // fnm 的 node 在 installation 目录下
+                let mut bin_dir = Self::get_node_bin_dir(&path.join("installation"));
+                if bin_dir.is_none() {
+                    bin_dir = Self::get_node_bin_dir(&path);
+                }
+
+                if let Some(bin_dir) = bin_dir {
+                    if !versions.iter().any(|(v, _)| v == &version) {
+                        versions.push((version, bin_dir));
+                    }
+                }
+            }
+        }
+
+        versions
+    }
+
+    /// 获取 node 可执行文件所在目录, windows 下 node.exe 在根目录
+    fn get_node_bin_dir(dir: &Path) -> Option<PathBuf> {
+        let bin_dir = dir.join("bin");
+        if bin_dir.join("node").exists() {
+            return Some(bin_dir);
+        }
+
+        if dir.join("node.exe").exists() || dir.join("node").is_file() {
+            return Some(dir.to_path_buf());
+        }
+
+        None
+    }
+
+    /// 格式化版本号, 去掉 `v` 前缀
+    fn format_version(version: &str) -> String {
+        let version = version.trim();
+        version.strip_prefix('v').unwrap_or(version).to_string()
+    }
+
+    /// 判断版本是否匹配, 如 18 匹配 18.20.1, 18.20 匹配 18.20.1
+    fn matched(version: &str, installed: &str) -> bool {
+        if version == installed {
+            return true;
+        }
+
+        let version_numbers = Self::get_version_numbers(version);
+        let installed_numbers = Self::get_version_numbers(installed);
+        if version_numbers.is_empty() || version_numbers.len() > installed_numbers.len() {
+            return false;
+        }
+
+        version_numbers.iter().zip(installed_numbers.iter()).all(|(a, b)| a == b)
+    }
+
+    /// 版本号转成数字, 用于比较
+    fn get_version_numbers(version: &str) -> Vec<u32> {
+        let mut numbers: Vec<u32> = Vec::new();
+        for str in version.split('.') {
+            match str.parse::<u32>() {
+                Ok(number) => numbers.push(number),
+                Err(_) => return Vec::new(),
+            }
+        }
+
+        numbers
+    }
+}
@@ -1,9 +1,145 @@
 //! 记录流水线日志, 日志目录 {server_id/id}
 
+use crate::error::Error;
 use crate::logger::Logger;
+use crate::prepare::{get_error_response, get_success_response_by_value, HttpResponse};
+use crate::setting::Settings;
+use chrono::{Local, TimeZone};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
 use handlers::file::FileHandler;
+use lazy_static::lazy_static;
 use log::{error, info, warn};
-use std::path::PathBuf;
+use regex::RegexBuilder;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+lazy_static! {
+    // 正在执行的步骤, key: {server_id}/{id}/{order}, value: (stage_index, group_index, step_index)
+    static ref PIPELINE_LOG_STEPS: Mutex<HashMap<String, (u32, u32, u32)>> = Mutex::new(HashMap::new());
+
+    // 日志文件锁, 串行化追加、压缩及删除, key: 日志文件路径
+    static ref PIPELINE_LOG_FILE_LOCKS: Mutex<HashMap<PathBuf, Arc<Mutex<()>>>> = Mutex::new(HashMap::new());
+}
+
+/// 日志级别
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PipelineLogLevel {
+    Info,
+    Warn,
+    Error,
+}
+
+impl Default for PipelineLogLevel {
+    fn default() -> Self {
+        PipelineLogLevel::Info
+    }
+}
+
+/// 日志来源
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PipelineLogStream {
+    Stdout, // 命令标准输出
+    Stderr, // 命令标准错误
+    System, // 流水线自身输出
+}
+
+impl Default for PipelineLogStream {
+    fn default() -> Self {
+        PipelineLogStream::System
+    }
+}
+
+/// 单条日志, 每行一条 JSON
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct PipelineLogRecord {
+    pub(crate) time: u64, // 时间戳, 毫秒
+    pub(crate) level: PipelineLogLevel,
+    #[serde(rename = "stageIndex")]
+    pub(crate) stage_index: u32,
+    #[serde(rename = "groupIndex")]
+    pub(crate) group_index: u32,
+    #[serde(rename = "stepIndex")]
+    pub(crate) step_index: u32,
+    pub(crate) stream: PipelineLogStream,
+    pub(crate) msg: String,
+}
+
+/// 日志查询条件
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct PipelineLogQueryForm {
+    #[serde(rename = "stageIndex", default)]
+    pub(crate) stage_index: Option<u32>,
+    #[serde(rename = "groupIndex", default)]
+    pub(crate) group_index: Option<u32>,
+    #[serde(rename = "stepIndex", default)]
+    pub(crate) step_index: Option<u32>,
+    #[serde(rename = "startTime", default)]
+    pub(crate) start_time: Option<u64>, // 开始时间, 毫秒
+    #[serde(rename = "endTime", default)]
+    pub(crate) end_time: Option<u64>, // 结束时间, 毫秒
+    #[serde(default)]
+    pub(crate) stream: Option<PipelineLogStream>,
+}
+
+/// 日志搜索条件
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct PipelineLogSearchForm {
+    #[serde(rename = "serverId")]
+    pub(crate) server_id: String,
+    #[serde(default)]
+    pub(crate) id: String, // 为空时搜索服务器下所有流水线
+    pub(crate) keyword: String,
+    #[serde(default)]
+    pub(crate) regex: bool, // 是否为正则表达式
+    #[serde(rename = "ignoreCase", default)]
+    pub(crate) ignore_case: bool,
+    #[serde(default)]
+    pub(crate) context: Option<usize>, // 上下文行数
+    #[serde(default)]
+    pub(crate) limit: Option<usize>, // 最多返回条数
+}
+
+/// 日志搜索匹配行
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct PipelineLogSearchMatch {
+    #[serde(rename = "serverId")]
+    pub(crate) server_id: String,
+    pub(crate) id: String,
+    pub(crate) order: u32,  // 运行序号
+    pub(crate) line: usize, // 行号, 从 1 开始
+    pub(crate) record: PipelineLogRecord,
+    pub(crate) before: Vec<String>, // 前几行
+    pub(crate) after: Vec<String>,  // 后几行
+}
+
+/// 日志搜索结果, 搜索过程中也用于发送进度
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct PipelineLogSearchResult {
+    pub(crate) list: Vec<PipelineLogSearchMatch>,
+    pub(crate) scanned: usize,  // 已搜索的日志文件数
+    pub(crate) total: usize,    // 日志文件总数
+    pub(crate) matched: usize,  // 已匹配的行数
+    pub(crate) truncated: bool, // 超过最多返回条数
+    pub(crate) finished: bool,
+}
+
+// 压缩日志后缀
+const LOG_GZ_SUFFIX: &str = ".gz";
+
+// 默认上下文行数
+const DEFAULT_SEARCH_CONTEXT: usize = 2;
+
+// 默认最多返回条数
+const DEFAULT_SEARCH_LIMIT: usize = 200;
 
 pub struct PipelineLogger;
 impl PipelineLogger {
@@ -38,8 +174,20 @@ impl PipelineLogger {
         Logger::get_log_dir(vec![server_id.to_string(), id.to_string()])
     }
 
+    /// 记录正在执行的步骤, 之后的日志都属于该步骤
+    pub(crate) fn set_step(server_id: &str, id: &str, order: u32, stage_index: u32, group_index: u32, step_index: u32) {
+        let mut steps = PIPELINE_LOG_STEPS.lock().unwrap();
+        steps.insert(Self::get_step_key(server_id, id, order), (stage_index, group_index, step_index));
+    }
+
+    /// 运行结束后清除步骤
+    pub(crate) fn remove_step(server_id: &str, id: &str, order: u32) {
+        let mut steps = PIPELINE_LOG_STEPS.lock().unwrap();
+        steps.remove(&Self::get_step_key(server_id, id, order));
+    }
+
     /// 保存流水线日志
-    pub(crate) fn save_log(msg: &str, server_id: &str, id: &str, order: u32) -> bool {
+    pub(crate) fn save_log(msg: &str, server_id: &str, id: &str, order: u32, level: PipelineLogLevel, stream: PipelineLogStream) -> bool {
         let dir = Self::get_log_by_id(server_id, id);
         if let Some(dir) = dir {
             if !dir.exists() {
@@ -47,10 +195,38 @@ impl PipelineLogger {
                 return false;
             }
 
+            let (stage_index, group_index, step_index) = PIPELINE_LOG_STEPS.lock().unwrap().get(&Self::get_step_key(server_id, id, order)).cloned().unwrap_or((0, 0, 0));
+            let record = PipelineLogRecord {
+                time: SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_millis() as u64).unwrap_or(0),
+                level,
+                stage_index,
+                group_index,
+                step_index,
+                stream,
+                msg: msg.to_string(),
+            };
+
+            let line = match serde_json::to_string(&record) {
+                Ok(line) => line,
+                Err(err) => {
+                    info!("serialize pipeline log error: {:#?}", err);
+                    return false;
+                }
+            };
+
             let log_file_name = format!("{}.log", order);
             let file_path = dir.join(&log_file_name);
+            let file_lock = Self::get_file_lock(&file_path);
+            let _guard = file_lock.lock().unwrap();
 
-            return match FileHandler::write_file_string_pre_line(file_path.as_path().to_string_lossy().to_string().as_str(), msg) {
+            // 重新运行已压缩的记录时, 先解压再追加
+            if !file_path.exists() && Self::get_gz_file(&file_path).exists() {
+                if let Err(err) = Self::decompress_log(&file_path) {
+                    error!("{}", err);
+                }
+            }
+
+            return match FileHandler::write_file_string_pre_line(file_path.as_path().to_string_lossy().to_string().as_str(), &line) {
                 Ok(_) => true,
                 Err(err) => {
                     info!("save pipeline log error: {}", err);
@@ -63,14 +239,357 @@ impl PipelineLogger {
         }
     }
 
-    /// 读取流水线日志
+    /// 读取流水线日志, 只返回日志内容
     pub(crate) fn read_log(file_path: PathBuf) -> Result<String, String> {
+        let records = Self::read_records(file_path)?;
+        let lines: Vec<String> = records.into_iter().map(|record| record.msg).collect();
+        Ok(lines.join("\n"))
+    }
+
+    /// 读取某次运行的日志, 按行返回
+    pub(crate) fn read_lines(server_id: &str, id: &str, order: u32) -> Result<Vec<String>, String> {
+        let records = Self::read_records(Self::get_log_file(server_id, id, order)?)?;
+        Ok(records.iter().flat_map(|record| record.msg.lines().map(|line| line.to_string())).collect())
+    }
+
+    /// 按步骤、时间范围查询日志
+    pub(crate) fn query_log(server_id: &str, id: &str, order: u32, form: &PipelineLogQueryForm) -> Result<Vec<PipelineLogRecord>, String> {
+        let records = Self::read_records(Self::get_log_file(server_id, id, order)?)?;
+        let records = records
+            .into_iter()
+            .filter(|record| {
+                if let Some(stage_index) = form.stage_index {
+                    if record.stage_index != stage_index {
+                        return false;
+                    }
+                }
+
+                if let Some(group_index) = form.group_index {
+                    if record.group_index != group_index {
+                        return false;
+                    }
+                }
+
+                if let Some(step_index) = form.step_index {
+                    if record.step_index != step_index {
+                        return false;
+                    }
+                }
+
+                if let Some(start_time) = form.start_time {
+                    if record.time < start_time {
+                        return false;
+                    }
+                }
+
+                if let Some(end_time) = form.end_time {
+                    if record.time > end_time {
+                        return false;
+                    }
+                }
+
+                if let Some(stream) = &form.stream {
+                    if &record.stream != stream {
+                        return false;
+                    }
+                }
+
+                true
+            })
+            .collect();
+
+        Ok(records)
+    }
+
+    /// 导出纯文本日志
+    pub(crate) fn export_log(server_id: &str, id: &str, order: u32, file: &str) -> Result<(), String> {
+        let records = Self::read_records(Self::get_log_file(server_id, id, order)?)?;
+        let lines: Vec<String> = records
+            .iter()
+            .map(|record| {
+                let time = Local.timestamp_millis_opt(record.time as i64).single().map(|time| time.format("%Y-%m-%d %H:%M:%S%.3f").to_string()).unwrap_or(String::new());
+                let level = serde_json::to_value(&record.level).ok().and_then(|value| value.as_str().map(|str| str.to_uppercase())).unwrap_or(String::new());
+                let stream = serde_json::to_value(&record.stream).ok().and_then(|value| value.as_str().map(|str| str.to_string())).unwrap_or(String::new());
+                format!("{} {:<5} [{}-{}-{}] [{}] {}", time, level, record.stage_index, record.group_index, record.step_index, stream, record.msg)
+            })
+            .collect();
+
+        std::fs::write(file, lines.join("\n")).map_err(|err| Error::Error(format!("export pipeline log error: {:#?}", err)).to_string())?;
+        info!("export pipeline log to: {}", file);
+        Ok(())
+    }
+
+    /// 搜索流水线或服务器下所有运行日志, 每搜索完一个文件通过 progress 返回新匹配的行
+    pub(crate) fn search_log<F>(form: &PipelineLogSearchForm, progress: F) -> Result<HttpResponse, String>
+    where
+        F: Fn(&PipelineLogSearchResult),
+    {
+        if form.server_id.is_empty() {
+            return Ok(get_error_response("搜索日志失败, `serverId` 不能为空"));
+        }
+
+        if form.keyword.is_empty() {
+            return Ok(get_error_response("搜索日志失败, `keyword` 不能为空"));
+        }
+
+        let pattern = if form.regex { form.keyword.clone() } else { regex::escape(&form.keyword) };
+        let regex = match RegexBuilder::new(&pattern).case_insensitive(form.ignore_case).build() {
+            Ok(regex) => regex,
+            Err(err) => return Ok(get_error_response(&format!("搜索日志失败, 正则表达式错误: {}", err))),
+        };
+
+        let context = form.context.unwrap_or(DEFAULT_SEARCH_CONTEXT);
+        let limit = form.limit.unwrap_or(DEFAULT_SEARCH_LIMIT);
+        let files = Self::get_search_files(&form.server_id, &form.id);
+        info!("search pipeline log, keyword: {}, files: {}", &form.keyword, files.len());
+
+        let mut result = PipelineLogSearchResult::default();
+        result.total = files.len();
+        for (id, order, file) in files.iter() {
+            let records = match Self::read_records(file.clone()) {
+                Ok(records) => records,
+                Err(err) => {
+                    error!("read pipeline log {:#?} error: {}", file, err);
+                    result.scanned += 1;
+                    continue;
+                }
+            };
+
+            let mut list: Vec<PipelineLogSearchMatch> = Vec::new();
+            for (index, record) in records.iter().enumerate() {
+                if !regex.is_match(&record.msg) {
+                    continue;
+                }
+
+                if result.matched >= limit {
+                    result.truncated = true;
+                    break;
+                }
+
+                let start = index.saturating_sub(context);
+                let end = (index + context + 1).min(records.len());
+                list.push(PipelineLogSearchMatch {
+                    server_id: form.server_id.clone(),
+                    id: id.clone(),
+                    order: *order,
+                    line: index + 1,
+                    record: record.clone(),
+                    before: records[start..index].iter().map(|record| record.msg.clone()).collect(),
+                    after: records[index + 1..end].iter().map(|record| record.msg.clone()).collect(),
+                });
+                result.matched += 1;
+            }
+
+            result.scanned += 1;
+            progress(&PipelineLogSearchResult { list: list.clone(), ..result.clone() });
+            result.list.extend(list);
+
+            if result.truncated {
+                break;
+            }
+        }
+
+        result.finished = true;
+        progress(&PipelineLogSearchResult { list: Vec::new(), ..result.clone() });
+        get_success_response_by_value(result)
+    }
+
+    /// 获取需要搜索的日志文件: (流水线 ID, 运行序号, 文件), 最近的运行在前
+    fn get_search_files(server_id: &str, id: &str) -> Vec<(String, u32, PathBuf)> {
+        let mut dirs: Vec<(String, PathBuf)> = Vec::new();
+        if id.is_empty() {
+            if let Some(server_dir) = Logger::get_log_dir(vec![server_id.to_string()]) {
+                if let Ok(entries) = fs::read_dir(&server_dir) {
+                    for entry in entries.flatten() {
+                        if entry.path().is_dir() {
+                            dirs.push((entry.file_name().to_string_lossy().to_string(), entry.path()));
+                        }
+                    }
+                }
+            }
+        } else if let Some(dir) = Self::get_log_by_id(server_id, id) {
+            dirs.push((id.to_string(), dir));
+        }
+
+        let mut files: Vec<(String, u32, PathBuf, SystemTime)> = Vec::new();
+        for (id, dir) in dirs.iter() {
+            for (order, path, modified) in Self::get_log_files(dir).into_iter() {
+                files.push((id.clone(), order, path, modified));
+            }
+        }
+
+        files.sort_by(|a, b| b.3.cmp(&a.3).then(b.1.cmp(&a.1)));
+        files.into_iter().map(|(id, order, path, _)| (id, order, path)).collect()
+    }
+
+    /// 日志保留策略: 只保留最近几次或几天内的运行日志, 已结束运行的日志压缩存储, `active_orders` 为未结束的运行序号
+    pub(crate) fn retain_log(server_id: &str, id: &str, active_orders: &Vec<u32>) {
+        let dir = match Self::get_log_by_id(server_id, id) {
+            Some(dir) => dir,
+            None => return,
+        };
+
+        let mut keep_count: usize = 0;
+        let mut keep_days: u64 = 0;
+        if let Some(settings) = Settings::get_settings() {
+            keep_count = settings.log_keep_count.trim().parse::<usize>().unwrap_or(0);
+            keep_days = settings.log_keep_days.trim().parse::<u64>().unwrap_or(0);
+        }
+
+        let mut files = Self::get_log_files(&dir);
+        files.sort_by(|a, b| b.0.cmp(&a.0));
+
+        let running_orders: Vec<u32> = {
+            let steps = PIPELINE_LOG_STEPS.lock().unwrap();
+            files.iter().map(|(order, _, _)| *order).filter(|order| steps.contains_key(&Self::get_step_key(server_id, id, *order))).collect()
+        };
+
+        let now = SystemTime::now();
+        for (index, (order, file, modified)) in files.iter().enumerate() {
+            // 最近一次运行及未结束(排队、运行中、等待审批、矩阵子运行等)的运行可能还在写入日志
+            if index == 0 || active_orders.contains(order) || running_orders.contains(order) {
+                continue;
+            }
+
+            let file_lock = Self::get_file_lock(file);
+            let _guard = file_lock.lock().unwrap();
+
+            let expired_by_count = keep_count > 0 && index >= keep_count;
+            let expired_by_days = keep_days > 0 && now.duration_since(*modified).map(|duration| duration.as_secs() > keep_days * 24 * 60 * 60).unwrap_or(false);
+            if expired_by_count || expired_by_days {
+                info!("remove expired pipeline log, id: {}, order: {}", id, order);
+                let _ = fs::remove_file(file);
+                let _ = fs::remove_file(Self::get_gz_file(file));
+                continue;
+            }
+
+            if file.exists() {
+                if let Err(err) = Self::compress_log(file, *modified) {
+                    error!("{}", err);
+                }
+            }
+        }
+    }
+
+    /// 获取目录下的运行日志: (运行序号, 日志文件, 修改时间), 已压缩的日志也返回 `{order}.log`
+    fn get_log_files(dir: &Path) -> Vec<(u32, PathBuf, SystemTime)> {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => return Vec::new(),
+        };
+
+        let mut files: Vec<(u32, PathBuf, SystemTime)> = Vec::new();
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let stem = name.strip_suffix(LOG_GZ_SUFFIX).unwrap_or(&name);
+            let order = match stem.strip_suffix(".log").and_then(|order| order.parse::<u32>().ok()) {
+                Some(order) => order,
+                None => continue,
+            };
+
+            if files.iter().any(|(o, _, _)| *o == order) {
+                continue;
+            }
+
+            let modified = entry.metadata().and_then(|metadata| metadata.modified()).unwrap_or(UNIX_EPOCH);
+            files.push((order, dir.join(format!("{}.log", order)), modified));
+        }
+
+        files
+    }
+
+    /// 压缩日志, 保留原修改时间
+    fn compress_log(file: &Path, modified: SystemTime) -> Result<(), String> {
+        let gz_file = Self::get_gz_file(file);
+        let temp_file = PathBuf::from(format!("{}.tmp", gz_file.to_string_lossy()));
+        if let Err(err) = Self::write_gz_file(file, &temp_file, modified) {
+            let _ = fs::remove_file(&temp_file);
+            return Err(Error::Error(format!("compress pipeline log {:#?} error: {:#?}", file, err)).to_string());
+        }
+
+        fs::rename(&temp_file, &gz_file)
+            .and_then(|_| fs::remove_file(file))
+            .map_err(|err| Error::Error(format!("compress pipeline log {:#?} error: {:#?}", file, err)).to_string())
+    }
+
+    fn write_gz_file(file: &Path, gz_file: &Path, modified: SystemTime) -> std::io::Result<()> {
+        let mut reader = File::open(file)?;
+        let mut encoder = GzEncoder::new(File::create(gz_file)?, Compression::default());
+        std::io::copy(&mut reader, &mut encoder)?;
+        let output = encoder.finish()?;
+        output.set_modified(modified)
+    }
+
+    /// 解压日志
+    fn decompress_log(file: &Path) -> Result<(), String> {
+        let gz_file = Self::get_gz_file(file);
+        let result = File::open(&gz_file).and_then(|input| {
+            let mut output = File::create(file)?;
+            std::io::copy(&mut GzDecoder::new(input), &mut output)?;
+            fs::remove_file(&gz_file)
+        });
+
+        result.map_err(|err| Error::Error(format!("decompress pipeline log {:#?} error: {:#?}", gz_file, err)).to_string())
+    }
+
+    /// 获取日志文件锁
+    fn get_file_lock(file: &Path) -> Arc<Mutex<()>> {
+        let mut locks = PIPELINE_LOG_FILE_LOCKS.lock().unwrap();
+        locks.entry(file.to_path_buf()).or_insert_with(|| Arc::new(Mutex::new(()))).clone()
+    }
+
+    fn get_gz_file(file: &Path) -> PathBuf {
+        let mut gz_file = file.as_os_str().to_os_string();
+        gz_file.push(LOG_GZ_SUFFIX);
+        PathBuf::from(gz_file)
+    }
+
+    /// 读取日志记录, 兼容旧的纯文本日志
+    fn read_records(file_path: PathBuf) -> Result<Vec<PipelineLogRecord>, String> {
         info!("pipeline log path: {:#?}", file_path);
-        if !file_path.exists() {
+
+        // 与写入、压缩、删除使用同一把锁, 避免读取时日志被压缩或删除
+        let file_lock = Self::get_file_lock(&file_path);
+        let _guard = file_lock.lock().unwrap();
+
+        let gz_file = Self::get_gz_file(&file_path);
+        let content;
+        if file_path.exists() {
+            content = FileHandler::read_file_string(file_path.as_path().to_string_lossy().to_string().as_str())?;
+        } else if gz_file.exists() {
+            // 已压缩的日志, 直接解压读取
+            let file = File::open(&gz_file).map_err(|err| Error::Error(format!("open pipeline log {:#?} error: {:#?}", gz_file, err)).to_string())?;
+            let mut decoded = String::new();
+            GzDecoder::new(file)
+                .read_to_string(&mut decoded)
+                .map_err(|err| Error::Error(format!("decompress pipeline log {:#?} error: {:#?}", gz_file, err)).to_string())?;
+            content = decoded;
+        } else {
             warn!("can not find pipeline log file: {:#?} !", file_path);
-            return Ok(String::new());
+            return Ok(Vec::new());
+        }
+
+        let records = content
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(|line| match serde_json::from_str::<PipelineLogRecord>(line) {
+                Ok(record) => record,
+                Err(_) => PipelineLogRecord { msg: line.to_string(), ..Default::default() },
+            })
+            .collect();
+
+        Ok(records)
+    }
+
+    /// 获取日志文件
+    fn get_log_file(server_id: &str, id: &str, order: u32) -> Result<PathBuf, String> {
+        match Self::get_log_by_id(server_id, id) {
+            Some(dir) => Ok(dir.join(format!("{}.log", order))),
+            None => Err(Error::convert_string("get pipeline log dir failed !")),
         }
+    }
 
-        FileHandler::read_file_string(file_path.as_path().to_string_lossy().to_string().as_str())
+    fn get_step_key(server_id: &str, id: &str, order: u32) -> String {
+        format!("{}/{}/{}", server_id, id, order)
     }
 }
@@ -2,25 +2,76 @@
   聊天机器人
 */
 
+pub(crate) mod webhook;
+
+use crate::helper::crypto::CryptoHandler;
 use crate::prepare::HttpResponse;
 use crate::utils::cache::CacheHelper;
 use serde::{Deserialize, Serialize};
 
 const ROBOT_FILE: &str = "robot.json";
 
-#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+// 默认重试次数
+const DEFAULT_RETRY_COUNT: u32 = 3;
+
+#[derive(Default, Clone, Serialize, Deserialize)]
 pub struct Robot {
     name: String,
     pub url: String,
+    #[serde(default)]
+    pub(crate) kind: RobotKind, // 机器人类型, 决定消息模板
+    #[serde(default)]
+    pub(crate) secret: String, // 加签密钥, 加密保存
+    #[serde(default)]
+    pub(crate) retry: Option<u32>, // 发送失败重试次数
+}
+
+impl std::fmt::Debug for Robot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Robot")
+            .field("name", &self.name)
+            .field("url", &self.url)
+            .field("kind", &self.kind)
+            .field("secret", &if self.secret.is_empty() { String::new() } else { String::from("******") })
+            .field("retry", &self.retry)
+            .finish()
+    }
+}
+
+/// 机器人类型
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum RobotKind {
+    DingTalk, // 钉钉
+    Feishu,   // 飞书/Lark
+    WeCom,    // 企业微信
+    Json,     // 通用 JSON
+}
+
+impl Default for RobotKind {
+    fn default() -> Self {
+        RobotKind::Json
+    }
 }
 
 impl Robot {
-    // 保存
+    // 保存, 加签密钥加密后保存
     pub fn save(robot: &Robot) -> Result<HttpResponse, String> {
-        CacheHelper::save::<Robot>(robot, ROBOT_FILE)
+        let mut robot = robot.clone();
+        robot.secret = CryptoHandler::encrypt(&robot.secret)?;
+        CacheHelper::save::<Robot>(&robot, ROBOT_FILE)
     }
 
     pub fn get() -> Result<HttpResponse, String> {
         CacheHelper::get::<Robot>(ROBOT_FILE)
     }
+
+    /// 获取配置, 未配置 url 时返回 None
+    pub(crate) fn get_config() -> Option<Robot> {
+        CacheHelper::get_config::<Robot>(ROBOT_FILE).filter(|robot| !robot.url.trim().is_empty())
+    }
+
+    /// 获取重试次数
+    pub(crate) fn get_retry(&self) -> u32 {
+        self.retry.unwrap_or(DEFAULT_RETRY_COUNT)
+    }
 }
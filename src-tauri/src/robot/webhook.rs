@@ -0,0 +1,332 @@
+//! 机器人 webhook 消息发送, 支持钉钉、飞书/Lark、企业微信及通用 JSON
+
+use crate::error::Error;
+use crate::helper::crypto::CryptoHandler;
+use crate::robot::{Robot, RobotKind};
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use log::{error, info};
+use serde::Serialize;
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::time::Duration;
+
+// 请求超时时间
+const REQUEST_TIMEOUT_SECONDS: u64 = 10;
+
+// 企业微信 markdown 内容最大长度, 单位字节
+const WECOM_MARKDOWN_MAX_BYTES: usize = 4096;
+
+// 钉钉、飞书请求体最大长度, 单位字节
+const DINGTALK_BODY_MAX_BYTES: usize = 20000;
+const FEISHU_BODY_MAX_BYTES: usize = 20 * 1024;
+
+/// 运行结果消息
+#[derive(Default, Debug, Clone, Serialize)]
+pub(crate) struct RobotMessage {
+    pub(crate) pipeline: String, // 流水线名称
+    pub(crate) order: u32,       // 运行序号
+    pub(crate) status: String,   // 运行状态
+    pub(crate) duration: u64,    // 耗时, 毫秒
+    pub(crate) branch: String,   // 分支
+    #[serde(rename = "failedStep")]
+    pub(crate) failed_step: Option<String>, // 失败步骤
+    pub(crate) log: Vec<String>, // 日志摘录
+}
+
+impl RobotMessage {
+    /// 测试用的失败运行消息, 机器人及邮件测试共用
+    #[cfg(test)]
+    pub(crate) fn test_failed() -> Self {
+        Self {
+            pipeline: String::from("demo"),
+            order: 3,
+            status: String::from("Failed"),
+            duration: 65000,
+            branch: String::from("master"),
+            failed_step: Some(String::from("build")),
+            log: vec![String::from("npm run build"), String::from("error: build failed")],
+        }
+    }
+
+    /// 标题
+    fn get_title(&self) -> String {
+        format!("【{}】#{} {}", self.pipeline, self.order, self.status)
+    }
+
+    /// markdown 内容
+    fn get_markdown(&self) -> String {
+        let mut lines = vec![
+            format!("### {}", self.get_title()),
+            format!("- 状态: {}", self.status),
+            format!("- 分支: {}", if self.branch.is_empty() { "-" } else { &self.branch }),
+            format!("- 耗时: {}s", self.duration / 1000),
+        ];
+
+        if let Some(failed_step) = &self.failed_step {
+            lines.push(format!("- 失败步骤: {}", failed_step));
+        }
+
+        if !self.log.is_empty() {
+            lines.push(String::new());
+            lines.push(format!("```\n{}\n```", self.log.join("\n")));
+        }
+
+        lines.join("\n")
+    }
+
+    /// 纯文本内容
+    fn get_text(&self) -> String {
+        let mut lines = vec![self.get_title(), format!("分支: {}", self.branch), format!("耗时: {}s", self.duration / 1000)];
+        if let Some(failed_step) = &self.failed_step {
+            lines.push(format!("失败步骤: {}", failed_step));
+        }
+
+        if !self.log.is_empty() {
+            lines.push(String::new());
+            lines.extend(self.log.iter().cloned());
+        }
+
+        lines.join("\n")
+    }
+}
+
+pub struct RobotWebhook;
+
+impl RobotWebhook {
+    /// 发送消息, 失败后按重试次数重试
+    pub(crate) async fn send(robot: &Robot, message: &RobotMessage) -> Result<(), String> {
+        let secret = CryptoHandler::decrypt(&robot.secret)?;
+        let client = reqwest::Client::builder().timeout(Duration::from_secs(REQUEST_TIMEOUT_SECONDS)).build().map_err(|err| Error::Error(err.to_string()).to_string())?;
+
+        let retry = robot.get_retry();
+        let mut last_error = String::new();
+        for attempt in 0..=retry {
+            if attempt > 0 {
+                // 退避重试: 2s, 4s, 8s ...
+                tokio::time::sleep(Duration::from_secs(2u64.pow(attempt.min(5)))).await;
+                info!("retry send robot message, attempt: {}", attempt);
+            }
+
+            match Self::post(&client, robot, &secret, message).await {
+                Ok(_) => {
+                    info!("send robot message success !");
+                    return Ok(());
+                }
+                Err(err) => {
+                    error!("send robot message error: {}", &err);
+                    last_error = err;
+                }
+            }
+        }
+
+        Err(Error::convert_string(&format!("send robot message failed after {} retries, {}", retry, last_error)))
+    }
+
+    /// 发送请求
+    async fn post(client: &reqwest::Client, robot: &Robot, secret: &str, message: &RobotMessage) -> Result<(), String> {
+        let timestamp = chrono::Utc::now().timestamp_millis();
+        let mut url = robot.url.trim().to_string();
+        let mut body = Self::get_body(&robot.kind, &Self::truncate(&robot.kind, message));
+        let mut request_headers: Vec<(&str, String)> = Vec::new();
+
+        if !secret.is_empty() {
+            match robot.kind {
+                RobotKind::DingTalk => {
+                    // 钉钉: url 携带 timestamp(毫秒) 和 sign
+                    let sign = Self::get_dingtalk_sign(timestamp, secret)?;
+                    let separator = if url.contains('?') { "&" } else { "?" };
+                    url = format!("{}{}timestamp={}&sign={}", url, separator, timestamp, urlencoding::encode(&sign));
+                }
+                RobotKind::Feishu => {
+                    // 飞书: body 携带 timestamp(秒) 和 sign, 以 `timestamp\nsecret` 为密钥签名空串
+                    let timestamp = timestamp / 1000;
+                    let sign = Self::get_feishu_sign(timestamp, secret)?;
+                    body["timestamp"] = json!(timestamp.to_string());
+                    body["sign"] = json!(sign);
+                }
+                RobotKind::WeCom => {
+                    // 企业微信 webhook 无加签, key 已包含在 url 中
+                }
+                RobotKind::Json => {
+                    // 通用 JSON: 请求头携带时间戳及 `timestamp\nbody` 的签名
+                    let sign = Self::sign(secret.as_bytes(), format!("{}\n{}", timestamp, body).as_bytes())?;
+                    request_headers.push(("X-Timestamp", timestamp.to_string()));
+                    request_headers.push(("X-Signature", sign));
+                }
+            }
+        }
+
+        let mut request = client.post(&url).json(&body);
+        for (name, value) in request_headers {
+            request = request.header(name, value);
+        }
+
+        let response = request.send().await.map_err(|err| Error::Error(err.to_string()).to_string())?;
+        let status = response.status();
+        let text = response.text().await.unwrap_or(String::new());
+        if !status.is_success() {
+            return Err(Error::convert_string(&format!("response status: {}, body: {}", status, text)));
+        }
+
+        // 钉钉、企业微信返回 errcode, 飞书返回 code, 非 0 为失败
+        if let Ok(value) = serde_json::from_str::<Value>(&text) {
+            let code = value.get("errcode").or(value.get("code")).and_then(|code| code.as_i64()).unwrap_or(0);
+            if code != 0 {
+                return Err(Error::convert_string(&format!("response error: {}", text)));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// 超出平台长度限制时, 从前往后丢弃日志摘录, 保留最后的日志
+    fn truncate(kind: &RobotKind, message: &RobotMessage) -> RobotMessage {
+        let limit = match kind {
+            RobotKind::DingTalk => DINGTALK_BODY_MAX_BYTES,
+            RobotKind::Feishu => FEISHU_BODY_MAX_BYTES,
+            RobotKind::WeCom => WECOM_MARKDOWN_MAX_BYTES,
+            RobotKind::Json => return message.clone(),
+        };
+
+        let mut message = message.clone();
+        if Self::get_size(kind, &message) <= limit {
+            return message;
+        }
+
+        message.log.insert(0, String::from("..."));
+        while message.log.len() > 1 && Self::get_size(kind, &message) > limit {
+            message.log.remove(1);
+        }
+
+        if message.log.len() <= 1 {
+            message.log.clear();
+        }
+
+        message
+    }
+
+    /// 平台限制的消息长度, 企业微信限制 markdown 内容, 钉钉、飞书限制请求体
+    fn get_size(kind: &RobotKind, message: &RobotMessage) -> usize {
+        return match kind {
+            RobotKind::WeCom => message.get_markdown().len(),
+            _ => Self::get_body(kind, message).to_string().len(),
+        };
+    }
+
+    /// 钉钉签名, 以 secret 为密钥签名 `timestamp\nsecret`, timestamp 单位毫秒
+    fn get_dingtalk_sign(timestamp: i64, secret: &str) -> Result<String, String> {
+        Self::sign(secret.as_bytes(), format!("{}\n{}", timestamp, secret).as_bytes())
+    }
+
+    /// 飞书签名, 以 `timestamp\nsecret` 为密钥签名空串, timestamp 单位秒
+    fn get_feishu_sign(timestamp: i64, secret: &str) -> Result<String, String> {
+        Self::sign(format!("{}\n{}", timestamp, secret).as_bytes(), b"")
+    }
+
+    /// 根据机器人类型生成消息体
+    fn get_body(kind: &RobotKind, message: &RobotMessage) -> Value {
+        return match kind {
+            RobotKind::DingTalk => json!({
+                "msgtype": "markdown",
+                "markdown": {
+                    "title": message.get_title(),
+                    "text": message.get_markdown(),
+                }
+            }),
+            RobotKind::Feishu => json!({
+                "msg_type": "text",
+                "content": {
+                    "text": message.get_text(),
+                }
+            }),
+            RobotKind::WeCom => json!({
+                "msgtype": "markdown",
+                "markdown": {
+                    "content": message.get_markdown(),
+                }
+            }),
+            RobotKind::Json => serde_json::to_value(message).unwrap_or(Value::Null),
+        };
+    }
+
+    /// HmacSHA256 签名, base64 编码
+    fn sign(key: &[u8], content: &[u8]) -> Result<String, String> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(key).map_err(|err| Error::Error(err.to_string()).to_string())?;
+        mac.update(content);
+        Ok(base64::engine::general_purpose::STANDARD.encode(mac.finalize().into_bytes()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sign() {
+        // RFC 4231 test case 2
+        let sign = RobotWebhook::sign(b"Jefe", b"what do ya want for nothing?").unwrap();
+        assert_eq!(sign, "W9zBRr9gdU5qBCQmCJV1x1oAPwidJzmDnexYuWTsOEM=");
+    }
+
+    #[test]
+    fn test_dingtalk_sign() {
+        let sign = RobotWebhook::get_dingtalk_sign(1700000000000, "SECtest").unwrap();
+        assert_eq!(sign, "aZLLrriXgn05YbwaGR7knYsLeJADjr9NwLaNNKpxh4g=");
+        assert_eq!(urlencoding::encode(&sign), "aZLLrriXgn05YbwaGR7knYsLeJADjr9NwLaNNKpxh4g%3D");
+    }
+
+    #[test]
+    fn test_feishu_sign() {
+        let sign = RobotWebhook::get_feishu_sign(1700000000, "SECtest").unwrap();
+        assert_eq!(sign, "G7XpBpG8NgG02fJOAhX6FRAObIljmFoxVReo8I62pEk=");
+    }
+
+    #[test]
+    fn test_get_body() {
+        let message = RobotMessage::test_failed();
+
+        let body = RobotWebhook::get_body(&RobotKind::DingTalk, &message);
+        assert_eq!(body["msgtype"], "markdown");
+        assert_eq!(body["markdown"]["title"], "【demo】#3 Failed");
+        let text = body["markdown"]["text"].as_str().unwrap();
+        assert!(text.starts_with("### 【demo】#3 Failed"));
+        assert!(text.contains("- 耗时: 65s"));
+        assert!(text.contains("- 失败步骤: build"));
+        assert!(text.contains("```\nnpm run build\nerror: build failed\n```"));
+
+        let body = RobotWebhook::get_body(&RobotKind::Feishu, &message);
+        assert_eq!(body["msg_type"], "text");
+        assert_eq!(body["content"]["text"], "【demo】#3 Failed\n分支: master\n耗时: 65s\n失败步骤: build\n\nnpm run build\nerror: build failed");
+
+        let body = RobotWebhook::get_body(&RobotKind::WeCom, &message);
+        assert_eq!(body["msgtype"], "markdown");
+        assert_eq!(body["markdown"]["content"], message.get_markdown());
+
+        let body = RobotWebhook::get_body(&RobotKind::Json, &message);
+        assert_eq!(body["pipeline"], "demo");
+        assert_eq!(body["failedStep"], "build");
+        assert_eq!(body["log"].as_array().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_truncate() {
+        let mut message = RobotMessage::test_failed();
+        message.log = (0..500).map(|index| format!("line {} {}", index, "x".repeat(40))).collect();
+
+        let truncated = RobotWebhook::truncate(&RobotKind::WeCom, &message);
+        assert!(truncated.get_markdown().len() <= WECOM_MARKDOWN_MAX_BYTES);
+        assert_eq!(truncated.log.first().unwrap(), "...");
+        assert_eq!(truncated.log.last(), message.log.last());
+
+        let truncated = RobotWebhook::truncate(&RobotKind::DingTalk, &message);
+        assert!(RobotWebhook::get_body(&RobotKind::DingTalk, &truncated).to_string().len() <= DINGTALK_BODY_MAX_BYTES);
+
+        let truncated = RobotWebhook::truncate(&RobotKind::Feishu, &message);
+        assert!(RobotWebhook::get_body(&RobotKind::Feishu, &truncated).to_string().len() <= FEISHU_BODY_MAX_BYTES);
+
+        // 通用 JSON 不截断, 未超出时不变
+        assert_eq!(RobotWebhook::truncate(&RobotKind::Json, &message).log.len(), 500);
+        assert_eq!(RobotWebhook::truncate(&RobotKind::WeCom, &RobotMessage::test_failed()).log, RobotMessage::test_failed().log);
+    }
+}
@@ -4,15 +4,19 @@ use crate::database::helper::DBHelper;
 use crate::database::interface::{Treat, TreatBody};
 use crate::error::Error;
 use crate::exports::pipeline::QueryForm;
-use crate::helper::git::GitHandler;
+use crate::helper::git::{GitEnvs, GitHandler};
 use crate::helper::index::Helper;
 use crate::logger::pipeline::PipelineLogger;
 use crate::prepare::{get_error_response, get_success_response, get_success_response_by_value, HttpResponse};
 use crate::server::pipeline::languages::h5::H5FileHandler;
+use crate::server::pipeline::pool::Pool;
 use crate::server::pipeline::props::{
-    H5RunnableVariable, OsCommands, PipelineBasic, PipelineCommandStatus, PipelineGroup, PipelineProcess, PipelineRuntime, PipelineStage, PipelineStatus, PipelineStep, PipelineStepComponent, PipelineTag, PipelineVariable, RunnableVariable,
+    H5RunnableVariable, OsCommands, PipelineBasic, PipelineCommandStatus, PipelineGitCredential, PipelineGitCredentialKind, PipelineGroup, PipelineOptions, PipelineProcess, PipelineRuntime, PipelineRuntimeProgress, PipelineStage, PipelineStatus,
+    PipelineStep, PipelineStepComponent, PipelineTag, PipelineVariable, RunnableVariable,
 };
+use crate::server::pipeline::runnable::matrix::PipelineMatrix;
 use crate::server::pipeline::runnable::{PipelineRunnable, PipelineRunnableQueryForm};
+use crate::server::pipeline::workspace::PipelineWorkspace;
 use async_trait::async_trait;
 use handlers::utils::Utils;
 use indexmap::IndexMap;
@@ -22,6 +26,7 @@ use serde_json::Value;
 use sqlx::mysql::{MySqlArguments, MySqlRow};
 use sqlx::query::Query;
 use sqlx::{FromRow, MySql, Row};
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use uuid::Uuid;
 
@@ -40,6 +45,8 @@ pub struct Pipeline {
     #[serde(rename = "processConfig")]
     pub(crate) process_config: PipelineProcess, // 流程配置
     pub(crate) variables: Vec<PipelineVariable>, // 变量
+    #[serde(default)]
+    pub(crate) options: PipelineOptions, // 附加配置
     #[serde(rename = "createTime")]
     pub(crate) create_time: Option<String>, // 创建时间
     #[serde(rename = "updateTime")]
@@ -55,6 +62,7 @@ impl<'r> FromRow<'r, MySqlRow> for Pipeline {
         let status_str: String = row.try_get("status")?;
         let tag_str: String = row.try_get("tagValue")?;
         let tag = Some(PipelineTag::get(&tag_str));
+        let options_str: String = row.try_get("options").unwrap_or(String::new());
 
         let basic = PipelineBasic {
             id: row.try_get("basic_id")?,
@@ -79,6 +87,7 @@ impl<'r> FromRow<'r, MySqlRow> for Pipeline {
             basic,
             process_config: Default::default(),
             variables: Vec::new(),
+            options: serde_json::from_str(&options_str).unwrap_or(PipelineOptions::default()),
             runnable_info: None,
             runtime: None,
         })
@@ -103,6 +112,11 @@ impl Treat<HttpResponse> for Pipeline {
             return Ok(res);
         }
 
+        let res = Self::validate_downstream(&pipeline).await?;
+        if let Some(res) = res {
+            return Ok(res);
+        }
+
         let mut pipeline_clone = pipeline.clone();
         if pipeline_clone.id.is_empty() {
             pipeline_clone.id = Uuid::new_v4().to_string()
@@ -143,10 +157,11 @@ impl Treat<HttpResponse> for Pipeline {
             tag_id,
             last_run_time,
             status,
+            options,
             create_time,
             update_time
             )
-            VALUES (?, ?, ?, ?, ?, ?, ?)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?)
         "#,
         )
         .bind(&pipeline_clone.id)
@@ -154,6 +169,7 @@ impl Treat<HttpResponse> for Pipeline {
         .bind(tag.id.clone())
         .bind("")
         .bind(PipelineStatus::got(PipelineStatus::No))
+        .bind(Self::get_options_str(&pipeline_clone.options)?)
         .bind(&create_time)
         .bind(&pipeline_clone.update_time);
         query_list.push(pipeline_query);
@@ -215,6 +231,11 @@ impl Treat<HttpResponse> for Pipeline {
             return Ok(res);
         }
 
+        let res = Self::validate_downstream(&pipeline).await?;
+        if let Some(res) = res {
+            return Ok(res);
+        }
+
         info!("update pipeline params: {:#?}", pipeline);
         let response = Self::get_query_list(&pipeline, None, true).await?;
         if response.code != 200 {
@@ -251,13 +272,14 @@ impl Treat<HttpResponse> for Pipeline {
             Self::insert_variables(pipeline.id.clone(), create_time.clone(), basic.update_time.clone(), &pipeline.variables, &mut query_list);
         }
 
-        // 更新 pipeline 表 update_time
+        // 更新 pipeline 表 update_time, options
         let pipeline_query = sqlx::query::<MySql>(
             r#"
-            UPDATE pipeline SET update_time = ? WHERE id = ?
+            UPDATE pipeline SET update_time = ?, options = ? WHERE id = ?
         "#,
         )
         .bind(update_time.clone())
+        .bind(Self::get_options_str(&pipeline.options)?)
         .bind(&pipeline.id);
         query_list.push(pipeline_query);
 
@@ -366,6 +388,9 @@ impl Treat<HttpResponse> for Pipeline {
 
         // 删除流水线日志
         PipelineLogger::delete_log_by_id(&server_id, &id);
+
+        // 删除工作空间及依赖缓存
+        PipelineWorkspace::delete(&server_id, &id);
         return Ok(response);
     }
 
@@ -415,8 +440,27 @@ impl Treat<HttpResponse> for Pipeline {
                 let log_file_dir = Path::new(log).join(log_file);
                 let log = PipelineLogger::read_log(log_file_dir)?;
                 runtime.log = Some(log);
-                pipeline.runtime = Some(runtime)
             }
+
+            // 矩阵构建的子运行记录
+            if runtime.is_matrix {
+                if let Some(id) = &runtime.id {
+                    runtime.children = PipelineMatrix::get_children(&pipeline, id).await?;
+                }
+            }
+
+            // 排队中, 获取排队位置及预计等待时长
+            if matches!(runtime.status, PipelineStatus::Queue) {
+                if let Some(item) = Pool::get_queue_list().await.into_iter().find(|item| Some(&item.runtime_id) == runtime.id.as_ref()) {
+                    runtime.progress = Some(PipelineRuntimeProgress {
+                        queue_position: Some(item.position),
+                        eta_ms: item.wait_ms,
+                        ..Default::default()
+                    });
+                }
+            }
+
+            pipeline.runtime = Some(runtime)
         }
 
         let data = serde_json::to_value(pipeline.clone()).map_err(|err| Error::Error(err.to_string()).to_string())?;
@@ -436,6 +480,7 @@ impl Pipeline {
                 p.last_run_time as pipeline_last_run_time,
                 p.last_run_id as pipeline_last_run_id,
                 p.`status` as pipeline_status,
+                p.options as pipeline_options,
                 p.create_time as pipeline_create_time,
                 p.update_time as pipeline_update_time,
                 b.id as basic_id,
@@ -566,6 +611,7 @@ impl Pipeline {
             let status_str: String = row.try_get("pipeline_status").unwrap_or(String::new());
             let tag_str: String = row.try_get("tagValue").unwrap_or(String::new());
             let tag = Some(PipelineTag::get(&tag_str));
+            let options_str: String = row.try_get("pipeline_options").unwrap_or(String::new());
 
             let basic = PipelineBasic {
                 id: row.try_get("basic_id").unwrap_or(String::new()),
@@ -589,6 +635,7 @@ impl Pipeline {
                 basic,
                 process_config: Default::default(),
                 variables: vec![],
+                options: serde_json::from_str(&options_str).unwrap_or(PipelineOptions::default()),
                 runnable_info: None,
                 runtime: None,
                 create_time: row.try_get("pipeline_create_time").unwrap_or(None),
@@ -753,7 +800,7 @@ impl Pipeline {
         if need_get_child {
             for pipe in list.iter_mut() {
                 let basic = &pipe.basic;
-                let runnable_info = Self::get_runnable_variable(&basic, installed_commands.clone(), &node);
+                let runnable_info = Self::get_runnable_variable(&basic, &pipe.options.credential, installed_commands.clone(), &node);
                 pipe.runnable_info = Some(runnable_info);
                 if let Some(last_run_id) = &pipe.last_run_id {
                     let result = PipelineRunnable::get_runtime_detail(
@@ -904,6 +951,13 @@ impl Pipeline {
         }
     }
 
+    /// 附加配置转成字符串, 凭证加密保存
+    fn get_options_str(options: &PipelineOptions) -> Result<String, String> {
+        let mut options = options.clone();
+        options.credential = options.credential.encrypt()?;
+        serde_json::to_string(&options).map_err(|err| Error::Error(err.to_string()).to_string())
+    }
+
     /// 数据检查
     fn validate(pipeline: &Pipeline) -> Option<HttpResponse> {
         let basic = &pipeline.basic;
@@ -922,10 +976,34 @@ impl Pipeline {
             return Some(get_error_response("更新流水线失败, `process_config` 中 `steps` 不能为空"));
         }
 
+        // 检查私有仓库凭证
+        let credential = &pipeline.options.credential;
+        match credential.kind {
+            PipelineGitCredentialKind::None => {}
+            PipelineGitCredentialKind::Https => {
+                if credential.token.is_empty() {
+                    return Some(get_error_response("更新流水线失败, `credential` 中 `token` 不能为空"));
+                }
+            }
+            PipelineGitCredentialKind::Ssh => {
+                if credential.ssh_key.is_empty() {
+                    return Some(get_error_response("更新流水线失败, `credential` 中 `sshKey` 不能为空"));
+                }
+            }
+        }
+
         // 判断路径是否存在(本地路径)
         let path = &basic.path;
         if GitHandler::is_remote_url(path) {
-            let validate_success = GitHandler::validate_remote_url(path);
+            let envs = match credential.get_git_credential().and_then(|credential| GitHandler::get_envs(&credential, path)) {
+                Ok(envs) => envs,
+                Err(err) => {
+                    error!("get git credential error: {}", err);
+                    return Some(get_error_response(&format!("更新流水线失败, 私有仓库凭证无效, {}", err)));
+                }
+            };
+
+            let validate_success = GitHandler::validate_remote_url(path, &envs);
             if !validate_success {
                 return Some(get_error_response("更新流水线失败, `在线项目路径` 不存在"));
             }
@@ -944,13 +1022,73 @@ impl Pipeline {
             }
         }
 
+        // 检查下游流水线
+        for downstream in pipeline.options.downstream.iter() {
+            if downstream.pipeline_id.is_empty() {
+                return Some(get_error_response("更新流水线失败, `downstream` 中 `pipelineId` 不能为空"));
+            }
+
+            if !pipeline.id.is_empty() && downstream.pipeline_id == pipeline.id {
+                return Some(get_error_response("更新流水线失败, 下游流水线不能是自身"));
+            }
+        }
+
+        // 检查矩阵构建
+        for axis in pipeline.options.matrix.iter() {
+            if axis.name.is_empty() || axis.values.is_empty() {
+                return Some(get_error_response("更新流水线失败, `matrix` 中 `name`、 `values` 不能为空"));
+            }
+        }
+
         return None;
     }
 
+    /// 检查下游流水线是否存在循环触发, 如 A -> B -> A
+    async fn validate_downstream(pipeline: &Pipeline) -> Result<Option<HttpResponse>, String> {
+        if pipeline.id.is_empty() || pipeline.options.downstream.is_empty() {
+            return Ok(None);
+        }
+
+        // 所有流水线的下游, 当前流水线使用保存中的配置
+        let rows = DBHelper::execute_rows(sqlx::query("SELECT id, options FROM pipeline")).await?;
+        let mut graph: HashMap<String, Vec<String>> = HashMap::new();
+        for row in rows.iter() {
+            let id: String = row.try_get("id").unwrap_or(String::new());
+            let options_str: String = row.try_get("options").unwrap_or(String::new());
+            let options: PipelineOptions = serde_json::from_str(&options_str).unwrap_or(PipelineOptions::default());
+            graph.insert(id, options.downstream.iter().map(|downstream| downstream.pipeline_id.clone()).collect());
+        }
+
+        graph.insert(pipeline.id.clone(), pipeline.options.downstream.iter().map(|downstream| downstream.pipeline_id.clone()).collect());
+
+        // 从当前流水线出发, 能回到自身即存在循环
+        let mut visited: HashSet<String> = HashSet::new();
+        let mut stack: Vec<String> = graph.get(&pipeline.id).cloned().unwrap_or(Vec::new());
+        while let Some(id) = stack.pop() {
+            if id == pipeline.id {
+                return Ok(Some(get_error_response("更新流水线失败, 下游流水线存在循环触发")));
+            }
+
+            if !visited.insert(id.clone()) {
+                continue;
+            }
+
+            if let Some(next) = graph.get(&id) {
+                stack.extend(next.iter().cloned());
+            }
+        }
+
+        Ok(None)
+    }
+
     /// 获取运行时的变量
-    fn get_runnable_variable(basic: &PipelineBasic, installed_commands: Vec<String>, node: &str) -> RunnableVariable {
+    fn get_runnable_variable(basic: &PipelineBasic, credential: &PipelineGitCredential, installed_commands: Vec<String>, node: &str) -> RunnableVariable {
         // branch
-        let branches = GitHandler::get_branch_list(&basic.path);
+        let envs = credential.get_git_credential().and_then(|credential| GitHandler::get_envs(&credential, &basic.path)).unwrap_or_else(|err| {
+            error!("get git credential error: {}", err);
+            GitEnvs::default()
+        });
+        let branches = GitHandler::get_branch_list(&basic.path, &envs);
 
         // h5
         let mut h5_variable: Option<H5RunnableVariable> = None;
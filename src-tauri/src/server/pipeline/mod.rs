@@ -7,3 +7,4 @@ pub(crate) mod index;
 pub(crate) mod languages;
 pub(crate) mod pool;
 pub(crate) mod tag;
+pub(crate) mod workspace;
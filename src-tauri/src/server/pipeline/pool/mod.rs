@@ -3,34 +3,44 @@
 use crate::database::helper::DBHelper;
 use crate::error::Error;
 use crate::event::EventEmitter;
+use crate::logger::pipeline::{PipelineLogLevel, PipelineLogStream, PipelineLogger};
 use crate::logger::Logger;
 use crate::prepare::{get_success_response_by_value, HttpResponse};
 use crate::server::pipeline::index::Pipeline;
 use crate::server::pipeline::languages::h5::H5FileHandler;
-use crate::server::pipeline::props::{PipelineRuntime, PipelineStageTask, PipelineStatus};
+use crate::server::pipeline::props::{PipelineApprovalResult, PipelineQueueItem, PipelineRecoveryPolicy, PipelineRuntime, PipelineRuntimeStage, PipelineStageTask, PipelineStatus};
+use crate::server::pipeline::runnable::approval::PipelineApproval;
+use crate::server::pipeline::runnable::matrix::PipelineMatrix;
+use crate::server::pipeline::runnable::notification::PipelineNotification;
 use crate::server::pipeline::runnable::stage::PipelineRunnableStage;
+use crate::server::pipeline::runnable::stats::PipelineStatistics;
 use crate::server::pipeline::runnable::{PipelineRunnable, PipelineRunnableQueryForm};
-use crate::{LOOP_SEC, MAX_THREAD_COUNT, POOLS};
-use futures::future::join_all;
+use crate::setting::Settings;
+use crate::{LOOP_SEC, MAX_THREAD_COUNT, POOLS, POOLS_NOTIFY, RUNNING_POOLS};
 use handlers::utils::Utils;
-use log::{error, info};
-use rayon::prelude::*;
+use log::{error, info, warn};
 use sqlx::MySql;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tauri::AppHandle;
 
+// 默认同一服务器下同时运行的流水线数量, 0 为不限制
+const DEFAULT_SERVER_CONCURRENCY: usize = 0;
+
 pub struct Pool;
 
 impl Pool {
-    /// 启动线程池
+    /// 启动线程池, 有新任务或任务结束时立即调度, 超时后兜底调度(如修改了同时运行数量)
     pub(crate) async fn start(app: &AppHandle) {
-        Self::exec_pool_tasks(app).await;
-        tokio::time::sleep(Duration::from_secs(LOOP_SEC)).await;
+        loop {
+            Self::exec_pool_tasks(app);
+            let _ = tokio::time::timeout(Duration::from_secs(LOOP_SEC), POOLS_NOTIFY.notified()).await;
+        }
     }
 
     /// 从数据库里读取 pools
-    pub(crate) async fn get_pools() {
+    pub(crate) async fn get_pools(app: &AppHandle) {
         info!("get pools list from database ...");
         let list = match Self::get_list().await {
             Ok(list) => list,
@@ -44,6 +54,11 @@ impl Pool {
         info!("get pools list count: {:#?}", list.len());
 
         for runtime in list.iter() {
+            // 矩阵构建的父运行记录不执行
+            if runtime.is_matrix {
+                continue;
+            }
+
             let mut pipeline = Pipeline::default();
             pipeline.id = runtime.pipeline_id.clone();
 
@@ -58,11 +73,64 @@ impl Pool {
 
             let pipe = pipeline_list.get(0).unwrap();
             let mut pip = pipe.clone();
-            pip.runtime = Some(runtime.clone());
+            let mut runtime = runtime.clone();
+            if !Self::recover(app, &pip, &mut runtime).await {
+                continue;
+            }
+
+            pip.runtime = Some(runtime);
             Self::insert_into_pool(&pip).unwrap_or(());
         }
     }
 
+    /// 恢复程序退出时运行中的记录, 记录中断日志后按流水线配置的策略处理, 返回是否需要放入线程池
+    async fn recover(app: &AppHandle, pipeline: &Pipeline, runtime: &mut PipelineRuntime) -> bool {
+        if !matches!(runtime.status, PipelineStatus::Process) {
+            return true;
+        }
+
+        let policy = pipeline.options.recovery_policy.clone();
+        let stage = &runtime.stage;
+        let msg = format!(
+            "run was interrupted at stage {}, group {}, step {} because the app exited, recovery policy: {:?}",
+            stage.stage_index, stage.group_index, stage.step_index, policy
+        );
+        warn!("runtime `{}` {}", runtime.id.clone().unwrap_or(String::new()), &msg);
+        PipelineLogger::save_log(&msg, &pipeline.server_id, &pipeline.id, runtime.order.unwrap_or(1), PipelineLogLevel::Warn, PipelineLogStream::System);
+
+        runtime.interrupted = true;
+        match policy {
+            PipelineRecoveryPolicy::Resume => runtime.status = PipelineStatus::Queue,
+            PipelineRecoveryPolicy::Restart => {
+                runtime.status = PipelineStatus::Queue;
+                runtime.stage = PipelineRuntimeStage { stage_index: 1, ..Default::default() };
+            }
+            PipelineRecoveryPolicy::Fail => runtime.status = PipelineStatus::Failed,
+        }
+
+        if let Err(err) = PipelineRunnable::update_stage(pipeline, runtime).await {
+            error!("update interrupted runtime stage error: {}", err);
+        }
+
+        let query = sqlx::query::<MySql>("UPDATE pipeline_runtime SET interrupted = 'true' WHERE id = ?").bind(&runtime.id);
+        if let Err(err) = DBHelper::execute_update(query).await {
+            error!("update runtime interrupted error: {}", err);
+        }
+
+        if !matches!(runtime.status, PipelineStatus::Failed) {
+            return true;
+        }
+
+        // 矩阵构建, 更新父运行记录状态
+        if let Some(parent_id) = &runtime.parent_id {
+            let mut pipe = pipeline.clone();
+            pipe.runtime = Some(runtime.clone());
+            PipelineMatrix::update_parent(app, &pipe, parent_id).await;
+        }
+
+        false
+    }
+
     /// 放入线程池
     pub(crate) fn insert_into_pool(pipeline: &Pipeline) -> Result<(), String> {
         info!("insert into pool: {:#?}", pipeline);
@@ -103,12 +171,50 @@ impl Pool {
 
         info!("pools task list: {:#?}", pools);
         info!("insert into pools success !");
+        POOLS_NOTIFY.notify_one();
         Ok(())
     }
 
-    /// 运行任务
-    pub(crate) async fn exec_pool_tasks(app: &AppHandle) {
-        info!("exec pipeline pools tasks ...");
+    /// 获取排队中的任务及预计等待时长
+    pub(crate) async fn get_queue() -> Result<HttpResponse, String> {
+        get_success_response_by_value(Self::get_queue_list().await)
+    }
+
+    /// 排队中的任务, 按调度顺序模拟每批执行的任务, 等待时长为前面每批任务中最长的预计耗时之和
+    pub(crate) async fn get_queue_list() -> Vec<PipelineQueueItem> {
+        let mut tasks: Vec<PipelineStageTask> = POOLS.lock().unwrap().clone();
+        let mut running: Vec<PipelineStageTask> = RUNNING_POOLS.lock().unwrap().clone();
+        let pipeline_ids: Vec<String> = tasks.iter().map(|task| task.pipeline.id.clone()).collect();
+        let estimates = PipelineStatistics::get_run_estimates(&pipeline_ids).await;
+
+        let worker_count = Self::get_worker_count();
+        let server_concurrency = Self::get_server_concurrency();
+        let mut list: Vec<PipelineQueueItem> = Vec::new();
+        let mut wait_ms = PipelineStatistics::get_max_running_eta();
+        while !tasks.is_empty() {
+            // 第一批需等待运行中的任务释放位置
+            let count = worker_count.saturating_sub(running.len()).max(1);
+            let chunk = Self::take_tasks(&mut tasks, &running, count, server_concurrency);
+            running.clear();
+            for task in chunk.iter() {
+                list.push(PipelineQueueItem {
+                    runtime_id: task.id.clone(),
+                    pipeline_id: task.pipeline.id.clone(),
+                    server_id: task.server_id.clone(),
+                    order: task.order,
+                    position: list.len() as u32 + 1,
+                    wait_ms,
+                });
+            }
+
+            wait_ms += chunk.iter().map(|task| estimates.get(&task.pipeline.id).cloned().unwrap_or(0)).max().unwrap_or(0);
+        }
+
+        list
+    }
+
+    /// 调度任务, 有空闲位置时立即运行, 每个任务结束后释放位置并重新调度
+    pub(crate) fn exec_pool_tasks(app: &AppHandle) {
         let tasks: Vec<PipelineStageTask> = {
             let mut pools = POOLS.lock().unwrap();
             if pools.is_empty() {
@@ -116,31 +222,90 @@ impl Pool {
                 return;
             }
 
-            // 取出 MAX_THREAD_COUNT 条数据
-            let pool_len = pools.len();
-            let len = pool_len.min(MAX_THREAD_COUNT as usize); // 取 pools 的长度和 5 的最小值
-            let tasks: Vec<PipelineStageTask> = pools.drain(0..len).collect();
-            info!("pipeline pools lave count: {}", pool_len - len);
+            let mut running = RUNNING_POOLS.lock().unwrap();
+            let worker_count = Self::get_worker_count();
+            if running.len() >= worker_count {
+                info!("pipeline pools running count: {}, no free worker !", running.len());
+                return;
+            }
+
+            // 按优先级取出可执行的任务
+            let tasks = Self::take_tasks(&mut pools, &running, worker_count - running.len(), Self::get_server_concurrency());
+            running.extend(tasks.iter().cloned());
+            info!("pipeline pools lave count: {}, running count: {}", pools.len(), running.len());
             tasks
         };
 
-        let installed_commands = H5FileHandler::get_installed_commands();
-        let app_cloned = Arc::new(app.clone());
-        let installed_commands_cloned = Arc::new(installed_commands.clone());
-        let futures: Vec<_> = tasks
-            .par_iter()
-            .map(|task| {
-                let app_clone = Arc::clone(&app_cloned);
-                let commands_clone = Arc::clone(&installed_commands_cloned);
-                async move {
-                    Self::exec_task(&*app_clone, &*commands_clone, task).await;
+        if tasks.is_empty() {
+            return;
+        }
+
+        let installed_commands = Arc::new(H5FileHandler::get_installed_commands());
+        for task in tasks.into_iter() {
+            let app = app.clone();
+            let installed_commands = Arc::clone(&installed_commands);
+            tauri::async_runtime::spawn(async move {
+                let id = task.id.clone();
+                let app_cloned = app.clone();
+                // 步骤中有大量阻塞调用(命令、git、ssh/sftp 上传), 放到阻塞线程中执行, 避免占用异步工作线程
+                let handle = tauri::async_runtime::spawn_blocking(move || {
+                    tauri::async_runtime::block_on(Self::exec_task(&app_cloned, &installed_commands, &task));
+                });
+
+                // 任务异常退出时同样需要释放位置
+                if let Err(err) = handle.await {
+                    error!("exec pipeline task `{}` error: {}", &id, err);
                 }
-            })
-            .collect();
 
-        // 并发地等待所有任务完成
-        join_all(futures).await;
-        info!("exec pipeline pools task end !");
+                RUNNING_POOLS.lock().unwrap().retain(|item| item.id != id);
+                POOLS_NOTIFY.notify_one();
+                info!("exec pipeline task `{}` end !", &id);
+            });
+        }
+    }
+
+    /// 取出最多 count 条可执行的任务, 优先级高的先执行, 相同优先级先进先出
+    /// 同一服务器下同时运行(包括 running 中)的流水线不超过 server_concurrency, 矩阵构建的子运行记录属于同一条流水线
+    fn take_tasks(pools: &mut Vec<PipelineStageTask>, running: &[PipelineStageTask], count: usize, server_concurrency: usize) -> Vec<PipelineStageTask> {
+        pools.sort_by(|a, b| b.runtime.priority.cmp(&a.runtime.priority));
+
+        let mut servers: HashMap<String, HashSet<String>> = HashMap::new();
+        for task in running.iter() {
+            servers.entry(task.server_id.clone()).or_default().insert(task.pipeline.id.clone());
+        }
+
+        let mut indexes: Vec<usize> = Vec::new();
+        for (index, task) in pools.iter().enumerate() {
+            if indexes.len() >= count {
+                break;
+            }
+
+            let pipelines = servers.entry(task.server_id.clone()).or_default();
+            if server_concurrency > 0 && !pipelines.contains(&task.pipeline.id) && pipelines.len() >= server_concurrency {
+                info!("server `{}` concurrency limit reached, pipeline `{}` will wait !", &task.server_id, &task.pipeline.id);
+                continue;
+            }
+
+            pipelines.insert(task.pipeline.id.clone());
+            indexes.push(index);
+        }
+
+        // 从后往前移除, 保持下标有效
+        let mut tasks: Vec<PipelineStageTask> = indexes.iter().rev().map(|index| pools.remove(*index)).collect();
+        tasks.reverse();
+        tasks
+    }
+
+    /// 同时运行的流水线数量
+    fn get_worker_count() -> usize {
+        let count = MAX_THREAD_COUNT as usize;
+        let count = Settings::get_cached_settings().max_worker_count.trim().parse::<usize>().unwrap_or(count);
+        count.max(1)
+    }
+
+    /// 同一服务器下同时运行的流水线数量, 默认不限制
+    fn get_server_concurrency() -> usize {
+        Settings::get_cached_settings().server_concurrency.trim().parse::<usize>().unwrap_or(DEFAULT_SERVER_CONCURRENCY)
     }
 
     /// 执行步骤
@@ -159,7 +324,15 @@ impl Pool {
 
         // 更改状态为 `执行中` 、运行开始时间、序号
         let status = PipelineStatus::Process;
-        let start_time = Utils::get_date(None);
+        let mut start_time = Utils::get_date(None);
+
+        // 审批通过后继续执行, 保留原开始时间, 耗时在审批前的基础上累加
+        let previous_duration_ms = PipelineApproval::get_previous_duration_ms(&task.runtime);
+        if matches!(task.runtime.approval.as_ref().map(|approval| &approval.result), Some(PipelineApprovalResult::Approved)) {
+            if let Some(time) = task.runtime.start_time.as_ref().filter(|time| !time.is_empty()) {
+                start_time = time.clone();
+            }
+        }
 
         // 1. 更新 pipeline 中状态为 Process
         // 2. 更新 pipeline_runtime 中的状态为 Process
@@ -202,6 +375,13 @@ impl Pool {
         let mut task = task.clone();
         let mut pipe = task.pipeline;
         let mut runtime = task.runtime;
+
+        // 回放时使用原运行记录的基本信息
+        if runtime.replay_id.is_some() {
+            if let Some(basic) = &runtime.basic {
+                pipe.basic = basic.clone();
+            }
+        }
         pipe.status = Some(status.clone());
         pipe.last_run_time = Some(start_time.clone());
         runtime.status = status.clone();
@@ -210,11 +390,17 @@ impl Pool {
         task.pipeline = pipe;
         task.runtime = runtime;
 
+        // 矩阵构建, 更新父运行记录状态
+        if let Some(parent_id) = &task.runtime.parent_id {
+            PipelineMatrix::update_parent(app, &task.pipeline, parent_id).await;
+        }
+
         // 执行 stages
         let mut pipe = PipelineRunnableStage::exec(app, &task, installed_commands).await;
         let mut runtime = pipe.clone().runtime.unwrap_or(PipelineRuntime::default());
-        let elapsed_now = format!("{:.2?}", start_now.elapsed());
-        runtime.duration = Some(elapsed_now);
+        let elapsed = start_now.elapsed() + Duration::from_millis(previous_duration_ms);
+        runtime.duration = Some(format!("{:.2?}", elapsed));
+        runtime.duration_ms = Some(elapsed.as_millis() as u64);
         pipe.runtime = Some(runtime.clone());
 
         info!("exec task duration: {:?}", runtime.duration);
@@ -229,6 +415,13 @@ impl Pool {
                 info!("update stage error: {} !", &err);
             }
         }
+
+        // 系统通知
+        PipelineNotification::notify(app, &pipe);
+
+        if let Some(parent_id) = &runtime.parent_id {
+            PipelineMatrix::update_parent(app, &pipe, parent_id).await;
+        }
     }
 
     /// 从 database 中读取任务列表
@@ -1,5 +1,7 @@
 //! 流水线属性
 
+use crate::helper::crypto::CryptoHandler;
+use crate::helper::git::GitCredential;
 use crate::server::pipeline::index::Pipeline;
 use serde::{Deserialize, Serialize};
 use sqlx::mysql::MySqlRow;
@@ -107,15 +109,18 @@ pub struct PipelineStepComponent {
 /// 流水线运行命令状态
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum PipelineCommandStatus {
-    None,      // 不运行
-    GitPull,   // 代码拉取
-    H5Install, // H5 安装依赖
-    Pack,      // 项目打包
-    Minimize,  // 文件压缩
-    Compress,  // 图片压缩
-    Deploy,    // 项目部署
-    Docker,    // Docker
-    Notice,    // 发送通知
+    None,       // 不运行
+    GitPull,    // 代码拉取
+    H5Install,  // H5 安装依赖
+    Pack,       // 项目打包
+    Minimize,   // 文件压缩
+    Compress,   // 图片压缩
+    Deploy,     // 项目部署
+    Docker,     // Docker
+    Notice,     // 发送通知
+    Approval,   // 人工审批
+    Kubernetes, // Kubernetes 部署
+    Compose,    // Docker Compose 部署
 }
 
 impl Default for PipelineCommandStatus {
@@ -162,6 +167,18 @@ impl PipelineCommandStatus {
             return PipelineCommandStatus::Notice;
         }
 
+        if status == "Approval" {
+            return PipelineCommandStatus::Approval;
+        }
+
+        if status == "Kubernetes" {
+            return PipelineCommandStatus::Kubernetes;
+        }
+
+        if status == "Compose" {
+            return PipelineCommandStatus::Compose;
+        }
+
         PipelineCommandStatus::None
     }
 
@@ -176,6 +193,9 @@ impl PipelineCommandStatus {
             PipelineCommandStatus::Deploy => "Deploy".to_string(),
             PipelineCommandStatus::Docker => "Docker".to_string(),
             PipelineCommandStatus::Notice => "Notice".to_string(),
+            PipelineCommandStatus::Approval => "Approval".to_string(),
+            PipelineCommandStatus::Kubernetes => "Kubernetes".to_string(),
+            PipelineCommandStatus::Compose => "Compose".to_string(),
         };
     }
 }
@@ -255,6 +275,159 @@ impl PipelineVariable {
     }
 }
 
+/// 流水线附加配置
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct PipelineOptions {
+    #[serde(default)]
+    pub(crate) downstream: Vec<PipelineDownstream>, // 下游流水线
+    #[serde(default)]
+    pub(crate) matrix: Vec<PipelineMatrixAxis>, // 矩阵构建
+    #[serde(default)]
+    pub(crate) credential: PipelineGitCredential, // 私有仓库凭证
+    #[serde(rename = "recoveryPolicy", default)]
+    pub(crate) recovery_policy: PipelineRecoveryPolicy, // 程序退出导致运行中断时的恢复策略
+    #[serde(rename = "emailNotice", default)]
+    pub(crate) email_notice: PipelineEmailNotice, // 运行结束邮件通知
+    #[serde(rename = "muteNotification", default)]
+    pub(crate) mute_notification: bool, // 运行结束时不发送系统通知
+}
+
+/// 运行结束邮件通知
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct PipelineEmailNotice {
+    #[serde(rename = "onSuccess", default)]
+    pub(crate) on_success: bool, // 运行成功时通知
+    #[serde(rename = "onFailure", default)]
+    pub(crate) on_failure: bool, // 运行失败时通知
+    #[serde(default)]
+    pub(crate) recipients: Vec<String>, // 收件人
+}
+
+/// 运行中断后的恢复策略
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum PipelineRecoveryPolicy {
+    Resume,  // 从中断的步骤继续运行
+    Restart, // 从头重新运行
+    Fail,    // 标记为运行失败
+}
+
+impl Default for PipelineRecoveryPolicy {
+    fn default() -> Self {
+        PipelineRecoveryPolicy::Resume
+    }
+}
+
+/// 私有仓库凭证, token 和 sshKey 加密保存
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub struct PipelineGitCredential {
+    #[serde(default)]
+    pub(crate) kind: PipelineGitCredentialKind,
+    #[serde(default)]
+    pub(crate) username: String, // HTTPS 用户名
+    #[serde(default)]
+    pub(crate) token: String, // HTTPS token
+    #[serde(rename = "sshKey", default)]
+    pub(crate) ssh_key: String, // SSH 部署私钥
+}
+
+impl std::fmt::Debug for PipelineGitCredential {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mask = |str: &str| if str.is_empty() { String::new() } else { String::from("******") };
+        f.debug_struct("PipelineGitCredential")
+            .field("kind", &self.kind)
+            .field("username", &self.username)
+            .field("token", &mask(&self.token))
+            .field("ssh_key", &mask(&self.ssh_key))
+            .finish()
+    }
+}
+
+impl PipelineGitCredential {
+    /// 加密 token 和 sshKey, 已加密的不再处理
+    pub(crate) fn encrypt(&self) -> Result<PipelineGitCredential, String> {
+        let mut credential = self.clone();
+        credential.token = CryptoHandler::encrypt(&self.token)?;
+        credential.ssh_key = CryptoHandler::encrypt(&self.ssh_key)?;
+        Ok(credential)
+    }
+
+    /// 根据凭证类型获取解密后的凭证
+    pub(crate) fn get_git_credential(&self) -> Result<GitCredential, String> {
+        return match self.kind {
+            PipelineGitCredentialKind::None => Ok(GitCredential::default()),
+            PipelineGitCredentialKind::Https => Ok(GitCredential {
+                username: self.username.clone(),
+                token: CryptoHandler::decrypt(&self.token)?,
+                ssh_key: String::new(),
+            }),
+            PipelineGitCredentialKind::Ssh => Ok(GitCredential {
+                username: String::new(),
+                token: String::new(),
+                ssh_key: CryptoHandler::decrypt(&self.ssh_key)?,
+            }),
+        };
+    }
+}
+
+/// 凭证类型
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum PipelineGitCredentialKind {
+    None,  // 无需凭证
+    Https, // HTTPS token
+    Ssh,   // SSH 部署私钥
+}
+
+impl Default for PipelineGitCredentialKind {
+    fn default() -> Self {
+        PipelineGitCredentialKind::None
+    }
+}
+
+/// 矩阵构建维度, 每个维度对应一个变量的多个取值
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct PipelineMatrixAxis {
+    pub(crate) name: String,        // 变量名
+    pub(crate) values: Vec<String>, // 变量取值
+}
+
+/// 下游流水线
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct PipelineDownstream {
+    #[serde(rename = "pipelineId")]
+    pub(crate) pipeline_id: String, // 下游流水线 ID
+    #[serde(rename = "serverId", default)]
+    pub(crate) server_id: String, // 下游流水线所在服务器, 为空时取当前服务器
+    #[serde(default)]
+    pub(crate) trigger: PipelineDownstreamTrigger, // 触发条件
+    #[serde(default)]
+    pub(crate) variables: Vec<String>, // 传递给下游的变量名
+}
+
+/// 下游流水线触发条件
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum PipelineDownstreamTrigger {
+    Success, // 运行成功
+    Failed,  // 运行失败
+    Always,  // 总是触发
+}
+
+impl Default for PipelineDownstreamTrigger {
+    fn default() -> Self {
+        PipelineDownstreamTrigger::Success
+    }
+}
+
+impl PipelineDownstreamTrigger {
+    /// 根据运行状态判断是否需要触发
+    pub fn matched(trigger: &PipelineDownstreamTrigger, status: &PipelineStatus) -> bool {
+        return match trigger {
+            PipelineDownstreamTrigger::Success => matches!(status, PipelineStatus::Success),
+            PipelineDownstreamTrigger::Failed => matches!(status, PipelineStatus::Failed),
+            PipelineDownstreamTrigger::Always => matches!(status, PipelineStatus::Success | PipelineStatus::Failed),
+        };
+    }
+}
+
 /// 附加的变量
 #[derive(Default, Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct RunnableVariable {
@@ -309,6 +482,7 @@ pub enum PipelineStatus {
     Success, // 运行成功
     Failed,  // 运行失败
     Stop,    // 中止运行
+    Waiting, // 等待审批
 }
 
 impl Default for PipelineStatus {
@@ -343,6 +517,10 @@ impl PipelineStatus {
             return PipelineStatus::Stop;
         }
 
+        if status == "Waiting" {
+            return PipelineStatus::Waiting;
+        }
+
         PipelineStatus::No
     }
 
@@ -354,6 +532,7 @@ impl PipelineStatus {
             PipelineStatus::Success => "Success".to_string(),
             PipelineStatus::Failed => "Failed".to_string(),
             PipelineStatus::Stop => "Stop".to_string(),
+            PipelineStatus::Waiting => "Waiting".to_string(),
         };
     }
 }
@@ -388,15 +567,161 @@ pub struct PipelineRuntime {
     #[serde(rename = "startTime")]
     pub(crate) start_time: Option<String>, // 开始时间
     pub(crate) duration: Option<String>,     // 运行时长, 单位秒
+    #[serde(rename = "durationMs", default)]
+    pub(crate) duration_ms: Option<u64>, // 运行时长, 单位毫秒
     pub(crate) snapshot: PipelineRuntimeSnapshot, // 运行时快照
     pub(crate) log: Option<String>,          // 日志, 根据 {server_id/id/order}.log 来读取
     pub(crate) remark: String,               //  运行备注
+    #[serde(default)]
+    pub(crate) trigger: Option<PipelineRuntimeLink>, // 触发本次运行的上游
+    #[serde(default)]
+    pub(crate) downstream: Vec<PipelineRuntimeLink>, // 本次运行触发的下游
+    #[serde(rename = "parentId", default)]
+    pub(crate) parent_id: Option<String>, // 矩阵构建的父运行记录 ID
+    #[serde(default)]
+    pub(crate) matrix: Option<String>, // 矩阵构建的变量组合, 如 node=18, env=test
+    #[serde(rename = "isMatrix", default)]
+    pub(crate) is_matrix: bool, // 是否为矩阵构建的父运行记录
+    #[serde(default)]
+    pub(crate) priority: i32, // 运行优先级, 越大越先执行, 如紧急修复发布可插队
+    #[serde(default)]
+    pub(crate) interrupted: bool, // 是否因程序退出而中断过
+    #[serde(rename = "replayId", default)]
+    pub(crate) replay_id: Option<String>, // 回放的运行记录 ID
+    #[serde(default)]
+    pub(crate) approval: Option<PipelineRuntimeApproval>, // 人工审批
+    #[serde(default)]
+    pub(crate) children: Vec<PipelineRuntime>, // 矩阵构建的子运行记录
+    #[serde(default)]
+    pub(crate) commit: PipelineRuntimeCommit, // 本次运行构建的提交
+    #[serde(default)]
+    pub(crate) progress: Option<PipelineRuntimeProgress>, // 运行进度及预计剩余时间
     #[serde(rename = "createTime")]
     pub(crate) create_time: Option<String>, // 创建时间
     #[serde(rename = "updateTime")]
     pub(crate) update_time: Option<String>, // 修改时间
 }
 
+/// 人工审批
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct PipelineRuntimeApproval {
+    pub(crate) result: PipelineApprovalResult, // 审批结果
+    pub(crate) approver: String,               // 审批人
+    pub(crate) comment: String,                // 审批意见
+    pub(crate) deadline: u64,                  // 审批截止时间, 单位毫秒, 0 为不超时
+    pub(crate) time: String,                   // 审批时间
+}
+
+impl PipelineRuntimeApproval {
+    /// 从查询结果中读取, prefix 为字段前缀, 未进入过审批时为空
+    pub(crate) fn from_row(row: &MySqlRow, prefix: &str) -> Option<Self> {
+        let result: String = row.try_get(format!("{}approval_result", prefix).as_str()).unwrap_or(None).unwrap_or(String::new());
+        if result.is_empty() {
+            return None;
+        }
+
+        Some(PipelineRuntimeApproval {
+            result: PipelineApprovalResult::get(&result),
+            approver: row.try_get(format!("{}approver", prefix).as_str()).unwrap_or(None).unwrap_or(String::new()),
+            comment: row.try_get(format!("{}approval_comment", prefix).as_str()).unwrap_or(None).unwrap_or(String::new()),
+            deadline: row.try_get::<Option<i64>, _>(format!("{}approval_deadline", prefix).as_str()).unwrap_or(None).unwrap_or(0) as u64,
+            time: row.try_get(format!("{}approval_time", prefix).as_str()).unwrap_or(None).unwrap_or(String::new()),
+        })
+    }
+}
+
+/// 审批结果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum PipelineApprovalResult {
+    Waiting,  // 等待审批
+    Approved, // 通过
+    Rejected, // 拒绝
+    Timeout,  // 超时
+}
+
+impl Default for PipelineApprovalResult {
+    fn default() -> Self {
+        PipelineApprovalResult::Waiting
+    }
+}
+
+impl PipelineApprovalResult {
+    pub fn get(result: &str) -> PipelineApprovalResult {
+        if result == "Approved" {
+            return PipelineApprovalResult::Approved;
+        }
+
+        if result == "Rejected" {
+            return PipelineApprovalResult::Rejected;
+        }
+
+        if result == "Timeout" {
+            return PipelineApprovalResult::Timeout;
+        }
+
+        PipelineApprovalResult::Waiting
+    }
+
+    pub fn got(result: PipelineApprovalResult) -> String {
+        return match result {
+            PipelineApprovalResult::Waiting => "Waiting".to_string(),
+            PipelineApprovalResult::Approved => "Approved".to_string(),
+            PipelineApprovalResult::Rejected => "Rejected".to_string(),
+            PipelineApprovalResult::Timeout => "Timeout".to_string(),
+        };
+    }
+}
+
+/// 运行进度, 根据历史步骤耗时估算, 时间单位毫秒
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct PipelineRuntimeProgress {
+    pub(crate) percent: u32, // 进度百分比, 0 ~ 100
+    #[serde(rename = "elapsedMs")]
+    pub(crate) elapsed_ms: u64, // 已运行时长
+    #[serde(rename = "etaMs")]
+    pub(crate) eta_ms: u64, // 预计剩余时长
+    #[serde(rename = "queuePosition", default)]
+    pub(crate) queue_position: Option<u32>, // 排队位置, 从 1 开始
+}
+
+/// 排队中的任务
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct PipelineQueueItem {
+    #[serde(rename = "runtimeId")]
+    pub(crate) runtime_id: String,
+    #[serde(rename = "pipelineId")]
+    pub(crate) pipeline_id: String,
+    #[serde(rename = "serverId")]
+    pub(crate) server_id: String,
+    pub(crate) order: u32,
+    pub(crate) position: u32, // 排队位置, 从 1 开始
+    #[serde(rename = "waitMs")]
+    pub(crate) wait_ms: u64, // 预计等待时长, 单位毫秒
+}
+
+/// 流水线运行构建的提交
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct PipelineRuntimeCommit {
+    pub(crate) sha: String,     // commit SHA
+    pub(crate) author: String,  // 提交人
+    pub(crate) message: String, // 提交信息
+}
+
+/// 流水线运行链路
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct PipelineRuntimeLink {
+    #[serde(rename = "runtimeId")]
+    pub(crate) runtime_id: String,
+    #[serde(rename = "pipelineId")]
+    pub(crate) pipeline_id: String,
+    #[serde(rename = "pipelineName", default)]
+    pub(crate) pipeline_name: String,
+    #[serde(default)]
+    pub(crate) order: Option<u32>,
+    #[serde(default)]
+    pub(crate) status: PipelineStatus,
+}
+
 impl<'r> FromRow<'r, MySqlRow> for PipelineRuntime {
     fn from_row(row: &MySqlRow) -> Result<Self, sqlx::Error> {
         let tag_str: String = row.try_get("tag")?;
@@ -415,9 +740,22 @@ impl<'r> FromRow<'r, MySqlRow> for PipelineRuntime {
             status: PipelineStatus::get(&status_str),
             start_time: row.try_get("start_time")?,
             duration: row.try_get("duration")?,
+            duration_ms: row.try_get::<Option<i64>, _>("duration_ms").unwrap_or(None).map(|duration_ms| duration_ms as u64),
             snapshot: Default::default(),
             log: None,
             remark: row.try_get("remark")?,
+            trigger: None,
+            downstream: Vec::new(),
+            parent_id: None,
+            matrix: None,
+            is_matrix: false,
+            priority: row.try_get::<Option<i32>, _>("priority").unwrap_or(None).unwrap_or(0),
+            interrupted: row.try_get::<Option<String>, _>("interrupted").unwrap_or(None).unwrap_or(String::new()).trim() == "true",
+            replay_id: row.try_get("replay_runtime_id").unwrap_or(None),
+            approval: PipelineRuntimeApproval::from_row(row, ""),
+            children: Vec::new(),
+            commit: Default::default(),
+            progress: None,
             create_time: row.try_get("create_time")?,
             update_time: row.try_get("update_time")?,
         })
@@ -444,6 +782,8 @@ pub struct PipelineRuntimeSnapshot {
     pub(crate) runtime_id: String, // 运行记录 ID,
     pub(crate) node: String,       // nodeJs 版本号
     pub(crate) branch: String,     // 分支
+    #[serde(default)]
+    pub(crate) revision: String, // tag 或 commit SHA, 为空时使用分支最新提交
     pub(crate) make: Option<String>, // Make 命令
     pub(crate) command: String,    // 本机安装的命令
     pub(crate) script: String,     // package.json 中的 scripts 命令
@@ -601,3 +941,193 @@ pub struct PipelineRunnableStageStep {
     pub(crate) step_index: u32,
     pub(crate) step: PipelineStep,
 }
+
+/// 流水线工作空间磁盘占用, 单位字节
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct PipelineWorkspaceUsage {
+    #[serde(rename = "serverId")]
+    pub(crate) server_id: String,
+    pub(crate) id: String,
+    pub(crate) name: String,
+    pub(crate) status: String,
+    pub(crate) workspace: u64, // 代码目录
+    pub(crate) log: u64,       // 日志目录
+    pub(crate) cache: u64,     // 依赖缓存目录
+    pub(crate) total: u64,
+    #[serde(rename = "lastUsedTime")]
+    pub(crate) last_used_time: u64, // 最后使用时间, 秒
+    pub(crate) orphan: bool, // 流水线已删除, 目录残留
+}
+
+/// 工作空间磁盘占用统计
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct PipelineWorkspaceReport {
+    pub(crate) list: Vec<PipelineWorkspaceUsage>,
+    pub(crate) total: u64, // 总占用, 单位字节
+    pub(crate) quota: u64, // 配额, 单位字节, 0 为不限制
+}
+
+/// 运行统计查询条件
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct PipelineStatsQueryForm {
+    #[serde(rename = "serverId")]
+    pub(crate) server_id: String,
+    #[serde(default)]
+    pub(crate) id: String, // 为空时统计服务器下所有流水线
+    #[serde(rename = "startDate", default)]
+    pub(crate) start_date: String, // 开始日期, 如 2024-01-01
+    #[serde(rename = "endDate", default)]
+    pub(crate) end_date: String, // 结束日期, 如 2024-01-31
+}
+
+/// 流水线运行统计, 时长单位毫秒
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct PipelineRunStats {
+    pub(crate) id: String,
+    pub(crate) name: String,
+    pub(crate) total: u32,
+    pub(crate) success: u32,
+    pub(crate) failed: u32,
+    pub(crate) stop: u32,
+    #[serde(rename = "successRate")]
+    pub(crate) success_rate: f64, // 成功率, 0 ~ 1
+    #[serde(rename = "avgDuration")]
+    pub(crate) avg_duration: u64,
+    #[serde(rename = "p95Duration")]
+    pub(crate) p95_duration: u64,
+    #[serde(rename = "slowestSteps")]
+    pub(crate) slowest_steps: Vec<PipelineStepStats>, // 平均耗时最长的步骤
+    pub(crate) trend: Vec<PipelineDailyStats>, // 每日趋势
+}
+
+/// 步骤运行统计
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct PipelineStepStats {
+    #[serde(rename = "stageIndex")]
+    pub(crate) stage_index: u32,
+    #[serde(rename = "groupIndex")]
+    pub(crate) group_index: u32,
+    #[serde(rename = "stepIndex")]
+    pub(crate) step_index: u32,
+    pub(crate) module: String,
+    pub(crate) label: String,
+    pub(crate) total: u32,
+    pub(crate) failed: u32,
+    #[serde(rename = "avgDuration")]
+    pub(crate) avg_duration: u64,
+    #[serde(rename = "maxDuration")]
+    pub(crate) max_duration: u64,
+}
+
+/// 每日运行统计
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct PipelineDailyStats {
+    pub(crate) date: String,
+    pub(crate) total: u32,
+    pub(crate) success: u32,
+    pub(crate) failed: u32,
+    #[serde(rename = "avgDuration")]
+    pub(crate) avg_duration: u64,
+}
+
+/// 运行记录对比条件
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct PipelineRunDiffForm {
+    #[serde(rename = "serverId")]
+    pub(crate) server_id: String,
+    pub(crate) id: String, // 流水线 ID
+    #[serde(rename = "baseRuntimeId")]
+    pub(crate) base_runtime_id: String, // 基准运行记录, 如 #41
+    #[serde(rename = "targetRuntimeId")]
+    pub(crate) target_runtime_id: String, // 对比运行记录, 如 #42
+    #[serde(rename = "contextLines", default)]
+    pub(crate) context_lines: Option<usize>, // 日志差异上下文行数
+}
+
+/// 两次运行记录的差异, 只返回有变化的字段
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct PipelineRunDiff {
+    #[serde(rename = "baseOrder")]
+    pub(crate) base_order: u32,
+    #[serde(rename = "targetOrder")]
+    pub(crate) target_order: u32,
+    pub(crate) snapshot: Vec<PipelineRunDiffField>,  // 快照: branch, node, make, command, script 等
+    pub(crate) variables: Vec<PipelineRunDiffField>, // 启动变量
+    pub(crate) stages: Vec<PipelineRunDiffField>,    // 构建过程中的步骤定义
+    pub(crate) commit: Vec<PipelineRunDiffField>,    // 构建的提交
+    pub(crate) steps: Vec<PipelineRunDiffStep>,      // 步骤耗时, 返回所有步骤
+    pub(crate) log: Vec<PipelineRunDiffHunk>,        // 日志差异
+}
+
+/// 字段差异, 不存在时为空
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct PipelineRunDiffField {
+    pub(crate) name: String,
+    pub(crate) base: Option<String>,
+    pub(crate) target: Option<String>,
+}
+
+/// 步骤耗时差异, 单位毫秒
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct PipelineRunDiffStep {
+    #[serde(rename = "stageIndex")]
+    pub(crate) stage_index: u32,
+    #[serde(rename = "groupIndex")]
+    pub(crate) group_index: u32,
+    #[serde(rename = "stepIndex")]
+    pub(crate) step_index: u32,
+    pub(crate) label: String,
+    #[serde(rename = "baseStatus")]
+    pub(crate) base_status: Option<String>,
+    #[serde(rename = "targetStatus")]
+    pub(crate) target_status: Option<String>,
+    #[serde(rename = "baseDuration")]
+    pub(crate) base_duration: Option<u64>,
+    #[serde(rename = "targetDuration")]
+    pub(crate) target_duration: Option<u64>,
+    pub(crate) delta: Option<i64>, // 对比运行减去基准运行
+}
+
+/// 日志差异片段
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct PipelineRunDiffHunk {
+    pub(crate) lines: Vec<PipelineRunDiffLine>,
+}
+
+/// 日志差异行
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct PipelineRunDiffLine {
+    pub(crate) tag: String, // equal, delete, insert
+    #[serde(rename = "baseLine")]
+    pub(crate) base_line: Option<usize>, // 基准日志行号, 从 1 开始
+    #[serde(rename = "targetLine")]
+    pub(crate) target_line: Option<usize>, // 对比日志行号, 从 1 开始
+    pub(crate) content: String,
+}
+
+/// 回放运行记录
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct PipelineReplayForm {
+    #[serde(rename = "serverId")]
+    pub(crate) server_id: String,
+    pub(crate) id: String, // 流水线 ID
+    #[serde(rename = "runtimeId")]
+    pub(crate) runtime_id: String, // 回放的运行记录 ID
+    #[serde(default)]
+    pub(crate) remark: String, // 运行备注, 为空时使用 `回放 #order`
+    #[serde(default)]
+    pub(crate) priority: i32, // 运行优先级
+}
+
+/// 审批运行记录
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct PipelineApprovalForm {
+    #[serde(rename = "serverId")]
+    pub(crate) server_id: String,
+    pub(crate) id: String, // 流水线 ID
+    #[serde(rename = "runtimeId")]
+    pub(crate) runtime_id: String,
+    pub(crate) approver: String, // 审批人
+    #[serde(default)]
+    pub(crate) comment: String, // 审批意见
+}
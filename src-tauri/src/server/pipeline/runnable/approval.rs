@@ -0,0 +1,283 @@
+//! 人工审批, 运行到审批步骤时暂停并释放线程池位置, 审批通过后从审批步骤的下一步继续运行
+
+use crate::database::helper::DBHelper;
+use crate::event::EventEmitter;
+use crate::logger::pipeline::{PipelineLogLevel, PipelineLogStream, PipelineLogger};
+use crate::prepare::{get_error_response, get_success_response_by_value, HttpResponse};
+use crate::server::pipeline::index::Pipeline;
+use crate::server::pipeline::pool::Pool;
+use crate::server::pipeline::props::{PipelineApprovalForm, PipelineApprovalResult, PipelineRunnableStageStep, PipelineRuntime, PipelineRuntimeApproval, PipelineStatus};
+use crate::server::pipeline::runnable::matrix::PipelineMatrix;
+use crate::server::pipeline::runnable::stage::PipelineRunnableResult;
+use crate::server::pipeline::runnable::{PipelineRunnable, PipelineRunnableQueryForm};
+use handlers::utils::Utils;
+use lazy_static::lazy_static;
+use log::{error, info};
+use sqlx::{MySql, Row};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::AppHandle;
+
+// 默认审批超时时间, 单位分钟, 审批步骤可通过 `timeout` 配置, 0 为不超时
+const DEFAULT_APPROVAL_TIMEOUT_MINUTES: u64 = 24 * 60;
+
+// 检查审批超时间隔, 单位秒
+pub(crate) const APPROVAL_CHECK_SECONDS: u64 = 60;
+
+// 超时自动拒绝时的审批人
+const TIMEOUT_APPROVER: &str = "system";
+
+// 审批与超时检查同时处理同一条运行记录时加锁
+lazy_static! {
+    static ref APPROVAL_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::new(());
+}
+
+pub struct PipelineApproval;
+
+impl PipelineApproval {
+    /// 审批步骤是否已通过, 审批通过后重新运行时审批步骤为第一个步骤
+    pub(crate) fn is_approved(pipeline: &Pipeline, index: usize) -> bool {
+        if index > 0 {
+            return false;
+        }
+
+        let approval = pipeline.runtime.as_ref().and_then(|runtime| runtime.approval.clone());
+        matches!(approval.map(|approval| approval.result), Some(PipelineApprovalResult::Approved))
+    }
+
+    /// 审批通过后继续运行时, 审批前已运行的时长, 单位毫秒, 未审批或未开始运行时为 0
+    pub(crate) fn get_previous_duration_ms(runtime: &PipelineRuntime) -> u64 {
+        let approved = matches!(runtime.approval.as_ref().map(|approval| &approval.result), Some(PipelineApprovalResult::Approved));
+        let started = runtime.start_time.as_ref().map(|time| !time.is_empty()).unwrap_or(false);
+        if approved && started {
+            return runtime.duration_ms.unwrap_or(0);
+        }
+
+        0
+    }
+
+    /// 进入等待审批状态, 记录截止时间并通知前端
+    pub(crate) async fn wait(app: &AppHandle, pipeline: &Pipeline, stage_step: &PipelineRunnableStageStep) -> Pipeline {
+        let step = &stage_step.step;
+        let mut pipe = pipeline.clone();
+        let mut runtime = pipe.runtime.clone().unwrap_or(PipelineRuntime::default());
+        let order = runtime.order.unwrap_or(1);
+
+        let timeout = step
+            .components
+            .iter()
+            .find(|com| com.prop.as_str() == "timeout")
+            .and_then(|com| com.value.trim().parse::<u64>().ok())
+            .unwrap_or(DEFAULT_APPROVAL_TIMEOUT_MINUTES);
+
+        let approval = PipelineRuntimeApproval {
+            result: PipelineApprovalResult::Waiting,
+            deadline: if timeout > 0 { Self::get_now() + timeout * 60 * 1000 } else { 0 },
+            ..Default::default()
+        };
+
+        runtime.status = PipelineStatus::Waiting;
+        runtime.stage.stage_index = stage_step.stage_index;
+        runtime.stage.group_index = stage_step.group_index;
+        runtime.stage.step_index = stage_step.step_index;
+        runtime.approval = Some(approval.clone());
+        pipe.status = Some(PipelineStatus::Waiting);
+        pipe.runtime = Some(runtime.clone());
+
+        let msg = if timeout > 0 {
+            format!("step 【{}】 waiting for approval, timeout after {} minutes ...", &step.label, timeout)
+        } else {
+            format!("step 【{}】 waiting for approval ...", &step.label)
+        };
+        PipelineRunnable::save_log(app, &msg, &pipe.server_id, &pipe.id, order);
+
+        if let Err(err) = Self::save_approval(&runtime, &approval).await {
+            error!("save pipeline approval error: {}", err);
+        }
+
+        // 通知前端有待审批的运行
+        EventEmitter::log_step_approval(app, get_success_response_by_value(pipe.clone()).ok());
+        pipe
+    }
+
+    /// 审批步骤, 已审批通过时记录日志后继续
+    pub(crate) async fn exec_step(app: &AppHandle, pipeline: &Pipeline, stage_step: &PipelineRunnableStageStep) -> Result<PipelineRunnableResult, String> {
+        let runtime = pipeline.runtime.clone().unwrap_or(PipelineRuntime::default());
+        let approval = runtime.approval.clone().unwrap_or(PipelineRuntimeApproval::default());
+        let msg = format!("step 【{}】 approved by {}, comment: {}", &stage_step.step.label, &approval.approver, &approval.comment);
+        PipelineRunnable::save_log(app, &msg, &pipeline.server_id, &pipeline.id, runtime.order.unwrap_or(1));
+        Ok(PipelineRunnableResult {
+            success: true,
+            msg,
+            pipeline: Some(pipeline.clone()),
+        })
+    }
+
+    /// 审批通过, 重新放入线程池
+    pub(crate) async fn approve(app: &AppHandle, form: &PipelineApprovalForm) -> Result<HttpResponse, String> {
+        let _lock = APPROVAL_LOCK.lock().await;
+        let (mut pipe, mut runtime) = match Self::get_waiting(form).await? {
+            Ok(result) => result,
+            Err(response) => return Ok(response),
+        };
+
+        let approval = Self::get_approval(&runtime, PipelineApprovalResult::Approved, &form.approver, &form.comment);
+        Self::save_approval(&runtime, &approval).await?;
+
+        runtime.status = PipelineStatus::Queue;
+        runtime.approval = Some(approval);
+        pipe.status = Some(PipelineStatus::Queue);
+        pipe.runtime = Some(runtime.clone());
+        PipelineRunnable::update_stage(&pipe, &runtime).await?;
+
+        let msg = format!("approval passed by {}, comment: {}", &form.approver, &form.comment);
+        PipelineRunnable::save_log(app, &msg, &pipe.server_id, &pipe.id, runtime.order.unwrap_or(1));
+        EventEmitter::log_step_res(app, get_success_response_by_value(pipe.clone()).ok());
+        if let Some(parent_id) = &runtime.parent_id {
+            PipelineMatrix::update_parent(app, &pipe, parent_id).await;
+        }
+
+        Pool::insert_into_pool(&pipe)?;
+        get_success_response_by_value(pipe)
+    }
+
+    /// 审批拒绝, 运行失败
+    pub(crate) async fn reject(app: &AppHandle, form: &PipelineApprovalForm) -> Result<HttpResponse, String> {
+        let _lock = APPROVAL_LOCK.lock().await;
+        let (pipe, runtime) = match Self::get_waiting(form).await? {
+            Ok(result) => result,
+            Err(response) => return Ok(response),
+        };
+
+        let pipe = Self::fail(app, pipe, runtime, PipelineApprovalResult::Rejected, &form.approver, &form.comment).await?;
+        get_success_response_by_value(pipe)
+    }
+
+    /// 审批超时, 自动拒绝
+    pub(crate) async fn check_timeout(app: &AppHandle) {
+        let _lock = APPROVAL_LOCK.lock().await;
+        let query = sqlx::query::<MySql>("SELECT id, pipeline_id FROM pipeline_runtime WHERE `status` = ? AND approval_deadline > 0 AND approval_deadline < ?")
+            .bind(PipelineStatus::got(PipelineStatus::Waiting))
+            .bind(Self::get_now() as i64);
+
+        let rows = match DBHelper::execute_rows(query).await {
+            Ok(rows) => rows,
+            Err(err) => {
+                error!("query approval timeout runtimes error: {}", err);
+                return;
+            }
+        };
+
+        for row in rows.iter() {
+            let runtime_id: String = row.try_get("id").unwrap_or(String::new());
+            let mut pipeline = Pipeline::default();
+            pipeline.id = row.try_get("pipeline_id").unwrap_or(String::new());
+
+            let result = match Self::get_pipeline(&pipeline, &runtime_id).await {
+                Ok(result) => result,
+                Err(err) => {
+                    error!("get approval timeout runtime {} error: {}", &runtime_id, err);
+                    continue;
+                }
+            };
+
+            if let Some((pipe, runtime)) = result {
+                info!("pipeline runtime {} approval timeout !", &runtime_id);
+                if let Err(err) = Self::fail(app, pipe, runtime, PipelineApprovalResult::Timeout, TIMEOUT_APPROVER, "approval timeout").await {
+                    error!("fail approval timeout runtime {} error: {}", &runtime_id, err);
+                }
+            }
+        }
+    }
+
+    /// 审批失败, 更新运行状态并结束
+    async fn fail(app: &AppHandle, mut pipe: Pipeline, mut runtime: PipelineRuntime, result: PipelineApprovalResult, approver: &str, comment: &str) -> Result<Pipeline, String> {
+        let approval = Self::get_approval(&runtime, result.clone(), approver, comment);
+        Self::save_approval(&runtime, &approval).await?;
+
+        runtime.status = PipelineStatus::Failed;
+        runtime.approval = Some(approval);
+        pipe.status = Some(PipelineStatus::Failed);
+        pipe.runtime = Some(runtime.clone());
+
+        let order = runtime.order.unwrap_or(1);
+        let msg = format!("approval {} by {}, comment: {}", PipelineApprovalResult::got(result).to_lowercase(), approver, comment);
+        PipelineLogger::save_log(&msg, &pipe.server_id, &pipe.id, order, PipelineLogLevel::Warn, PipelineLogStream::System);
+        PipelineRunnable::exec_end_log(app, &pipe, false, "exec task").await;
+        if let Some(parent_id) = &runtime.parent_id {
+            PipelineMatrix::update_parent(app, &pipe, parent_id).await;
+        }
+
+        Ok(pipe)
+    }
+
+    /// 查询等待审批的运行记录
+    async fn get_waiting(form: &PipelineApprovalForm) -> Result<Result<(Pipeline, PipelineRuntime), HttpResponse>, String> {
+        if form.server_id.is_empty() || form.id.is_empty() || form.runtime_id.is_empty() {
+            return Ok(Err(get_error_response("审批失败, `serverId`、`id` 或 `runtimeId` 不能为空")));
+        }
+
+        if form.approver.trim().is_empty() {
+            return Ok(Err(get_error_response("审批失败, `approver` 不能为空")));
+        }
+
+        let mut pipeline = Pipeline::default();
+        pipeline.id = form.id.clone();
+        pipeline.server_id = form.server_id.clone();
+
+        match Self::get_pipeline(&pipeline, &form.runtime_id).await? {
+            Some(result) => Ok(Ok(result)),
+            None => Ok(Err(get_error_response("审批失败, 该运行记录不存在或不在等待审批状态"))),
+        }
+    }
+
+    /// 查询流水线及等待审批的运行记录
+    async fn get_pipeline(pipeline: &Pipeline, runtime_id: &str) -> Result<Option<(Pipeline, PipelineRuntime)>, String> {
+        let pipeline_list = Pipeline::get_pipeline_list(pipeline, None, false).await?;
+        let pipe = match pipeline_list.get(0) {
+            Some(pipe) => pipe.clone(),
+            None => return Ok(None),
+        };
+
+        let result = PipelineRunnable::get_runtime_detail(
+            &pipe,
+            true,
+            Some(PipelineRunnableQueryForm {
+                status_list: vec![PipelineStatus::got(PipelineStatus::Waiting)],
+                runtime_id: Some(runtime_id.to_string()),
+                need_condition_last_run_id: None,
+            }),
+        )
+        .await?;
+
+        Ok(result.runtime.map(|runtime| (pipe, runtime)))
+    }
+
+    fn get_approval(runtime: &PipelineRuntime, result: PipelineApprovalResult, approver: &str, comment: &str) -> PipelineRuntimeApproval {
+        let mut approval = runtime.approval.clone().unwrap_or(PipelineRuntimeApproval::default());
+        approval.result = result;
+        approval.approver = approver.trim().to_string();
+        approval.comment = comment.to_string();
+        approval.time = Utils::get_date(None);
+        approval
+    }
+
+    /// 保存审批信息
+    async fn save_approval(runtime: &PipelineRuntime, approval: &PipelineRuntimeApproval) -> Result<HttpResponse, String> {
+        let query = sqlx::query::<MySql>(
+            r#"
+            UPDATE pipeline_runtime SET approval_result = ?, approver = ?, approval_comment = ?, approval_deadline = ?, approval_time = ? WHERE id = ?
+        "#,
+        )
+        .bind(PipelineApprovalResult::got(approval.result.clone()))
+        .bind(&approval.approver)
+        .bind(&approval.comment)
+        .bind(approval.deadline as i64)
+        .bind(&approval.time)
+        .bind(&runtime.id);
+        DBHelper::execute_update(query).await
+    }
+
+    fn get_now() -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_millis() as u64).unwrap_or(0)
+    }
+}
@@ -0,0 +1,269 @@
+//! 依赖缓存, 根据 lock 文件的 hash 缓存 node_modules
+
+use crate::error::Error;
+use crate::helper::index::Helper;
+use crate::prepare::{get_success_response, HttpResponse};
+use crate::setting::Settings;
+use log::{error, info};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use uuid::Uuid;
+
+// 缓存目录
+const CACHE_DIR_NAME: &str = "cache";
+
+// 依赖目录
+const NODE_MODULES_DIR_NAME: &str = "node_modules";
+
+// 记录 node_modules 对应的缓存 key
+const CACHE_KEY_FILE: &str = ".n-nacos-cache";
+
+// 记录缓存大小, 修改时间即最后使用时间
+const CACHE_SIZE_FILE_SUFFIX: &str = ".size";
+
+// 默认缓存总大小, 单位 MB
+const DEFAULT_CACHE_SIZE: u64 = 2048;
+
+// 每条流水线最多保留的缓存数量
+const MAX_CACHE_COUNT: usize = 3;
+
+pub struct PipelineCache;
+
+impl PipelineCache {
+    /// 根据 lock 文件内容、安装命令及 node 版本生成缓存 key
+    pub(crate) fn get_key(lock_file: &Path, command: &str, node: &str) -> Option<String> {
+        let content = match fs::read(lock_file) {
+            Ok(content) => content,
+            Err(err) => {
+                error!("read lock file {:#?} error: {:#?}", lock_file, err);
+                return None;
+            }
+        };
+
+        let mut data = content;
+        data.extend(format!("\n{}\n{}\n{}", command, node, std::env::consts::OS).as_bytes());
+        Some(format!("{:x}", md5::compute(data)))
+    }
+
+    /// 安装前恢复缓存, 返回是否命中
+    pub(crate) fn restore(server_id: &str, id: &str, key: &str, project_path: &Path) -> Result<bool, String> {
+        let node_modules = project_path.join(NODE_MODULES_DIR_NAME);
+
+        // node_modules 已是当前 key 对应的依赖
+        let key_file = node_modules.join(CACHE_KEY_FILE);
+        if key_file.exists() && fs::read_to_string(&key_file).unwrap_or(String::new()).trim() == key {
+            info!("node_modules already matched cache key: {}", key);
+            return Ok(true);
+        }
+
+        let cache_dir = Self::get_pipeline_cache_dir(server_id, id)?.join(key);
+        if !cache_dir.exists() {
+            return Ok(false);
+        }
+
+        if node_modules.exists() {
+            fs::remove_dir_all(&node_modules).map_err(|err| Error::Error(format!("remove node_modules error: {:#?}", err)).to_string())?;
+        }
+
+        Self::copy_dir(&cache_dir, &node_modules).map_err(|err| Error::Error(format!("restore dependency cache error: {:#?}", err)).to_string())?;
+        Self::touch(&cache_dir);
+        Ok(true)
+    }
+
+    /// 安装成功后保存缓存, 返回是否保存了新的缓存
+    pub(crate) fn save(server_id: &str, id: &str, key: &str, project_path: &Path) -> Result<bool, String> {
+        let node_modules = project_path.join(NODE_MODULES_DIR_NAME);
+        if !node_modules.exists() {
+            return Ok(false);
+        }
+
+        fs::write(node_modules.join(CACHE_KEY_FILE), key).map_err(|err| Error::Error(format!("write cache key error: {:#?}", err)).to_string())?;
+
+        let pipeline_cache_dir = Self::get_pipeline_cache_dir(server_id, id)?;
+        let cache_dir = pipeline_cache_dir.join(key);
+        if cache_dir.exists() {
+            Self::touch(&cache_dir);
+            return Ok(false);
+        }
+
+        // 先复制到临时目录, 防止同时运行时缓存不完整
+        let temp_dir = pipeline_cache_dir.join(format!("{}.{}", key, Uuid::new_v4()));
+        let size = match Self::copy_dir(&node_modules, &temp_dir) {
+            Ok(size) => size,
+            Err(err) => {
+                let _ = fs::remove_dir_all(&temp_dir);
+                return Err(Error::Error(format!("save dependency cache error: {:#?}", err)).to_string());
+            }
+        };
+
+        if fs::rename(&temp_dir, &cache_dir).is_err() {
+            let _ = fs::remove_dir_all(&temp_dir);
+            return Ok(false);
+        }
+
+        fs::write(Self::get_size_file(&cache_dir), format!("{}", size)).map_err(|err| Error::Error(format!("write cache size error: {:#?}", err)).to_string())?;
+        Self::prune(&pipeline_cache_dir)?;
+        Ok(true)
+    }
+
+    /// 清除缓存, id 为空时清除服务器下所有流水线的缓存, server_id 也为空时清除全部缓存
+    pub(crate) fn purge(server_id: &str, id: &str) -> Result<HttpResponse, String> {
+        let mut dir = Self::get_cache_root()?;
+        if !server_id.is_empty() {
+            dir = dir.join(server_id);
+            if !id.is_empty() {
+                dir = dir.join(id);
+            }
+        }
+
+        if dir.exists() {
+            fs::remove_dir_all(&dir).map_err(|err| Error::Error(format!("purge dependency cache error: {:#?}", err)).to_string())?;
+        }
+
+        info!("purge dependency cache: {:#?}", dir);
+        Ok(get_success_response(None))
+    }
+
+    /// 缓存根目录
+    fn get_cache_root() -> Result<PathBuf, String> {
+        let dir = Helper::get_project_config_dir(vec![String::from(CACHE_DIR_NAME)])?;
+        if let Some(dir) = dir {
+            return Ok(dir);
+        }
+
+        return Err(Error::convert_string("get dependency cache dir failed !"));
+    }
+
+    /// 流水线缓存目录
+    fn get_pipeline_cache_dir(server_id: &str, id: &str) -> Result<PathBuf, String> {
+        let dir = Helper::get_project_config_dir(vec![String::from(CACHE_DIR_NAME), server_id.to_string(), id.to_string()])?;
+        if let Some(dir) = dir {
+            return Ok(dir);
+        }
+
+        return Err(Error::convert_string("get pipeline dependency cache dir failed !"));
+    }
+
+    /// 清理缓存, 每条流水线保留最近使用的几个, 所有缓存总大小不超过设置
+    fn prune(pipeline_cache_dir: &Path) -> Result<(), String> {
+        let mut entries = Self::get_entries(pipeline_cache_dir);
+        entries.sort_by(|a, b| b.2.cmp(&a.2));
+        for (dir, _, _) in entries.iter().skip(MAX_CACHE_COUNT) {
+            Self::remove_entry(dir);
+        }
+
+        let mut limit = DEFAULT_CACHE_SIZE;
+        if let Some(settings) = Settings::get_settings() {
+            limit = settings.dependency_cache_size.trim().parse::<u64>().unwrap_or(DEFAULT_CACHE_SIZE);
+        }
+        let limit = limit * 1024 * 1024;
+
+        // 所有流水线的缓存
+        let root = Self::get_cache_root()?;
+        let mut entries: Vec<(PathBuf, u64, SystemTime)> = Vec::new();
+        for server_dir in Self::get_sub_dirs(&root).iter() {
+            for pipeline_dir in Self::get_sub_dirs(server_dir).iter() {
+                entries.extend(Self::get_entries(pipeline_dir));
+            }
+        }
+
+        let mut total: u64 = entries.iter().map(|(_, size, _)| size).sum();
+        entries.sort_by(|a, b| a.2.cmp(&b.2));
+        for (dir, size, _) in entries.iter() {
+            if total <= limit {
+                break;
+            }
+
+            Self::remove_entry(dir);
+            total = total.saturating_sub(*size);
+        }
+
+        Ok(())
+    }
+
+    /// 获取缓存列表: 目录、大小、最后使用时间
+    fn get_entries(pipeline_cache_dir: &Path) -> Vec<(PathBuf, u64, SystemTime)> {
+        let mut entries: Vec<(PathBuf, u64, SystemTime)> = Vec::new();
+        for dir in Self::get_sub_dirs(pipeline_cache_dir).into_iter() {
+            let size_file = Self::get_size_file(&dir);
+            if !size_file.exists() {
+                continue;
+            }
+
+            let size = fs::read_to_string(&size_file).unwrap_or(String::new()).trim().parse::<u64>().unwrap_or(0);
+            let modified = fs::metadata(&size_file).and_then(|metadata| metadata.modified()).unwrap_or(SystemTime::UNIX_EPOCH);
+            entries.push((dir, size, modified));
+        }
+
+        entries
+    }
+
+    /// 获取子目录
+    fn get_sub_dirs(dir: &Path) -> Vec<PathBuf> {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => return Vec::new(),
+        };
+
+        entries.flatten().map(|entry| entry.path()).filter(|path| path.is_dir()).collect()
+    }
+
+    /// 删除缓存
+    fn remove_entry(dir: &Path) {
+        info!("remove dependency cache: {:#?}", dir);
+        if let Err(err) = fs::remove_dir_all(dir) {
+            error!("remove dependency cache {:#?} error: {:#?}", dir, err);
+        }
+
+        let _ = fs::remove_file(Self::get_size_file(dir));
+    }
+
+    /// 缓存大小文件
+    fn get_size_file(dir: &Path) -> PathBuf {
+        let mut file = dir.as_os_str().to_os_string();
+        file.push(CACHE_SIZE_FILE_SUFFIX);
+        PathBuf::from(file)
+    }
+
+    /// 更新最后使用时间
+    fn touch(dir: &Path) {
+        let size_file = Self::get_size_file(dir);
+        let content = fs::read_to_string(&size_file).unwrap_or(String::from("0"));
+        if let Err(err) = fs::write(&size_file, content) {
+            error!("update dependency cache {:#?} time error: {:#?}", dir, err);
+        }
+    }
+
+    /// 复制目录, 保留软链接(pnpm), 返回文件总大小
+    fn copy_dir(from: &Path, to: &Path) -> io::Result<u64> {
+        fs::create_dir_all(to)?;
+
+        let mut size: u64 = 0;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            let target = to.join(entry.file_name());
+            if file_type.is_symlink() {
+                let link = fs::read_link(entry.path())?;
+                #[cfg(unix)]
+                std::os::unix::fs::symlink(&link, &target)?;
+                #[cfg(windows)]
+                {
+                    if entry.path().is_dir() {
+                        std::os::windows::fs::symlink_dir(&link, &target)?;
+                    } else {
+                        std::os::windows::fs::symlink_file(&link, &target)?;
+                    }
+                }
+            } else if file_type.is_dir() {
+                size += Self::copy_dir(&entry.path(), &target)?;
+            } else {
+                size += fs::copy(entry.path(), &target)?;
+            }
+        }
+
+        Ok(size)
+    }
+}
@@ -0,0 +1,305 @@
+//! Docker Compose 部署, 渲染 compose 文件并上传到服务器, 通过 SSH 拉取镜像、启动服务并检查容器状态
+
+use crate::error::Error;
+use crate::server::index::Server;
+use crate::server::pipeline::index::Pipeline;
+use crate::server::pipeline::props::{PipelineRunnableStageStep, PipelineRuntime, PipelineStepComponent};
+use crate::server::pipeline::runnable::stage::{PipelineRunnableResult, PipelineRunnableStage};
+use crate::server::pipeline::runnable::PipelineRunnable;
+use log::info;
+use sftp::sftp::SftpHandler;
+use ssh2::{ExtendedData, Session};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::AppHandle;
+
+// 上传到服务器的 compose 文件名
+const COMPOSE_FILE_NAME: &str = "docker-compose.yml";
+
+// 默认等待容器运行超时时间, 单位秒
+const DEFAULT_HEALTH_TIMEOUT_SECONDS: u64 = 120;
+
+// 检查容器状态间隔, 单位秒
+const HEALTH_CHECK_SECONDS: u64 = 3;
+
+/// Compose 配置
+#[derive(Default, Debug, Clone)]
+struct ComposeConfig {
+    file: String,          // compose 文件模板, 文件路径或内容
+    dir: String,           // 服务器目录
+    project: String,       // 项目名称, 为空时使用目录名
+    services: Vec<String>, // 部署的服务, 为空时部署所有服务
+    timeout: u64,          // 等待容器运行超时时间, 单位秒
+}
+
+pub struct PipelineCompose;
+
+impl PipelineCompose {
+    /// 执行 Docker Compose 部署步骤
+    pub(crate) async fn exec_step(app: &AppHandle, pipeline: &Pipeline, stage_step: &PipelineRunnableStageStep) -> Result<PipelineRunnableResult, String> {
+        let pack_name = format!("【{}】", &stage_step.step.label);
+        let runtime = pipeline.runtime.clone().unwrap_or(PipelineRuntime::default());
+        let order = runtime.order.unwrap_or(1);
+        PipelineRunnable::save_log(app, &format!("exec step {} ...", pack_name), &pipeline.server_id, &pipeline.id, order);
+
+        let config = Self::get_config(&stage_step.step.components);
+        if config.file.trim().is_empty() || config.dir.is_empty() {
+            return Err(Error::convert_string("docker compose deploy failed, `compose.file` or `compose.dir` is empty!"));
+        }
+
+        // 渲染 compose 文件
+        let dir = PipelineRunnableStage::get_work_dir(pipeline)?;
+        let content = PipelineRunnableStage::read_template(&dir, &config.file)?;
+        let content = PipelineRunnableStage::render_template(&content, &PipelineRunnableStage::get_template_variables(&runtime));
+        PipelineRunnable::save_log(app, "render docker compose file success !", &pipeline.server_id, &pipeline.id, order);
+
+        // 查找服务器信息
+        let mut server = Server::default();
+        server.id = pipeline.server_id.clone();
+        let response = Server::get_by_id(&server).await?;
+        if response.code != 200 {
+            return Err(Error::convert_string(&response.error));
+        }
+
+        let server: Server = serde_json::from_value(response.body).map_err(|err| Error::Error(err.to_string()).to_string())?;
+        let serve = sftp::config::Server {
+            host: server.ip.to_string(),
+            port: server.port,
+            username: server.account.to_string(),
+            password: server.pwd.to_string(),
+            timeout: Some(5),
+        };
+
+        // SSH 操作及等待容器运行均为阻塞调用, 放到阻塞线程中执行
+        let app_cloned = app.clone();
+        let server_id = pipeline.server_id.clone();
+        let id = pipeline.id.clone();
+        async_std::task::spawn_blocking(move || {
+            let log = |msg: &str| PipelineRunnable::save_output_log(&app_cloned, msg, &server_id, &id, order);
+            Self::deploy(&serve, &config, &content, &log)
+        })
+        .await?;
+
+        Ok(PipelineRunnableResult {
+            success: true,
+            msg: pack_name,
+            pipeline: Some(pipeline.clone()),
+        })
+    }
+
+    /// 上传 compose 文件, 拉取镜像并启动, 等待容器运行
+    fn deploy<F>(serve: &sftp::config::Server, config: &ComposeConfig, content: &str, log: &F) -> Result<(), String>
+    where
+        F: Fn(&str),
+    {
+        let func = |_: &str| {};
+        let log_func = Arc::new(Mutex::new(func));
+        let session = SftpHandler::connect(serve, log_func.clone())?;
+
+        // 上传
+        if !Self::exec_remote(&session, &format!("mkdir -p \"{}\"", &config.dir), log)? {
+            return Err(Error::convert_string(&format!("create server dir `{}` error!!", &config.dir)));
+        }
+
+        let file_path = Path::new(&config.dir).join(COMPOSE_FILE_NAME);
+        Self::upload(&session, &file_path, content)?;
+        log(&format!("upload compose file to {} success !", file_path.to_string_lossy()));
+
+        // 拉取镜像并启动
+        let compose = Self::get_compose(config);
+        let services = config.services.join(" ");
+        let command = format!("{} pull {} && {} up -d {}", &compose, &services, &compose, &services);
+        if !Self::exec_remote(&session, &command, log)? {
+            return Err(Error::convert_string("docker compose up error!!"));
+        }
+
+        // 检查容器状态
+        let result = Self::wait_running(&session, config, log);
+        Self::exec_remote(&session, &format!("{} ps", &compose), log)?;
+        result
+    }
+
+    /// 获取配置
+    fn get_config(components: &Vec<PipelineStepComponent>) -> ComposeConfig {
+        let mut config = ComposeConfig {
+            timeout: DEFAULT_HEALTH_TIMEOUT_SECONDS,
+            ..Default::default()
+        };
+
+        for component in components.iter() {
+            let prop = &component.prop;
+            let value = component.value.trim();
+            if value.is_empty() {
+                continue;
+            }
+
+            if prop == "compose.file" {
+                config.file = component.value.clone();
+            }
+
+            if prop == "compose.dir" {
+                config.dir = value.to_string();
+            }
+
+            if prop == "compose.project" {
+                config.project = value.to_string();
+            }
+
+            if prop == "compose.services" {
+                config.services = value.split(|c: char| c == ',' || c.is_whitespace()).filter(|service| !service.is_empty()).map(|service| service.to_string()).collect();
+            }
+
+            if prop == "compose.timeout" {
+                config.timeout = value.parse::<u64>().unwrap_or(DEFAULT_HEALTH_TIMEOUT_SECONDS);
+            }
+        }
+
+        config
+    }
+
+    /// docker compose 命令及公共参数
+    fn get_compose(config: &ComposeConfig) -> String {
+        let mut compose = format!("cd \"{}\" && docker compose -f {}", &config.dir, COMPOSE_FILE_NAME);
+        if !config.project.is_empty() {
+            compose.push_str(&format!(" -p \"{}\"", &config.project));
+        }
+
+        compose
+    }
+
+    /// 上传 compose 文件
+    fn upload(session: &Session, file_path: &Path, content: &str) -> Result<(), String> {
+        let sftp = session.sftp().map_err(|err| Error::Error(format!("open sftp error: {:#?}", err)).to_string())?;
+        let mut file = sftp.create(file_path).map_err(|err| Error::Error(format!("create file `{}` error: {:#?}", file_path.to_string_lossy(), err)).to_string())?;
+        file.write_all(content.as_bytes())
+            .map_err(|err| Error::Error(format!("write file `{}` error: {:#?}", file_path.to_string_lossy(), err)).to_string())?;
+        Ok(())
+    }
+
+    /// 等待容器运行, 配置了健康检查的容器需要达到 healthy 状态, 正常退出(退出码为 0)的一次性容器(如数据库迁移)视为完成
+    fn wait_running<F>(session: &Session, config: &ComposeConfig, log: &F) -> Result<(), String>
+    where
+        F: Fn(&str),
+    {
+        let command = format!(
+            "{} ps -a -q {} | xargs -r docker inspect -f '{{{{.Name}}}} {{{{.State.Status}}}} {{{{.State.ExitCode}}}} {{{{if .State.Health}}}}{{{{.State.Health.Status}}}}{{{{end}}}}'",
+            Self::get_compose(config),
+            config.services.join(" ")
+        );
+
+        let now = Instant::now();
+        loop {
+            let outputs: Mutex<Vec<String>> = Mutex::new(Vec::new());
+            Self::exec_remote(session, &command, &|msg: &str| {
+                if let Ok(mut outputs) = outputs.lock() {
+                    outputs.push(msg.to_string());
+                }
+            })?;
+
+            let outputs = outputs.into_inner().unwrap_or(Vec::new());
+            // 输出中容器名称以 `/` 开头, 过滤掉命令本身的输出
+            let containers: Vec<Vec<&str>> = outputs.iter().map(|line| line.split_whitespace().collect::<Vec<&str>>()).filter(|fields| fields.len() >= 3 && fields[0].starts_with('/')).collect();
+            if containers.is_empty() {
+                return Err(Error::convert_string("docker compose deploy failed, no containers found !"));
+            }
+
+            let (failed, pending) = Self::get_container_states(&containers);
+            if !failed.is_empty() {
+                return Err(Error::convert_string(&format!("docker compose containers failed: {}", failed.join(", "))));
+            }
+
+            if pending.is_empty() {
+                log("all containers are running !");
+                return Ok(());
+            }
+
+            if now.elapsed().as_secs() >= config.timeout {
+                return Err(Error::convert_string(&format!("wait docker compose containers timeout: {}", pending.join(", "))));
+            }
+
+            info!("waiting containers: {:?}", pending);
+            std::thread::sleep(Duration::from_secs(HEALTH_CHECK_SECONDS));
+        }
+    }
+
+    /// 根据容器状态 `name status exit_code [health]` 获取失败及未就绪的容器
+    fn get_container_states(containers: &Vec<Vec<&str>>) -> (Vec<String>, Vec<String>) {
+        let mut failed: Vec<String> = Vec::new();
+        let mut pending: Vec<String> = Vec::new();
+        for fields in containers.iter() {
+            let status = fields[1];
+            let exit_code = fields[2];
+            let health = fields.get(3).cloned();
+
+            // 异常退出或健康检查失败时直接失败
+            if status == "dead" || (status == "exited" && exit_code != "0") || health == Some("unhealthy") {
+                failed.push(fields.join(" "));
+                continue;
+            }
+
+            // 正常退出的一次性容器视为完成
+            if status == "exited" {
+                continue;
+            }
+
+            if status != "running" || health.map(|health| health != "healthy").unwrap_or(false) {
+                pending.push(fields.join(" "));
+            }
+        }
+
+        (failed, pending)
+    }
+
+    /// 在服务器上执行命令, 标准错误合并到标准输出, 逐行输出
+    fn exec_remote<F>(session: &Session, command: &str, func: &F) -> Result<bool, String>
+    where
+        F: Fn(&str),
+    {
+        func(&format!("exec command: {}", command));
+        let mut channel = SftpHandler::create_channel(session)?;
+        channel.handle_extended_data(ExtendedData::Merge).map_err(|err| Error::Error(format!("exec command error: {:#?}", err)).to_string())?;
+        channel.exec(command).map_err(|err| {
+            SftpHandler::close_channel_in_err(&mut channel);
+            Error::Error(format!("exec command `{}` error: {:#?}", command, err)).to_string()
+        })?;
+
+        let reader = BufReader::new(channel.stream(0));
+        for line in reader.lines() {
+            match line {
+                Ok(line) => func(&line),
+                Err(_) => break,
+            }
+        }
+
+        channel.wait_close().map_err(|err| Error::Error(format!("exec command error: {:#?}", err)).to_string())?;
+        let status = channel.exit_status().map_err(|err| Error::Error(format!("exec command error: {:#?}", err)).to_string())?;
+        Ok(status == 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_container_states() {
+        let containers: Vec<Vec<&str>> = vec![
+            vec!["/web", "running", "0", "healthy"],
+            vec!["/api", "running", "0"],
+            vec!["/migrate", "exited", "0"],
+            vec!["/db", "running", "0", "starting"],
+            vec!["/cache", "restarting", "1"],
+        ];
+
+        let (failed, pending) = PipelineCompose::get_container_states(&containers);
+        assert!(failed.is_empty());
+        assert_eq!(pending, vec!["/db running 0 starting", "/cache restarting 1"]);
+
+        let containers: Vec<Vec<&str>> = vec![vec!["/migrate", "exited", "1"], vec!["/web", "running", "0", "unhealthy"], vec!["/worker", "dead", "137"]];
+        let (failed, pending) = PipelineCompose::get_container_states(&containers);
+        assert_eq!(failed, vec!["/migrate exited 1", "/web running 0 unhealthy", "/worker dead 137"]);
+        assert!(pending.is_empty());
+    }
+}
@@ -0,0 +1,263 @@
+//! 对比两次运行记录, 快照、启动变量、步骤定义、提交、步骤耗时及日志
+
+use crate::database::helper::DBHelper;
+use crate::logger::pipeline::PipelineLogger;
+use crate::prepare::{get_error_response, get_success_response_by_value, HttpResponse};
+use crate::server::pipeline::index::Pipeline;
+use crate::server::pipeline::props::{PipelineRunDiff, PipelineRunDiffField, PipelineRunDiffForm, PipelineRunDiffHunk, PipelineRunDiffLine, PipelineRunDiffStep, PipelineRuntime};
+use crate::server::pipeline::runnable::{PipelineRunnable, PipelineRunnableQueryForm};
+use serde_json::json;
+use similar::{ChangeTag, TextDiff};
+use sqlx::{MySql, Row};
+use std::collections::BTreeMap;
+use std::time::Duration;
+use uuid::Uuid;
+
+// 默认日志差异上下文行数
+const DEFAULT_CONTEXT_LINES: usize = 3;
+
+// 日志差异计算超时, 超时后返回粗略结果
+const LOG_DIFF_TIMEOUT_SECONDS: u64 = 5;
+
+/// 步骤运行记录: 名称、状态、耗时
+type StepRecord = (String, String, Option<u64>);
+
+pub struct PipelineRunDiffer;
+
+impl PipelineRunDiffer {
+    /// 对比两次运行记录
+    pub(crate) async fn diff(form: &PipelineRunDiffForm) -> Result<HttpResponse, String> {
+        if form.server_id.is_empty() || form.id.is_empty() {
+            return Ok(get_error_response("对比运行记录失败, `serverId` 或 `id` 不能为空"));
+        }
+
+        if form.base_runtime_id.is_empty() || form.target_runtime_id.is_empty() {
+            return Ok(get_error_response("对比运行记录失败, `baseRuntimeId` 或 `targetRuntimeId` 不能为空"));
+        }
+
+        // ID 会用于查询及日志路径, 只接受 UUID
+        let ids = [&form.server_id, &form.id, &form.base_runtime_id, &form.target_runtime_id];
+        if ids.iter().any(|id| Uuid::parse_str(id).is_err()) {
+            return Ok(get_error_response("对比运行记录失败, `serverId`、`id`、`baseRuntimeId` 或 `targetRuntimeId` 格式不正确"));
+        }
+
+        let mut pipeline = Pipeline::default();
+        pipeline.id = form.id.clone();
+        pipeline.server_id = form.server_id.clone();
+
+        let base = match Self::get_runtime(&pipeline, &form.base_runtime_id).await? {
+            Some(runtime) => runtime,
+            None => return Ok(get_error_response("对比运行记录失败, 基准运行记录不存在")),
+        };
+
+        let target = match Self::get_runtime(&pipeline, &form.target_runtime_id).await? {
+            Some(runtime) => runtime,
+            None => return Ok(get_error_response("对比运行记录失败, 对比运行记录不存在")),
+        };
+
+        let base_order = base.order.unwrap_or(1);
+        let target_order = target.order.unwrap_or(1);
+        let base_lines = PipelineLogger::read_lines(&form.server_id, &form.id, base_order)?;
+        let target_lines = PipelineLogger::read_lines(&form.server_id, &form.id, target_order)?;
+
+        let diff = PipelineRunDiff {
+            base_order,
+            target_order,
+            snapshot: Self::diff_snapshot(&base, &target),
+            variables: Self::diff_map(&Self::get_variables(&base), &Self::get_variables(&target)),
+            stages: Self::diff_map(&Self::get_stage_steps(&base), &Self::get_stage_steps(&target)),
+            commit: Self::diff_commit(&base, &target),
+            steps: Self::diff_steps(&form.base_runtime_id, &form.target_runtime_id).await?,
+            log: Self::diff_log(&base_lines, &target_lines, form.context_lines.unwrap_or(DEFAULT_CONTEXT_LINES)),
+        };
+
+        get_success_response_by_value(diff)
+    }
+
+    /// 查询运行记录, 包括快照及启动变量
+    async fn get_runtime(pipeline: &Pipeline, runtime_id: &str) -> Result<Option<PipelineRuntime>, String> {
+        let result = PipelineRunnable::get_runtime_detail(
+            pipeline,
+            true,
+            Some(PipelineRunnableQueryForm {
+                status_list: vec![],
+                runtime_id: Some(runtime_id.to_string()),
+                need_condition_last_run_id: None,
+            }),
+        )
+        .await?;
+
+        Ok(result.runtime)
+    }
+
+    /// 快照差异
+    fn diff_snapshot(base: &PipelineRuntime, target: &PipelineRuntime) -> Vec<PipelineRunDiffField> {
+        let get_fields = |runtime: &PipelineRuntime| {
+            let snapshot = &runtime.snapshot;
+            BTreeMap::from([
+                (String::from("branch"), snapshot.branch.clone()),
+                (String::from("revision"), snapshot.revision.clone()),
+                (String::from("node"), snapshot.node.clone()),
+                (String::from("make"), snapshot.make.clone().unwrap_or(String::new())),
+                (String::from("command"), snapshot.command.clone()),
+                (String::from("script"), snapshot.script.clone()),
+            ])
+        };
+
+        Self::diff_map(&get_fields(base), &get_fields(target))
+    }
+
+    /// 提交差异
+    fn diff_commit(base: &PipelineRuntime, target: &PipelineRuntime) -> Vec<PipelineRunDiffField> {
+        let get_fields = |runtime: &PipelineRuntime| {
+            let commit = &runtime.commit;
+            BTreeMap::from([(String::from("sha"), commit.sha.clone()), (String::from("author"), commit.author.clone()), (String::from("message"), commit.message.clone())])
+        };
+
+        Self::diff_map(&get_fields(base), &get_fields(target))
+    }
+
+    /// 启动变量: name -> value
+    fn get_variables(runtime: &PipelineRuntime) -> BTreeMap<String, String> {
+        runtime.snapshot.runnable_variables.iter().map(|variable| (variable.name.clone(), variable.value.clone())).collect()
+    }
+
+    /// 步骤定义, 与运行时一致按 stage 顺序展开: `stage 1 / group 0 / step 0` -> 步骤配置
+    fn get_stage_steps(runtime: &PipelineRuntime) -> BTreeMap<String, String> {
+        let mut stages = runtime.stages.clone();
+        stages.sort_by(|stage1, stage2| stage1.order.cmp(&stage2.order));
+
+        let mut map: BTreeMap<String, String> = BTreeMap::new();
+        for (i, stage) in stages.iter().enumerate() {
+            for (j, group) in stage.groups.iter().enumerate() {
+                for (k, step) in group.steps.iter().enumerate() {
+                    let value = json!({
+                        "group": group.label,
+                        "label": step.label,
+                        "module": step.module,
+                        "command": step.command,
+                        "components": step.components,
+                    });
+                    map.insert(format!("stage {} / group {} / step {}", i + 1, j, k), value.to_string());
+                }
+            }
+        }
+
+        map
+    }
+
+    /// 对比两组字段, 只返回有变化的
+    fn diff_map(base: &BTreeMap<String, String>, target: &BTreeMap<String, String>) -> Vec<PipelineRunDiffField> {
+        let mut names: Vec<&String> = base.keys().chain(target.keys()).collect();
+        names.sort();
+        names.dedup();
+
+        names
+            .into_iter()
+            .filter(|name| base.get(*name) != target.get(*name))
+            .map(|name| PipelineRunDiffField {
+                name: name.clone(),
+                base: base.get(name).cloned(),
+                target: target.get(name).cloned(),
+            })
+            .collect()
+    }
+
+    /// 步骤耗时差异
+    async fn diff_steps(base_runtime_id: &str, target_runtime_id: &str) -> Result<Vec<PipelineRunDiffStep>, String> {
+        let base = Self::get_step_records(base_runtime_id).await?;
+        let target = Self::get_step_records(target_runtime_id).await?;
+
+        let mut keys: Vec<&(u32, u32, u32)> = base.keys().chain(target.keys()).collect();
+        keys.sort();
+        keys.dedup();
+
+        let list = keys
+            .into_iter()
+            .map(|key| {
+                let base_step = base.get(key);
+                let target_step = target.get(key);
+                let base_duration = base_step.and_then(|(_, _, duration)| *duration);
+                let target_duration = target_step.and_then(|(_, _, duration)| *duration);
+                PipelineRunDiffStep {
+                    stage_index: key.0,
+                    group_index: key.1,
+                    step_index: key.2,
+                    label: target_step.or(base_step).map(|(label, _, _)| label.clone()).unwrap_or(String::new()),
+                    base_status: base_step.map(|(_, status, _)| status.clone()),
+                    target_status: target_step.map(|(_, status, _)| status.clone()),
+                    base_duration,
+                    target_duration,
+                    delta: match (base_duration, target_duration) {
+                        (Some(base_duration), Some(target_duration)) => Some(target_duration as i64 - base_duration as i64),
+                        _ => None,
+                    },
+                }
+            })
+            .collect();
+
+        Ok(list)
+    }
+
+    /// 查询运行记录的步骤耗时, 重试过的步骤取最后一次
+    async fn get_step_records(runtime_id: &str) -> Result<BTreeMap<(u32, u32, u32), StepRecord>, String> {
+        let query = sqlx::query::<MySql>(
+            r#"
+            SELECT
+                CAST( stage_index AS UNSIGNED ) AS stage_index,
+                CAST( group_index AS UNSIGNED ) AS group_index,
+                CAST( step_index AS UNSIGNED ) AS step_index,
+                label,
+                `status`,
+                CAST( duration_ms AS UNSIGNED ) AS duration_ms
+            FROM pipeline_runtime_step
+            WHERE runtime_id = ?
+            ORDER BY create_time
+        "#,
+        )
+        .bind(runtime_id);
+
+        let rows = DBHelper::execute_rows(query).await?;
+        let mut map: BTreeMap<(u32, u32, u32), StepRecord> = BTreeMap::new();
+        for row in rows.iter() {
+            let stage_index: u64 = row.try_get("stage_index").unwrap_or(0);
+            let group_index: u64 = row.try_get("group_index").unwrap_or(0);
+            let step_index: u64 = row.try_get("step_index").unwrap_or(0);
+            let label: String = row.try_get("label").unwrap_or(String::new());
+            let status: String = row.try_get("status").unwrap_or(String::new());
+            let duration: Option<u64> = row.try_get("duration_ms").unwrap_or(None);
+            map.insert((stage_index as u32, group_index as u32, step_index as u32), (label, status, duration));
+        }
+
+        Ok(map)
+    }
+
+    /// 日志按行对比, 只返回有变化的片段及其上下文
+    fn diff_log(base: &[String], target: &[String], context_lines: usize) -> Vec<PipelineRunDiffHunk> {
+        let base: Vec<&str> = base.iter().map(|line| line.as_str()).collect();
+        let target: Vec<&str> = target.iter().map(|line| line.as_str()).collect();
+        let diff = TextDiff::configure().timeout(Duration::from_secs(LOG_DIFF_TIMEOUT_SECONDS)).diff_slices(&base, &target);
+
+        diff.grouped_ops(context_lines)
+            .iter()
+            .map(|group| {
+                let lines = group
+                    .iter()
+                    .flat_map(|op| diff.iter_changes(op))
+                    .map(|change| PipelineRunDiffLine {
+                        tag: String::from(match change.tag() {
+                            ChangeTag::Equal => "equal",
+                            ChangeTag::Delete => "delete",
+                            ChangeTag::Insert => "insert",
+                        }),
+                        base_line: change.old_index().map(|index| index + 1),
+                        target_line: change.new_index().map(|index| index + 1),
+                        content: change.value().to_string(),
+                    })
+                    .collect();
+
+                PipelineRunDiffHunk { lines }
+            })
+            .collect()
+    }
+}
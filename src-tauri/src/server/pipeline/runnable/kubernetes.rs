@@ -0,0 +1,327 @@
+//! Kubernetes 部署, 使用流水线变量渲染清单模板后通过 kubectl 部署, 等待发布完成, 失败时回滚本次变更的工作负载
+
+use crate::error::Error;
+use crate::helper::index::Helper;
+use crate::server::pipeline::index::Pipeline;
+use crate::server::pipeline::props::{PipelineRunnableStageStep, PipelineRuntime, PipelineStepComponent};
+use crate::server::pipeline::runnable::stage::{PipelineRunnableResult, PipelineRunnableStage, PipelineTempFile};
+use crate::server::pipeline::runnable::PipelineRunnable;
+use regex::Regex;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tauri::AppHandle;
+
+// 默认命名空间
+const DEFAULT_NAMESPACE: &str = "default";
+
+// 默认副本数
+const DEFAULT_REPLICAS: &str = "1";
+
+// 默认等待发布超时时间, 单位秒
+const DEFAULT_ROLLOUT_TIMEOUT_SECONDS: u64 = 300;
+
+// `apply` 输出中表示新建的动作
+const ACTION_CREATED: &str = "created";
+
+/// Kubernetes 配置
+#[derive(Default, Debug, Clone)]
+struct KubernetesConfig {
+    manifest: String,  // 清单模板, 文件路径或内容
+    context: String,   // kubectl context, 为空时使用当前 context
+    namespace: String, // 命名空间
+    replicas: String,  // 副本数
+    timeout: u64,      // 等待发布超时时间, 单位秒
+}
+
+pub struct PipelineKubernetes;
+
+impl PipelineKubernetes {
+    /// 执行 Kubernetes 部署步骤
+    pub(crate) async fn exec_step(app: &AppHandle, pipeline: &Pipeline, stage_step: &PipelineRunnableStageStep) -> Result<PipelineRunnableResult, String> {
+        let pack_name = format!("【{}】", &stage_step.step.label);
+        let runtime = pipeline.runtime.clone().unwrap_or(PipelineRuntime::default());
+        let order = runtime.order.unwrap_or(1);
+        PipelineRunnable::save_log(app, &format!("exec step {} ...", pack_name), &pipeline.server_id, &pipeline.id, order);
+
+        let config = Self::get_config(&stage_step.step.components);
+        if config.manifest.trim().is_empty() {
+            return Err(Error::convert_string("kubernetes deploy failed, `kubernetes.manifest` is empty!"));
+        }
+
+        // 渲染清单
+        let dir = PipelineRunnableStage::get_work_dir(pipeline)?;
+        let manifest = PipelineRunnableStage::read_template(&dir, &config.manifest)?;
+        let mut variables = PipelineRunnableStage::get_template_variables(&runtime);
+        variables.insert(String::from("replicas"), config.replicas.clone());
+        variables.insert(String::from("namespace"), config.namespace.clone());
+        let manifest = PipelineRunnableStage::render_template(&manifest, &variables);
+
+        // 清单中可能包含 Secret, 写入配置目录下的临时文件, 部署后删除
+        let manifest_file = PipelineTempFile::create("yaml", &manifest)?;
+        let manifest_path = manifest_file.path.to_string_lossy().to_string();
+        PipelineRunnable::save_log(app, "render kubernetes manifest success !", &pipeline.server_id, &pipeline.id, order);
+
+        let dir = dir.to_string_lossy().to_string();
+        let kubectl = Self::get_kubectl(&config);
+        let outputs: Arc<Mutex<Vec<String>>> = Arc::new(Mutex::new(Vec::new()));
+        let exec_command = |command: &str| {
+            let server_id_cloned = Arc::new(pipeline.server_id.clone());
+            let id_cloned = Arc::new(pipeline.id.clone());
+            let app_cloned = Arc::new(app.clone());
+            let outputs_cloned = outputs.clone();
+            Helper::exec_command_by_path(command, &dir, None, move |msg| {
+                if let Ok(mut outputs) = outputs_cloned.lock() {
+                    outputs.push(msg.to_string());
+                }
+                PipelineRunnable::save_output_log(&*app_cloned, msg, &*server_id_cloned, &*id_cloned, order);
+            })
+        };
+
+        // 执行命令并获取输出, 不写入日志
+        let query_command = |command: &str| -> Option<Vec<String>> {
+            let query_outputs: Arc<Mutex<Vec<String>>> = Arc::new(Mutex::new(Vec::new()));
+            let query_outputs_cloned = query_outputs.clone();
+            let success = Helper::exec_command_by_path(command, &dir, None, move |msg| {
+                if let Ok(mut outputs) = query_outputs_cloned.lock() {
+                    outputs.push(msg.to_string());
+                }
+            });
+
+            if !success {
+                return None;
+            }
+
+            let outputs = query_outputs.lock().map(|outputs| outputs.clone()).unwrap_or(Vec::new());
+            Some(outputs.into_iter().filter(|output| !output.starts_with("exec command:")).collect())
+        };
+
+        // 部署前记录清单中工作负载的当前版本, 不存在时为 None
+        let outputs_before = match query_command(&format!("{} apply --dry-run=client -f \"{}\" -o name", &kubectl, &manifest_path)) {
+            Some(outputs) => outputs,
+            None => return Err(Error::convert_string("kubernetes manifest validate error!!")),
+        };
+
+        let mut revisions: HashMap<String, Option<String>> = HashMap::new();
+        for (resource, _) in Self::get_rollout_resources(&outputs_before).iter() {
+            let revision = Self::get_revision(&query_command, &kubectl, resource);
+            revisions.insert(resource.clone(), revision);
+        }
+        PipelineRunnable::save_log(app, &format!("kubernetes workload revisions before apply: {:?}", &revisions), &pipeline.server_id, &pipeline.id, order);
+
+        // 部署, 输出中只有资源名称和动作(created/configured/unchanged), 不包含清单内容
+        let success = exec_command(&format!("{} apply -f \"{}\"", &kubectl, &manifest_path));
+        drop(manifest_file);
+        if !success {
+            return Err(Error::convert_string("kubernetes apply error!!"));
+        }
+
+        // 等待发布完成, 失败时回滚
+        let resources = Self::get_rollout_resources(&outputs.lock().map(|outputs| outputs.clone()).unwrap_or(Vec::new()));
+        PipelineRunnable::save_log(app, &format!("kubernetes rollout resources: {:?}", &resources), &pipeline.server_id, &pipeline.id, order);
+        for (resource, _) in resources.iter() {
+            let success = exec_command(&format!("{} rollout status {} --timeout={}s", &kubectl, resource, config.timeout));
+            if success {
+                continue;
+            }
+
+            PipelineRunnable::save_log(app, &format!("rollout {} failed, rollback ...", resource), &pipeline.server_id, &pipeline.id, order);
+            for (resource, action) in resources.iter() {
+                // 本次新建的直接删除
+                if action == ACTION_CREATED {
+                    exec_command(&format!("{} delete {}", &kubectl, resource));
+                    continue;
+                }
+
+                // 版本未变化的不回滚
+                let before = revisions.get(resource).cloned().flatten();
+                let after = Self::get_revision(&query_command, &kubectl, resource);
+                if !Self::is_revision_changed(&before, &after) {
+                    PipelineRunnable::save_log(app, &format!("{} revision unchanged, skip rollback", resource), &pipeline.server_id, &pipeline.id, order);
+                    continue;
+                }
+
+                exec_command(&format!("{} rollout undo {}", &kubectl, resource));
+            }
+
+            return Err(Error::convert_string(&format!("kubernetes rollout {} error, rolled back !", resource)));
+        }
+
+        Ok(PipelineRunnableResult {
+            success: true,
+            msg: pack_name,
+            pipeline: Some(pipeline.clone()),
+        })
+    }
+
+    /// 获取配置
+    fn get_config(components: &Vec<PipelineStepComponent>) -> KubernetesConfig {
+        let mut config = KubernetesConfig {
+            namespace: String::from(DEFAULT_NAMESPACE),
+            replicas: String::from(DEFAULT_REPLICAS),
+            timeout: DEFAULT_ROLLOUT_TIMEOUT_SECONDS,
+            ..Default::default()
+        };
+
+        for component in components.iter() {
+            let prop = &component.prop;
+            let value = component.value.trim();
+            if value.is_empty() {
+                continue;
+            }
+
+            if prop == "kubernetes.manifest" {
+                config.manifest = component.value.clone();
+            }
+
+            if prop == "kubernetes.context" {
+                config.context = value.to_string();
+            }
+
+            if prop == "kubernetes.namespace" {
+                config.namespace = value.to_string();
+            }
+
+            if prop == "kubernetes.replicas" {
+                config.replicas = value.to_string();
+            }
+
+            if prop == "kubernetes.timeout" {
+                config.timeout = value.parse::<u64>().unwrap_or(DEFAULT_ROLLOUT_TIMEOUT_SECONDS);
+            }
+        }
+
+        config
+    }
+
+    /// kubectl 命令及公共参数
+    fn get_kubectl(config: &KubernetesConfig) -> String {
+        let mut kubectl = String::from("kubectl");
+        if !config.context.is_empty() {
+            kubectl.push_str(&format!(" --context \"{}\"", &config.context));
+        }
+
+        kubectl.push_str(&format!(" -n \"{}\"", &config.namespace));
+        kubectl
+    }
+
+    /// 从 `apply` 的输出中获取需要等待发布的资源及动作, `-o name` 输出时动作为空
+    fn get_rollout_resources(outputs: &Vec<String>) -> Vec<(String, String)> {
+        let re = Regex::new(r"^((?:deployment|statefulset|daemonset)\.apps/\S+)(?:\s+(created|configured|unchanged))?$").unwrap();
+        outputs
+            .iter()
+            .flat_map(|output| output.lines())
+            .filter_map(|line| re.captures(line.trim()))
+            .map(|caps| (caps[1].to_string(), caps.get(2).map(|action| action.as_str().to_string()).unwrap_or(String::new())))
+            .collect()
+    }
+
+    /// 工作负载版本的 jsonpath, deployment 使用 `deployment.kubernetes.io/revision` 注解
+    fn get_revision_path(resource: &str) -> &'static str {
+        if resource.starts_with("deployment.") {
+            return "{.metadata.annotations.deployment\\.kubernetes\\.io/revision}";
+        }
+
+        if resource.starts_with("statefulset.") {
+            return "{.status.updateRevision}";
+        }
+
+        "{.metadata.generation}"
+    }
+
+    /// 查询工作负载当前版本, 不存在或查询失败时为 None
+    fn get_revision<F>(query_command: &F, kubectl: &str, resource: &str) -> Option<String>
+    where
+        F: Fn(&str) -> Option<Vec<String>>,
+    {
+        let outputs = query_command(&format!("{} get {} --ignore-not-found -o jsonpath=\"{}\"", kubectl, resource, Self::get_revision_path(resource)))?;
+        let revision = outputs.join("").trim().to_string();
+        if revision.is_empty() {
+            return None;
+        }
+
+        Some(revision)
+    }
+
+    /// 版本是否变化, 部署前后都查询不到时视为未变化
+    fn is_revision_changed(before: &Option<String>, after: &Option<String>) -> bool {
+        match (before, after) {
+            (Some(before), Some(after)) => before != after,
+            (None, None) => false,
+            _ => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_rollout_resources() {
+        let outputs = vec![
+            String::from("exec command: kubectl -n \"devops\" apply -f \"k8s.yaml\""),
+            String::from("deployment.apps/web configured"),
+            String::from("  statefulset.apps/db unchanged  "),
+            String::from("daemonset.apps/agent created\nservice/web created\nsecret/web-tls configured"),
+            String::from("configmap/web-config created"),
+            String::from("deployment.apps/api"),
+            String::from("deployment.apps/web extra"),
+        ];
+
+        let resources = PipelineKubernetes::get_rollout_resources(&outputs);
+        let expected: Vec<(String, String)> = vec![
+            (String::from("deployment.apps/web"), String::from("configured")),
+            (String::from("statefulset.apps/db"), String::from("unchanged")),
+            (String::from("daemonset.apps/agent"), String::from(ACTION_CREATED)),
+            (String::from("deployment.apps/api"), String::new()),
+        ];
+        assert_eq!(resources, expected);
+        assert!(PipelineKubernetes::get_rollout_resources(&Vec::new()).is_empty());
+    }
+
+    #[test]
+    fn test_get_revision() {
+        assert_eq!(PipelineKubernetes::get_revision_path("deployment.apps/web"), "{.metadata.annotations.deployment\\.kubernetes\\.io/revision}");
+        assert_eq!(PipelineKubernetes::get_revision_path("statefulset.apps/db"), "{.status.updateRevision}");
+        assert_eq!(PipelineKubernetes::get_revision_path("daemonset.apps/agent"), "{.metadata.generation}");
+
+        let query_command = |command: &str| -> Option<Vec<String>> {
+            if command.contains("deployment.apps/web") {
+                return Some(vec![String::from("3")]);
+            }
+
+            if command.contains("deployment.apps/api") {
+                return Some(Vec::new());
+            }
+
+            None
+        };
+        assert_eq!(PipelineKubernetes::get_revision(&query_command, "kubectl", "deployment.apps/web"), Some(String::from("3")));
+        assert_eq!(PipelineKubernetes::get_revision(&query_command, "kubectl", "deployment.apps/api"), None);
+        assert_eq!(PipelineKubernetes::get_revision(&query_command, "kubectl", "statefulset.apps/db"), None);
+    }
+
+    #[test]
+    fn test_is_revision_changed() {
+        assert!(!PipelineKubernetes::is_revision_changed(&Some(String::from("3")), &Some(String::from("3"))));
+        assert!(PipelineKubernetes::is_revision_changed(&Some(String::from("3")), &Some(String::from("4"))));
+        assert!(PipelineKubernetes::is_revision_changed(&None, &Some(String::from("1"))));
+        assert!(!PipelineKubernetes::is_revision_changed(&None, &None));
+    }
+
+    #[test]
+    fn test_get_kubectl() {
+        let config = KubernetesConfig {
+            namespace: String::from("devops"),
+            ..Default::default()
+        };
+        assert_eq!(PipelineKubernetes::get_kubectl(&config), "kubectl -n \"devops\"");
+
+        let config = KubernetesConfig {
+            context: String::from("prod"),
+            namespace: String::from("web"),
+            ..Default::default()
+        };
+        assert_eq!(PipelineKubernetes::get_kubectl(&config), "kubectl --context \"prod\" -n \"web\"");
+    }
+}
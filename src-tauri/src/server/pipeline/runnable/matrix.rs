@@ -0,0 +1,298 @@
+//! 矩阵构建, 一次运行按变量组合展开为多个子运行记录
+
+use crate::database::helper::DBHelper;
+use crate::error::Error;
+use crate::event::EventEmitter;
+use crate::logger::Logger;
+use crate::prepare::{get_error_response, get_success_response_by_value, HttpResponse};
+use crate::server::pipeline::index::Pipeline;
+use crate::server::pipeline::pool::Pool;
+use crate::server::pipeline::props::{PipelineMatrixAxis, PipelineRuntime, PipelineStatus};
+use crate::server::pipeline::runnable::stage::PipelineRunnableStage;
+use crate::server::pipeline::runnable::trigger::PipelineTrigger;
+use crate::server::pipeline::runnable::{PipelineRunnable, PipelineRunnableQueryForm};
+use lazy_static::lazy_static;
+use log::{error, info};
+use sqlx::mysql::MySqlArguments;
+use sqlx::query::Query;
+use sqlx::MySql;
+use std::collections::HashSet;
+use tauri::AppHandle;
+use uuid::Uuid;
+
+// 更新父运行记录时加锁, 防止多个子运行记录同时结束时重复触发
+lazy_static! {
+    static ref MATRIX_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::new(());
+}
+
+pub struct PipelineMatrix;
+
+impl PipelineMatrix {
+    /// 插入父运行记录及所有子运行记录, 返回子运行记录 ID
+    pub(crate) fn insert_runtimes<'a>(query_list: &mut Vec<Query<'a, MySql, MySqlArguments>>, pipe: &Pipeline, props: &PipelineRuntime, runtime_id: &str, order: u32, create_time: &str) -> Vec<String> {
+        // 父运行记录, 不执行
+        let mut parent = props.clone();
+        parent.is_matrix = true;
+        parent.parent_id = None;
+        parent.matrix = None;
+        PipelineRunnable::insert_runtime(query_list, pipe, &parent, runtime_id, order, create_time);
+
+        // 子运行记录
+        let mut children: Vec<String> = Vec::new();
+        let combinations = Self::get_combinations(&pipe.options.matrix);
+        for (i, combination) in combinations.iter().enumerate() {
+            let child_id = Uuid::new_v4().to_string();
+            let mut child = props.clone();
+            child.is_matrix = false;
+            child.parent_id = Some(runtime_id.to_string());
+            child.matrix = Some(Self::get_label(combination));
+            child.trigger = None;
+
+            for (name, value) in combination.iter() {
+                // node 版本同步到快照
+                if name.as_str() == "node" {
+                    child.snapshot.node = value.clone();
+                }
+
+                PipelineRunnableStage::set_value_to_variables(&mut child.snapshot.runnable_variables, name, value, "matrix", "矩阵构建变量");
+            }
+
+            PipelineRunnable::insert_runtime(query_list, pipe, &child, &child_id, order + 1 + i as u32, create_time);
+            children.push(child_id);
+        }
+
+        info!("matrix expand {} runtimes, parent runtime: {}", children.len(), runtime_id);
+        children
+    }
+
+    /// 子运行记录放入线程池
+    pub(crate) async fn insert_into_pool(pipeline: &Pipeline, children: &Vec<String>) -> Result<(), String> {
+        for child_id in children.iter() {
+            let result = PipelineRunnable::get_runtime_detail(
+                pipeline,
+                true,
+                Some(PipelineRunnableQueryForm {
+                    status_list: vec![],
+                    runtime_id: Some(child_id.clone()),
+                    need_condition_last_run_id: None,
+                }),
+            )
+            .await?;
+
+            if let Some(runtime) = result.runtime {
+                let mut pipe = pipeline.clone();
+                pipe.runtime = Some(runtime);
+                Pool::insert_into_pool(&pipe)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// 获取子运行记录
+    pub(crate) async fn get_children(pipeline: &Pipeline, parent_id: &str) -> Result<Vec<PipelineRuntime>, String> {
+        let mut pipe = Pipeline::default();
+        pipe.id = pipeline.id.clone();
+        pipe.server_id = pipeline.server_id.clone();
+
+        let result = PipelineRunnable::get_runtime_detail(&pipe, false, None).await?;
+        let mut children: Vec<PipelineRuntime> = result.runtime_list.into_iter().filter(|runtime| runtime.parent_id.as_deref() == Some(parent_id)).collect();
+        children.sort_by(|runtime1, runtime2| runtime1.order.cmp(&runtime2.order));
+        Ok(children)
+    }
+
+    /// 重试失败的子运行记录
+    pub(crate) async fn retry(pipeline: &Pipeline, parent_id: &str, children: &Vec<PipelineRuntime>) -> Result<HttpResponse, String> {
+        let failed: Vec<&PipelineRuntime> = children.iter().filter(|runtime| matches!(runtime.status, PipelineStatus::Failed | PipelineStatus::Stop)).collect();
+        if failed.is_empty() {
+            return Ok(get_error_response("重试失败, 没有需要重试的矩阵构建任务"));
+        }
+
+        let query = sqlx::query::<MySql>("UPDATE pipeline_runtime SET `status` = ?, finished = 'false' WHERE id = ?")
+            .bind(PipelineStatus::got(PipelineStatus::Queue))
+            .bind(parent_id);
+        let response = DBHelper::execute_update(query).await?;
+        if response.code != 200 {
+            return Ok(response);
+        }
+
+        let mut response = HttpResponse::default();
+        for runtime in failed.iter() {
+            let runtime_id = runtime.id.clone().unwrap_or(String::new());
+            response = PipelineRunnable::retry(pipeline, &runtime_id).await?;
+            if response.code != 200 {
+                return Ok(response);
+            }
+        }
+
+        Ok(response)
+    }
+
+    /// 子运行记录状态变化后, 更新父运行记录的汇总状态
+    pub(crate) async fn update_parent(app: &AppHandle, pipeline: &Pipeline, parent_id: &str) {
+        let _lock = MATRIX_LOCK.lock().await;
+        if let Err(err) = Self::exec_update_parent(app, pipeline, parent_id).await {
+            error!("update matrix parent runtime {} error: {}", parent_id, err);
+        }
+    }
+
+    async fn exec_update_parent(app: &AppHandle, pipeline: &Pipeline, parent_id: &str) -> Result<(), String> {
+        let result = PipelineRunnable::get_runtime_detail(
+            pipeline,
+            true,
+            Some(PipelineRunnableQueryForm {
+                status_list: vec![],
+                runtime_id: Some(parent_id.to_string()),
+                need_condition_last_run_id: None,
+            }),
+        )
+        .await?;
+
+        let mut parent = match result.runtime {
+            Some(parent) => parent,
+            None => return Err(Error::convert_string(&format!("can not find matrix parent runtime: {}", parent_id))),
+        };
+
+        let children = Self::get_children(pipeline, parent_id).await?;
+        if children.is_empty() {
+            return Ok(());
+        }
+
+        let status = Self::get_status(&children);
+        let changed = PipelineStatus::got(parent.status.clone()) != PipelineStatus::got(status.clone());
+        let finished = matches!(status, PipelineStatus::Success | PipelineStatus::Failed);
+        let start_time = children.iter().filter_map(|runtime| runtime.start_time.clone()).filter(|time| !time.is_empty()).min();
+        let log_dir = Logger::get_log_dir(vec![pipeline.server_id.clone(), pipeline.id.clone()]).map(|dir| dir.as_path().to_string_lossy().to_string());
+
+        // 1. 更新 pipeline 表中的 status
+        // 2. 更新父运行记录的 status, finished, start_time, log
+        let mut query_list = Vec::new();
+        let pipeline_query = sqlx::query::<MySql>("UPDATE pipeline SET `status` = ? WHERE id = ? AND last_run_id = ?")
+            .bind(PipelineStatus::got(status.clone()))
+            .bind(&pipeline.id)
+            .bind(parent_id);
+        query_list.push(pipeline_query);
+
+        let runtime_query = sqlx::query::<MySql>("UPDATE pipeline_runtime SET `status` = ?, finished = ?, start_time = ?, log = ? WHERE id = ?")
+            .bind(PipelineStatus::got(status.clone()))
+            .bind(format!("{}", finished))
+            .bind(start_time.clone())
+            .bind(log_dir)
+            .bind(parent_id);
+        query_list.push(runtime_query);
+
+        let response = DBHelper::batch_commit(query_list).await?;
+        if response.code != 200 {
+            return Err(response.error);
+        }
+
+        // 记录子运行记录结果到父运行记录日志
+        let order = parent.order.unwrap_or(1);
+        if let Some(runtime) = &pipeline.runtime {
+            let msg = format!("matrix run [{}] #{} {}", runtime.matrix.clone().unwrap_or(String::new()), runtime.order.unwrap_or(1), PipelineStatus::got(runtime.status.clone()));
+            PipelineRunnable::save_log(app, &msg, &pipeline.server_id, &pipeline.id, order);
+        }
+
+        if changed {
+            let msg = format!("matrix runtime status: {}", PipelineStatus::got(status.clone()));
+            PipelineRunnable::save_log(app, &msg, &pipeline.server_id, &pipeline.id, order);
+        }
+
+        parent.status = status.clone();
+        parent.start_time = start_time;
+        parent.stage.finished = finished;
+        parent.children = children;
+
+        let mut pipe = pipeline.clone();
+        pipe.status = Some(status.clone());
+        pipe.runtime = Some(parent);
+        EventEmitter::log_step_res(app, Some(get_success_response_by_value(pipe.clone()).unwrap_or(HttpResponse::default())));
+
+        // 全部结束后触发下游流水线
+        if changed && finished {
+            PipelineTrigger::exec_downstream(app, &pipe).await;
+        }
+
+        Ok(())
+    }
+
+    /// 把子运行记录放到对应的父运行记录下
+    pub(crate) fn fill_children(list: &mut Vec<PipelineRuntime>) {
+        let parent_ids: HashSet<String> = list.iter().filter(|runtime| runtime.is_matrix).filter_map(|runtime| runtime.id.clone()).collect();
+        if parent_ids.is_empty() {
+            return;
+        }
+
+        let mut children: Vec<PipelineRuntime> = Vec::new();
+        list.retain(|runtime| {
+            if let Some(parent_id) = &runtime.parent_id {
+                if parent_ids.contains(parent_id) {
+                    children.push(runtime.clone());
+                    return false;
+                }
+            }
+
+            true
+        });
+
+        children.sort_by(|runtime1, runtime2| runtime1.order.cmp(&runtime2.order));
+        for runtime in list.iter_mut() {
+            if !runtime.is_matrix {
+                continue;
+            }
+
+            runtime.children = children.iter().filter(|child| child.parent_id == runtime.id).cloned().collect();
+        }
+    }
+
+    /// 获取工作目录名称, 相同的变量组合共用一个目录
+    pub(crate) fn get_workspace_name(label: &str) -> String {
+        label.chars().map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '-' { c } else { '_' }).collect()
+    }
+
+    /// 汇总状态: 全部成功为成功, 全部结束且有失败为失败, 否则为运行中(包括等待审批)
+    fn get_status(children: &Vec<PipelineRuntime>) -> PipelineStatus {
+        let running = children
+            .iter()
+            .any(|runtime| matches!(runtime.status, PipelineStatus::Queue | PipelineStatus::Process | PipelineStatus::Waiting | PipelineStatus::No));
+        if running {
+            let started = children.iter().any(|runtime| !matches!(runtime.status, PipelineStatus::Queue | PipelineStatus::No));
+            return if started { PipelineStatus::Process } else { PipelineStatus::Queue };
+        }
+
+        let failed = children.iter().any(|runtime| matches!(runtime.status, PipelineStatus::Failed | PipelineStatus::Stop));
+        if failed {
+            return PipelineStatus::Failed;
+        }
+
+        PipelineStatus::Success
+    }
+
+    /// 计算所有变量组合
+    fn get_combinations(axes: &Vec<PipelineMatrixAxis>) -> Vec<Vec<(String, String)>> {
+        let mut combinations: Vec<Vec<(String, String)>> = vec![Vec::new()];
+        for axis in axes.iter() {
+            if axis.name.is_empty() || axis.values.is_empty() {
+                continue;
+            }
+
+            let mut list: Vec<Vec<(String, String)>> = Vec::new();
+            for combination in combinations.iter() {
+                for value in axis.values.iter() {
+                    let mut item = combination.clone();
+                    item.push((axis.name.clone(), value.clone()));
+                    list.push(item);
+                }
+            }
+
+            combinations = list;
+        }
+
+        combinations
+    }
+
+    /// 变量组合名称, 如 node=18, env=test
+    fn get_label(combination: &Vec<(String, String)>) -> String {
+        combination.iter().map(|(name, value)| format!("{}={}", name, value)).collect::<Vec<String>>().join(", ")
+    }
+}
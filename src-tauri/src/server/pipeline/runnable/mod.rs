@@ -1,17 +1,33 @@
 //! 流水线运行
 
+pub(crate) mod approval;
+pub(crate) mod cache;
+pub(crate) mod compose;
+pub(crate) mod diff;
+pub(crate) mod kubernetes;
+pub(crate) mod matrix;
+pub(crate) mod notification;
+pub(crate) mod replay;
 pub(crate) mod stage;
+pub(crate) mod stats;
+pub(crate) mod trigger;
 
 use crate::database::helper::DBHelper;
 use crate::database::interface::Treat;
 use crate::error::Error;
 use crate::event::EventEmitter;
 use crate::helper::git::GitHandler;
-use crate::logger::pipeline::PipelineLogger;
+use crate::helper::index::Helper;
+use crate::logger::pipeline::{PipelineLogLevel, PipelineLogStream, PipelineLogger};
 use crate::prepare::{get_error_response, get_success_response, get_success_response_by_value, HttpResponse};
 use crate::server::pipeline::index::Pipeline;
 use crate::server::pipeline::pool::Pool;
-use crate::server::pipeline::props::{PipelineBasic, PipelineRuntime, PipelineRuntimeSnapshot, PipelineRuntimeStage, PipelineRuntimeVariable, PipelineStage, PipelineStatus, PipelineTag};
+use crate::server::pipeline::props::{
+    PipelineBasic, PipelineCommandStatus, PipelineRunnableStageStep, PipelineRuntime, PipelineRuntimeApproval, PipelineRuntimeCommit, PipelineRuntimeLink, PipelineRuntimeSnapshot, PipelineRuntimeStage, PipelineRuntimeVariable, PipelineStage,
+    PipelineStatus, PipelineTag,
+};
+use crate::server::pipeline::runnable::matrix::PipelineMatrix;
+use crate::server::pipeline::runnable::trigger::PipelineTrigger;
 use handlers::utils::Utils;
 use lazy_static::lazy_static;
 use log::{error, info};
@@ -89,6 +105,7 @@ impl PipelineRunnable {
         );
         query_list.push(sqlx::query::<MySql>(&variable_sql).bind(&pipeline_id));
         query_list.push(sqlx::query::<MySql>(&snapshot_sql).bind(&pipeline_id));
+        query_list.push(sqlx::query::<MySql>("DELETE FROM pipeline_runtime_step WHERE pipeline_id = ?").bind(pipeline_id));
         query_list.push(sqlx::query::<MySql>("DELETE FROM pipeline_runtime WHERE pipeline_id = ?").bind(pipeline_id));
         DBHelper::batch_commit(query_list).await
     }
@@ -113,7 +130,13 @@ impl PipelineRunnable {
             return Ok(get_error_response("查询历史记录失败, `serverId` 为空"));
         }
 
-        let result = Self::get_runtime_detail(pipeline, false, None).await?;
+        let mut result = Self::get_runtime_detail(pipeline, false, None).await?;
+
+        // 矩阵构建的子运行记录放到父运行记录下
+        PipelineMatrix::fill_children(&mut result.runtime_list);
+
+        // 上下游链路
+        PipelineTrigger::fill_runtime_links(&mut result.runtime_list).await?;
         get_success_response_by_value(result.runtime_list)
     }
 
@@ -152,17 +175,35 @@ impl PipelineRunnable {
                     r.start_time AS runtime_start_time,
                     r.remark as runtime_remark,
                     r.duration AS runtime_duration,
+                    CAST( r.duration_ms AS UNSIGNED ) AS runtime_duration_ms,
                     CAST( r.stage_index AS UNSIGNED ) AS runtime_stage_index,
                     CAST( r.group_index AS UNSIGNED ) AS runtime_group_index,
                     CAST( r.step_index AS UNSIGNED ) AS runtime_step_index,
                     r.finished AS runtime_finished,
                     r.log AS runtime_log,
+                    r.trigger_runtime_id AS runtime_trigger_runtime_id,
+                    r.trigger_pipeline_id AS runtime_trigger_pipeline_id,
+                    r.parent_runtime_id AS runtime_parent_runtime_id,
+                    r.matrix AS runtime_matrix,
+                    r.is_matrix AS runtime_is_matrix,
+                    r.priority AS runtime_priority,
+                    r.interrupted AS runtime_interrupted,
+                    r.replay_runtime_id AS runtime_replay_runtime_id,
+                    r.approval_result AS runtime_approval_result,
+                    r.approver AS runtime_approver,
+                    r.approval_comment AS runtime_approval_comment,
+                    CAST( r.approval_deadline AS SIGNED ) AS runtime_approval_deadline,
+                    r.approval_time AS runtime_approval_time,
+                    r.commit_sha AS runtime_commit_sha,
+                    r.commit_author AS runtime_commit_author,
+                    r.commit_message AS runtime_commit_message,
                     r.create_time as runtime_create_time,
                     r.update_time as runtime_update_time,
                     s.id as runtime_snapshot_id,
                     s.runtime_id as runtime_snapshot_runtime_id,
                     s.node as runtime_snapshot_node,
                     s.branch as runtime_snapshot_branch,
+                    s.revision as runtime_snapshot_revision,
                     s.make as runtime_snapshot_make,
                     s.command as runtime_snapshot_command,
                     s.script as runtime_snapshot_script,
@@ -190,15 +231,18 @@ impl PipelineRunnable {
         if let Some(query_form) = query_form.clone() {
             if let Some(need_condition_last_run_id) = query_form.need_condition_last_run_id {
                 if need_condition_last_run_id {
-                    sql.push_str(" INNER JOIN pipeline p on p.last_run_id = r.id ");
+                    sql.push_str(" INNER JOIN pipeline p on (p.last_run_id = r.id OR p.last_run_id = r.parent_runtime_id) ");
                 }
             }
         }
 
         sql.push_str(" WHERE 1 = 1");
 
+        // 参数均通过占位符绑定, 流水线 ID、运行记录 ID 等可能来自前端
+        let mut params: Vec<String> = Vec::new();
         if !pipeline.id.is_empty() {
-            sql.push_str(&format!(" AND r.pipeline_id = '{}'", pipeline.id));
+            sql.push_str(" AND r.pipeline_id = ?");
+            params.push(pipeline.id.clone());
         }
 
         if let Some(query_form) = query_form {
@@ -207,7 +251,8 @@ impl PipelineRunnable {
             if status_list.len() > 0 {
                 let mut status_condition_sql = String::from(" AND (");
                 for (size, s) in status_list.iter().enumerate() {
-                    status_condition_sql.push_str(&format!(" r.status = '{}'", s));
+                    status_condition_sql.push_str(" r.status = ?");
+                    params.push(s.clone());
                     if size != status_list.len() - 1 {
                         status_condition_sql.push_str(" OR ");
                     }
@@ -218,13 +263,17 @@ impl PipelineRunnable {
 
             // runtime_id
             if let Some(runtime_id) = query_form.runtime_id.clone() {
-                sql.push_str(&format!(" AND r.id = '{}'", runtime_id));
+                sql.push_str(" AND r.id = ?");
+                params.push(runtime_id);
             }
         }
 
         sql.push_str(" ORDER BY r.start_time DESC");
 
-        let query = sqlx::query(&sql);
+        let mut query = sqlx::query(&sql);
+        for param in params.iter() {
+            query = query.bind(param);
+        }
         let rows = DBHelper::execute_rows(query).await?;
         if rows.is_empty() {
             return Ok(PipelineRuntimeResult::default());
@@ -248,6 +297,9 @@ impl PipelineRunnable {
             let stages_str = row.try_get("runtime_stages").unwrap_or(String::new());
             let basic: PipelineBasic = serde_json::from_str(&basic_str).unwrap_or(PipelineBasic::default());
             let stages: Vec<PipelineStage> = serde_json::from_str(&stages_str).unwrap_or(Vec::new());
+            let trigger_runtime_id: String = row.try_get("runtime_trigger_runtime_id").unwrap_or(String::new());
+            let parent_runtime_id: String = row.try_get("runtime_parent_runtime_id").unwrap_or(String::new());
+            let is_matrix_str: String = row.try_get("runtime_is_matrix").unwrap_or(String::new());
 
             map.entry(runtime_id.clone()).or_insert_with(|| PipelineRuntime {
                 id: Some(runtime_id.clone()),
@@ -267,9 +319,34 @@ impl PipelineRunnable {
                 status: PipelineStatus::get(&status_str),
                 start_time: row.try_get("runtime_start_time").unwrap_or(None),
                 duration: row.try_get("runtime_duration").unwrap_or(None),
+                duration_ms: row.try_get("runtime_duration_ms").unwrap_or(None),
                 snapshot: Default::default(),
                 log: row.try_get("runtime_log").unwrap_or(None),
                 remark: row.try_get("runtime_remark").unwrap_or(String::new()),
+                trigger: if trigger_runtime_id.is_empty() {
+                    None
+                } else {
+                    Some(PipelineRuntimeLink {
+                        runtime_id: trigger_runtime_id.clone(),
+                        pipeline_id: row.try_get("runtime_trigger_pipeline_id").unwrap_or(String::new()),
+                        ..Default::default()
+                    })
+                },
+                downstream: Vec::new(),
+                parent_id: if parent_runtime_id.is_empty() { None } else { Some(parent_runtime_id.clone()) },
+                matrix: row.try_get("runtime_matrix").unwrap_or(None),
+                is_matrix: is_matrix_str.trim() == "true",
+                priority: row.try_get::<Option<i32>, _>("runtime_priority").unwrap_or(None).unwrap_or(0),
+                interrupted: row.try_get::<Option<String>, _>("runtime_interrupted").unwrap_or(None).unwrap_or(String::new()).trim() == "true",
+                replay_id: row.try_get("runtime_replay_runtime_id").unwrap_or(None),
+                approval: PipelineRuntimeApproval::from_row(row, "runtime_"),
+                children: Vec::new(),
+                commit: PipelineRuntimeCommit {
+                    sha: row.try_get("runtime_commit_sha").unwrap_or(String::new()),
+                    author: row.try_get("runtime_commit_author").unwrap_or(String::new()),
+                    message: row.try_get("runtime_commit_message").unwrap_or(String::new()),
+                },
+                progress: None,
                 create_time: row.try_get("runtime_create_time").unwrap_or(None),
                 update_time: row.try_get("runtime_update_time").unwrap_or(None),
             });
@@ -281,6 +358,7 @@ impl PipelineRunnable {
                 runtime_id: row.try_get("runtime_snapshot_runtime_id").unwrap_or(String::new()),
                 node: row.try_get("runtime_snapshot_node").unwrap_or(String::new()),
                 branch: row.try_get("runtime_snapshot_branch").unwrap_or(String::new()),
+                revision: row.try_get("runtime_snapshot_revision").unwrap_or(String::new()),
                 make: row.try_get("runtime_snapshot_make").unwrap_or(None),
                 command: row.try_get("runtime_snapshot_command").unwrap_or(String::new()),
                 script: row.try_get("runtime_snapshot_script").unwrap_or(String::new()),
@@ -380,7 +458,7 @@ impl PipelineRunnable {
             &pipeline,
             true,
             Some(PipelineRunnableQueryForm {
-                status_list: vec![PipelineStatus::got(PipelineStatus::Queue), PipelineStatus::got(PipelineStatus::Process)],
+                status_list: vec![PipelineStatus::got(PipelineStatus::Queue), PipelineStatus::got(PipelineStatus::Process), PipelineStatus::got(PipelineStatus::Waiting)],
                 runtime_id: props.id.clone(),
                 need_condition_last_run_id: None,
             }),
@@ -402,9 +480,66 @@ impl PipelineRunnable {
                 return Ok(get_error_response("运行流水线失败, `runtime id` 为空"));
             }
 
+            // 矩阵构建, 重试失败的子运行记录
+            let children = PipelineMatrix::get_children(&pipeline, &runtime_id).await?;
+            if !children.is_empty() {
+                return PipelineMatrix::retry(&pipeline, &runtime_id, &children).await;
+            }
+
             return Self::retry(&pipeline, &runtime_id).await;
         }
 
+        let order = Self::get_max_order(&pipe.id).await?;
+
+        // 插入到数据库
+        let mut query_list: Vec<Query<MySql, MySqlArguments>> = Vec::new();
+        let runtime_id = Uuid::new_v4().to_string();
+        let create_time = Utils::get_date(None);
+
+        // 更新 pipeline 表 status
+        let pipeline_query = sqlx::query::<MySql>(
+            r#"
+            UPDATE pipeline SET `status` = ?, last_run_id = ? WHERE id = ?
+        "#,
+        )
+        .bind(&status)
+        .bind(&runtime_id)
+        .bind(&pipe.id);
+        query_list.push(pipeline_query);
+
+        // 插入到 pipeline_runtime 表, 矩阵构建时插入父运行记录及所有子运行记录
+        let mut children: Vec<String> = Vec::new();
+        if pipe.options.matrix.is_empty() {
+            Self::insert_runtime(&mut query_list, pipe, props, &runtime_id, order + 1, &create_time);
+        } else {
+            children = PipelineMatrix::insert_runtimes(&mut query_list, pipe, props, &runtime_id, order + 1, &create_time);
+        }
+
+        let response = DBHelper::batch_commit(query_list).await?;
+        if response.code != 200 {
+            return Ok(response);
+        }
+
+        // 查询数据, 并插入到线程池
+        let response = Pipeline::get_by_id(&pipeline).await?;
+        if response.code != 200 {
+            return Ok(response);
+        }
+
+        let pipe: Pipeline = serde_json::from_value(response.body).map_err(|err| Error::Error(err.to_string()).to_string())?;
+        info!("pipe: {:#?}", pipe);
+        // thread::sleep(Duration::from_secs(1000000));
+        if children.is_empty() {
+            Pool::insert_into_pool(&pipe)?;
+        } else {
+            PipelineMatrix::insert_into_pool(&pipe, &children).await?;
+        }
+
+        Ok(get_success_response_by_value(pipe).unwrap_or(HttpResponse::default()))
+    }
+
+    /// 查询运行记录最大序号
+    pub(crate) async fn get_max_order(pipeline_id: &str) -> Result<u32, String> {
         info!("query max order ...");
         // 查询 pipeline_runtime order
         let runtime_order_query = sqlx::query(
@@ -412,7 +547,7 @@ impl PipelineRunnable {
             select MAX(CAST(`order` AS UNSIGNED)) AS max_order FROM pipeline_runtime WHERE pipeline_id = ?
           "#,
         )
-        .bind(&pipe.id);
+        .bind(pipeline_id);
 
         let mut order: u32 = 1;
         let rows = DBHelper::execute_rows(runtime_order_query).await?;
@@ -425,48 +560,67 @@ impl PipelineRunnable {
         }
 
         info!("max order: {}", order);
+        Ok(order)
+    }
 
-        // 插入到数据库
-        let mut query_list: Vec<Query<MySql, MySqlArguments>> = Vec::new();
-        let stage = &props.stage;
-        let runtime_id = Uuid::new_v4().to_string();
-        let create_time = Utils::get_date(None);
-
-        // 更新 pipeline 表 status
-        let pipeline_query = sqlx::query::<MySql>(
+    /// 查询未结束(排队中、运行中、等待审批)的运行记录序号
+    pub(crate) async fn get_active_orders(pipeline_id: &str) -> Result<Vec<u32>, String> {
+        let query = sqlx::query(
             r#"
-            UPDATE pipeline SET `status` = ?, last_run_id = ? WHERE id = ?
-        "#,
+            SELECT CAST(`order` AS UNSIGNED) AS runtime_order FROM pipeline_runtime WHERE pipeline_id = ? AND `status` IN (?, ?, ?)
+          "#,
         )
-        .bind(&status)
-        .bind(&runtime_id)
-        .bind(&pipe.id);
-        query_list.push(pipeline_query);
+        .bind(pipeline_id)
+        .bind(PipelineStatus::got(PipelineStatus::Queue))
+        .bind(PipelineStatus::got(PipelineStatus::Process))
+        .bind(PipelineStatus::got(PipelineStatus::Waiting));
+
+        let rows = DBHelper::execute_rows(query).await?;
+        Ok(rows.iter().filter_map(|row| row.try_get::<u32, _>("runtime_order").ok()).collect())
+    }
 
-        // 插入到 pipeline_runtime 表
+    /// 日志保留策略, 跳过未结束的运行记录
+    pub(crate) async fn retain_log(server_id: &str, id: &str) {
+        match Self::get_active_orders(id).await {
+            Ok(orders) => PipelineLogger::retain_log(server_id, id, &orders),
+            Err(err) => error!("get pipeline `{}` active runtime orders error: {}", id, err),
+        }
+    }
+
+    /// 插入运行记录、快照及启动变量
+    pub(crate) fn insert_runtime<'a>(query_list: &mut Vec<Query<'a, MySql, MySqlArguments>>, pipe: &Pipeline, props: &PipelineRuntime, runtime_id: &str, order: u32, create_time: &str) {
+        let stage = &props.stage;
         let basic_str = serde_json::to_string(&pipe.basic).unwrap_or(String::from(""));
         let stages_str = serde_json::to_string(&pipe.process_config.stages).unwrap_or(String::from(""));
         let process_query = sqlx::query::<MySql>(
             r#"
             INSERT INTO pipeline_runtime (
-                id, pipeline_id, project_name, `order`, tag, basic, stages, `status`, stage_index, group_index, step_index, finished, remark, create_time, update_time
-            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                id, pipeline_id, project_name, `order`, tag, basic, stages, `status`, stage_index, group_index, step_index, finished, remark,
+                trigger_runtime_id, trigger_pipeline_id, parent_runtime_id, matrix, is_matrix, priority, replay_runtime_id, create_time, update_time
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
         "#,
         )
-        .bind(runtime_id.clone())
-        .bind(&pipe.id)
+        .bind(runtime_id.to_string())
+        .bind(pipe.id.clone())
         .bind(GitHandler::get_project_name_by_git(&pipe.basic.path))
-        .bind(format!("{}", order + 1))
+        .bind(format!("{}", order))
         .bind(PipelineTag::got(props.tag.clone()))
         .bind(basic_str) // basic
         .bind(stages_str) // stages
-        .bind(&status) // 排队中
+        .bind(PipelineStatus::got(PipelineStatus::Queue)) // 排队中
         .bind(format!("{}", stage.stage_index.clone()))
         .bind(format!("{}", stage.group_index.clone()))
         .bind(format!("{}", stage.step_index.clone()))
         .bind("false")
-        .bind(&props.remark)
-        .bind(&create_time)
+        .bind(props.remark.clone())
+        .bind(props.trigger.as_ref().map(|trigger| trigger.runtime_id.clone()))
+        .bind(props.trigger.as_ref().map(|trigger| trigger.pipeline_id.clone()))
+        .bind(props.parent_id.clone())
+        .bind(props.matrix.clone())
+        .bind(format!("{}", props.is_matrix))
+        .bind(props.priority)
+        .bind(props.replay_id.clone())
+        .bind(create_time.to_string())
         .bind("");
         query_list.push(process_query);
 
@@ -476,83 +630,65 @@ impl PipelineRunnable {
         let snapshot_query = sqlx::query::<MySql>(
             r#"
             INSERT INTO pipeline_runtime_snapshot (
-                id, runtime_id, node, branch, make, command, script, create_time, update_time
-            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+                id, runtime_id, node, branch, revision, make, command, script, create_time, update_time
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
         "#,
         )
         .bind(snapshot_id.clone())
-        .bind(runtime_id.clone())
-        .bind(&snapshot.node)
-        .bind(&snapshot.branch)
-        .bind(&snapshot.make)
-        .bind(&snapshot.command)
-        .bind(&snapshot.script)
-        .bind(&create_time)
+        .bind(runtime_id.to_string())
+        .bind(snapshot.node.clone())
+        .bind(snapshot.branch.clone())
+        .bind(snapshot.revision.clone())
+        .bind(snapshot.make.clone())
+        .bind(snapshot.command.clone())
+        .bind(snapshot.script.clone())
+        .bind(create_time.to_string())
         .bind("");
         query_list.push(snapshot_query);
 
         // 插入 pipeline_runtime_variable 表
-        let runnable_variables = &snapshot.runnable_variables;
-        if !runnable_variables.is_empty() {
-            for variable in runnable_variables.iter() {
-                let variable_query = sqlx::query::<MySql>(
-                    r#"
+        for variable in snapshot.runnable_variables.iter() {
+            let variable_query = sqlx::query::<MySql>(
+                r#"
             INSERT INTO pipeline_runtime_variable (
                 id, snapshot_id, `order`, name, `value`, genre, `require`, disabled, description, create_time, update_time
             ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
         "#,
-                )
-                .bind(Uuid::new_v4().to_string())
-                .bind(snapshot_id.clone())
-                .bind(&variable.order)
-                .bind(&variable.name)
-                .bind(&variable.value)
-                .bind(&variable.genre)
-                .bind(&variable.require)
-                .bind(&variable.disabled)
-                .bind(&variable.description)
-                .bind(&create_time)
-                .bind("");
-                query_list.push(variable_query);
-            }
-        }
-
-        let response = DBHelper::batch_commit(query_list).await?;
-        if response.code != 200 {
-            return Ok(response);
-        }
-
-        // 查询数据, 并插入到线程池
-        let response = Pipeline::get_by_id(&pipeline).await?;
-        if response.code != 200 {
-            return Ok(response);
+            )
+            .bind(Uuid::new_v4().to_string())
+            .bind(snapshot_id.clone())
+            .bind(variable.order)
+            .bind(variable.name.clone())
+            .bind(variable.value.clone())
+            .bind(variable.genre.clone())
+            .bind(variable.require.clone())
+            .bind(variable.disabled.clone())
+            .bind(variable.description.clone())
+            .bind(create_time.to_string())
+            .bind("");
+            query_list.push(variable_query);
         }
-
-        let pipe: Pipeline = serde_json::from_value(response.body).map_err(|err| Error::Error(err.to_string()).to_string())?;
-        info!("pipe: {:#?}", pipe);
-        // thread::sleep(Duration::from_secs(1000000));
-        Pool::insert_into_pool(&pipe)?;
-        Ok(get_success_response_by_value(pipe).unwrap_or(HttpResponse::default()))
     }
 
     /// 错误阶段重试
-    async fn retry(pipeline: &Pipeline, runtime_id: &str) -> Result<HttpResponse, String> {
+    pub(crate) async fn retry(pipeline: &Pipeline, runtime_id: &str) -> Result<HttpResponse, String> {
         let status = PipelineStatus::got(PipelineStatus::Queue); // 排队中
         let mut query_list: Vec<Query<MySql, MySqlArguments>> = Vec::new();
 
         // 更新 pipeline 表 status
         let pipeline_query = sqlx::query::<MySql>(
             r#"
-                    UPDATE pipeline SET `status` = ?
+                    UPDATE pipeline SET `status` = ? WHERE id = ?
                 "#,
         )
-        .bind(&status);
+        .bind(&status)
+        .bind(&pipeline.id);
         query_list.push(pipeline_query);
 
-        // 更新 pipeline_runtime 表中的 status, start_time = '', duration = ''
+        // 更新 pipeline_runtime 表中的 status, start_time = '', duration = '', duration_ms = NULL
         let runtime_query = sqlx::query::<MySql>(
             r#"
-                    UPDATE pipeline_runtime SET `status` = ?, start_time = '', duration = '' WHERE id = ?
+                    UPDATE pipeline_runtime SET `status` = ?, start_time = '', duration = '', duration_ms = NULL WHERE id = ?
                 "#,
         )
         .bind(&status)
@@ -570,7 +706,24 @@ impl PipelineRunnable {
             return Ok(response);
         }
 
-        let pipe: Pipeline = serde_json::from_value(response.body).map_err(|err| Error::Error(err.to_string()).to_string())?;
+        let mut pipe: Pipeline = serde_json::from_value(response.body).map_err(|err| Error::Error(err.to_string()).to_string())?;
+
+        // 重试的运行记录可能不是最后一次运行记录(如矩阵构建的子运行记录)
+        let result = Self::get_runtime_detail(
+            &pipe,
+            true,
+            Some(PipelineRunnableQueryForm {
+                status_list: vec![],
+                runtime_id: Some(runtime_id.to_string()),
+                need_condition_last_run_id: None,
+            }),
+        )
+        .await?;
+
+        if let Some(runtime) = result.runtime {
+            pipe.runtime = Some(runtime);
+        }
+
         info!("retry pipe: {:#?}", pipe);
         Pool::insert_into_pool(&pipe)?;
         Ok(get_success_response_by_value(pipe).unwrap_or(HttpResponse::default()))
@@ -620,16 +773,20 @@ impl PipelineRunnable {
         let runtime = &pipeline.clone().runtime.unwrap_or(PipelineRuntime::default());
         let order = runtime.order.unwrap_or(1);
         let msg = format!("{} {} !", msg, err);
-        Self::save_log(app, &msg, &pipeline.server_id, &pipeline.id, order);
+        let level = if success { PipelineLogLevel::Info } else { PipelineLogLevel::Error };
+        Self::save_log_by_level(app, &msg, &pipeline.server_id, &pipeline.id, order, level, PipelineLogStream::System);
 
         let result = Self::update_stage(pipeline, runtime).await;
         return match result {
             Ok(_) => {
                 EventEmitter::log_step_res(app, Some(get_success_response_by_value(pipeline.clone()).unwrap()));
+
+                // 触发下游流水线
+                PipelineTrigger::exec_downstream(app, pipeline).await;
                 Some(pipeline.clone())
             }
             Err(err) => {
-                Self::save_log(app, &err, &pipeline.server_id, &pipeline.id, order);
+                Self::save_log_by_level(app, &err, &pipeline.server_id, &pipeline.id, order, PipelineLogLevel::Error, PipelineLogStream::System);
                 EventEmitter::log_step_res(app, Some(get_error_response(&err)));
                 None
             }
@@ -662,6 +819,10 @@ impl PipelineRunnable {
             runtime_sql.push_str(&format!(", duration = '{}'", duration));
         }
 
+        if let Some(duration_ms) = runtime.duration_ms {
+            runtime_sql.push_str(&format!(", duration_ms = {}", duration_ms));
+        }
+
         runtime_sql.push_str(" WHERE id = ?");
 
         let runtime_query = sqlx::query::<MySql>(&runtime_sql)
@@ -675,9 +836,64 @@ impl PipelineRunnable {
         DBHelper::batch_commit(query_list).await
     }
 
+    /// 保存步骤运行耗时, 用于统计
+    pub(crate) async fn save_step_duration(pipeline: &Pipeline, step: &PipelineRunnableStageStep, success: bool, duration_ms: u64) {
+        let runtime_id = pipeline.runtime.as_ref().and_then(|runtime| runtime.id.clone()).unwrap_or(String::new());
+        if runtime_id.is_empty() {
+            return;
+        }
+
+        let status = if success { PipelineStatus::Success } else { PipelineStatus::Failed };
+        let create_time = Utils::get_date(None);
+        let query = sqlx::query::<MySql>(
+            r#"
+            INSERT INTO pipeline_runtime_step (id, runtime_id, pipeline_id, stage_index, group_index, step_index, module, label, `status`, duration_ms, create_time)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+        )
+        .bind(Uuid::new_v4().to_string())
+        .bind(runtime_id)
+        .bind(&pipeline.id)
+        .bind(step.stage_index)
+        .bind(step.group_index)
+        .bind(step.step_index)
+        .bind(PipelineCommandStatus::got(step.step.module.clone()))
+        .bind(&step.step.label)
+        .bind(PipelineStatus::got(status))
+        .bind(duration_ms)
+        .bind(create_time);
+
+        if let Err(err) = DBHelper::execute_update(query).await {
+            error!("save step duration error: {}", err);
+        }
+    }
+
+    /// 保存本次运行构建的提交
+    pub(crate) async fn update_commit(runtime_id: &str, commit: &PipelineRuntimeCommit) -> Result<HttpResponse, String> {
+        let query = sqlx::query::<MySql>("UPDATE pipeline_runtime SET commit_sha = ?, commit_author = ?, commit_message = ? WHERE id = ?")
+            .bind(commit.sha.clone())
+            .bind(commit.author.clone())
+            .bind(commit.message.clone())
+            .bind(runtime_id.to_string());
+        DBHelper::execute_update(query).await
+    }
+
     /// 保存日志, 发送消息到前端
     pub(crate) fn save_log(app: &AppHandle, msg: &str, server_id: &str, id: &str, order: u32) {
+        Self::save_log_by_level(app, msg, server_id, id, order, PipelineLogLevel::Info, PipelineLogStream::System);
+    }
+
+    /// 保存命令输出日志, 区分标准输出和标准错误
+    pub(crate) fn save_output_log(app: &AppHandle, msg: &str, server_id: &str, id: &str, order: u32) {
+        if Helper::is_stderr_output() {
+            Self::save_log_by_level(app, msg, server_id, id, order, PipelineLogLevel::Warn, PipelineLogStream::Stderr);
+        } else {
+            Self::save_log_by_level(app, msg, server_id, id, order, PipelineLogLevel::Info, PipelineLogStream::Stdout);
+        }
+    }
+
+    fn save_log_by_level(app: &AppHandle, msg: &str, server_id: &str, id: &str, order: u32, level: PipelineLogLevel, stream: PipelineLogStream) {
         EventEmitter::log_event(app, id, msg);
-        PipelineLogger::save_log(msg, server_id, id, order);
+        PipelineLogger::save_log(msg, server_id, id, order, level, stream);
     }
 }
@@ -0,0 +1,133 @@
+//! 运行结束系统通知
+//! 桌面端通知插件无法回调通知的点击事件, 只能在通知后短时间内回到应用时跳转到运行详情, 超时后不再跳转
+
+use crate::event::EventEmitter;
+use crate::prepare::get_success_response_by_value;
+use crate::server::pipeline::index::Pipeline;
+use crate::server::pipeline::props::PipelineStatus;
+use crate::setting::Settings;
+use lazy_static::lazy_static;
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager};
+use tauri_plugin_notification::NotificationExt;
+
+// 通知后回到应用时跳转的有效时间, 超时后视为与通知无关
+const PENDING_TARGET_EXPIRE_SECONDS: u64 = 10;
+
+lazy_static! {
+    // 最近一次通知的运行记录及通知时间, 有效时间内回到应用时打开
+    static ref PENDING_TARGET: Mutex<Option<(PipelineNotificationTarget, Instant)>> = Mutex::new(None);
+}
+
+/// 通知对应的运行记录
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct PipelineNotificationTarget {
+    #[serde(rename = "serverId")]
+    pub(crate) server_id: String,
+    pub(crate) id: String,
+    #[serde(rename = "runtimeId")]
+    pub(crate) runtime_id: String,
+}
+
+pub struct PipelineNotification;
+
+impl PipelineNotification {
+    /// 运行结束时发送系统通知, 窗口在前台时不通知
+    pub(crate) fn notify(app: &AppHandle, pipeline: &Pipeline) {
+        let runtime = match &pipeline.runtime {
+            Some(runtime) => runtime,
+            None => return,
+        };
+
+        let success = matches!(runtime.status, PipelineStatus::Success);
+        if !success && !matches!(runtime.status, PipelineStatus::Failed) {
+            return;
+        }
+
+        if !Self::is_enabled() || pipeline.options.mute_notification {
+            info!("desktop notification disabled, skip notify !");
+            return;
+        }
+
+        if let Some(window) = app.get_webview_window("main") {
+            if window.is_visible().unwrap_or(false) && window.is_focused().unwrap_or(false) {
+                return;
+            }
+        }
+
+        let mut title = format!("{} #{}", pipeline.basic.name, runtime.order.unwrap_or(1));
+        if let Some(matrix) = &runtime.matrix {
+            title.push_str(&format!(" ({})", matrix));
+        }
+
+        let body = if success { String::from("运行成功") } else { String::from("运行失败") };
+        let body = match &runtime.duration {
+            Some(duration) => format!("{}, 耗时 {}", body, duration),
+            None => body,
+        };
+
+        match app.notification().builder().title(&title).body(&body).show() {
+            Ok(_) => {
+                info!("show desktop notification success !");
+                if let Ok(mut target) = PENDING_TARGET.lock() {
+                    *target = Some((
+                        PipelineNotificationTarget {
+                            server_id: pipeline.server_id.clone(),
+                            id: pipeline.id.clone(),
+                            runtime_id: runtime.id.clone().unwrap_or(String::new()),
+                        },
+                        Instant::now(),
+                    ));
+                }
+            }
+            Err(err) => {
+                error!("show desktop notification error: {}", err);
+            }
+        }
+    }
+
+    /// 回到应用时打开最近一次通知的运行详情
+    /// 无法区分是点击通知还是切换窗口、点击托盘回到应用, 只在通知后 `PENDING_TARGET_EXPIRE_SECONDS` 秒内打开, 否则丢弃
+    pub(crate) fn open_pending(app: &AppHandle) {
+        let target = match PENDING_TARGET.lock() {
+            Ok(mut target) => target.take(),
+            Err(_) => None,
+        };
+
+        let target = match target {
+            Some((target, time)) if Self::is_pending_valid(time.elapsed()) => Some(target),
+            Some(_) => {
+                info!("pending notification target expired, skip open !");
+                None
+            }
+            None => None,
+        };
+
+        if let Some(target) = target {
+            if let Some(window) = app.get_webview_window("main") {
+                let _ = window.show();
+                let _ = window.set_focus();
+            }
+
+            EventEmitter::log_open_runtime(app, get_success_response_by_value(target).ok());
+        }
+    }
+
+    /// 通知是否仍在有效时间内
+    fn is_pending_valid(elapsed: Duration) -> bool {
+        elapsed <= Duration::from_secs(PENDING_TARGET_EXPIRE_SECONDS)
+    }
+
+    /// 系统设置中是否开启通知, 默认开启
+    fn is_enabled() -> bool {
+        if let Some(settings) = Settings::get_settings() {
+            let value = settings.desktop_notification.trim().to_lowercase();
+            return !(value == "0" || value == "false" || value == "no");
+        }
+
+        true
+    }
+}
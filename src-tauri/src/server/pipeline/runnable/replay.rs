@@ -0,0 +1,136 @@
+//! 回放运行记录, 使用历史运行的配置、快照、启动变量及提交重新运行
+
+use crate::database::helper::DBHelper;
+use crate::error::Error;
+use crate::prepare::{get_error_response, get_success_response_by_value, HttpResponse};
+use crate::server::pipeline::index::Pipeline;
+use crate::server::pipeline::pool::Pool;
+use crate::server::pipeline::props::{PipelineReplayForm, PipelineRuntimeStage, PipelineStatus};
+use crate::server::pipeline::runnable::{PipelineRunnable, PipelineRunnableQueryForm};
+use handlers::utils::Utils;
+use log::info;
+use sqlx::mysql::MySqlArguments;
+use sqlx::query::Query;
+use sqlx::MySql;
+use uuid::Uuid;
+
+pub struct PipelineReplay;
+
+impl PipelineReplay {
+    /// 回放运行记录, 不受流水线当前配置影响
+    pub(crate) async fn replay(form: &PipelineReplayForm) -> Result<HttpResponse, String> {
+        if form.server_id.is_empty() || form.id.is_empty() {
+            return Ok(get_error_response("回放运行记录失败, `serverId` 或 `id` 不能为空"));
+        }
+
+        if form.runtime_id.is_empty() {
+            return Ok(get_error_response("回放运行记录失败, `runtimeId` 不能为空"));
+        }
+
+        // ID 来自前端, 只接受 UUID
+        let ids = [&form.server_id, &form.id, &form.runtime_id];
+        if ids.iter().any(|id| Uuid::parse_str(id).is_err()) {
+            return Ok(get_error_response("回放运行记录失败, `serverId`、`id` 或 `runtimeId` 格式不正确"));
+        }
+
+        let mut pipeline = Pipeline::default();
+        pipeline.id = form.id.clone();
+        pipeline.server_id = form.server_id.clone();
+
+        let pipeline_list: Vec<Pipeline> = Pipeline::get_pipeline_list(&pipeline, None, true).await?;
+        if pipeline_list.is_empty() {
+            return Ok(get_error_response("回放运行记录失败, 该流水线不存在"));
+        }
+
+        // 查询流水线是不是在排队状态或执行状态
+        let result = PipelineRunnable::get_runtime_detail(
+            &pipeline,
+            true,
+            Some(PipelineRunnableQueryForm {
+                status_list: vec![PipelineStatus::got(PipelineStatus::Queue), PipelineStatus::got(PipelineStatus::Process), PipelineStatus::got(PipelineStatus::Waiting)],
+                runtime_id: None,
+                need_condition_last_run_id: None,
+            }),
+        )
+        .await?;
+
+        if result.runtime.is_some() {
+            return Ok(get_error_response("该流水线已在运行状态, 请等待运行完成"));
+        }
+
+        let result = PipelineRunnable::get_runtime_detail(
+            &pipeline,
+            true,
+            Some(PipelineRunnableQueryForm {
+                status_list: vec![],
+                runtime_id: Some(form.runtime_id.clone()),
+                need_condition_last_run_id: None,
+            }),
+        )
+        .await?;
+
+        let source = match result.runtime {
+            Some(runtime) => runtime,
+            None => return Ok(get_error_response("回放运行记录失败, 该运行记录不存在")),
+        };
+
+        if source.is_matrix {
+            return Ok(get_error_response("回放运行记录失败, 矩阵构建请回放具体的子运行记录"));
+        }
+
+        // 使用历史运行的基本信息及构建过程
+        let mut pipe = pipeline_list.get(0).unwrap().clone();
+        if let Some(basic) = &source.basic {
+            pipe.basic = basic.clone();
+        }
+        pipe.process_config.stages = source.stages.clone();
+
+        // 快照及启动变量沿用历史运行, 已记录提交时构建同一个提交
+        let mut props = source.clone();
+        props.id = None;
+        props.stage = PipelineRuntimeStage { stage_index: 1, ..Default::default() };
+        props.remark = if form.remark.is_empty() { format!("回放 #{}", source.order.unwrap_or(1)) } else { form.remark.clone() };
+        props.priority = form.priority;
+        props.trigger = None;
+        props.parent_id = None;
+        props.replay_id = source.id.clone();
+        if !source.commit.sha.is_empty() {
+            props.snapshot.revision = source.commit.sha.clone();
+        }
+
+        info!("replay runtime: {}, snapshot: {:#?}", &form.runtime_id, props.snapshot);
+
+        let order = PipelineRunnable::get_max_order(&pipe.id).await?;
+        let runtime_id = Uuid::new_v4().to_string();
+        let create_time = Utils::get_date(None);
+        let mut query_list: Vec<Query<MySql, MySqlArguments>> = Vec::new();
+
+        // 更新 pipeline 表 status
+        let pipeline_query = sqlx::query::<MySql>(
+            r#"
+            UPDATE pipeline SET `status` = ?, last_run_id = ? WHERE id = ?
+        "#,
+        )
+        .bind(PipelineStatus::got(PipelineStatus::Queue))
+        .bind(&runtime_id)
+        .bind(&pipe.id);
+        query_list.push(pipeline_query);
+
+        PipelineRunnable::insert_runtime(&mut query_list, &pipe, &props, &runtime_id, order + 1, &create_time);
+
+        let response = DBHelper::batch_commit(query_list).await?;
+        if response.code != 200 {
+            return Ok(response);
+        }
+
+        // 查询数据, 并插入到线程池
+        let response = Pipeline::get_by_id(&pipeline).await?;
+        if response.code != 200 {
+            return Ok(response);
+        }
+
+        let pipe: Pipeline = serde_json::from_value(response.body).map_err(|err| Error::Error(err.to_string()).to_string())?;
+        Pool::insert_into_pool(&pipe)?;
+        Ok(get_success_response_by_value(pipe).unwrap_or(HttpResponse::default()))
+    }
+}
@@ -6,27 +6,58 @@ use crate::event::EventEmitter;
 use crate::helper::git::pull::GitConfig;
 use crate::helper::git::GitHandler;
 use crate::helper::index::Helper;
+use crate::helper::mail::MailHandler;
+use crate::helper::node::NodeHandler;
+use crate::logger::pipeline::PipelineLogger;
 use crate::prepare::{convert_res, get_error_response, get_success_response_by_value};
+use crate::robot::webhook::{RobotMessage, RobotWebhook};
+use crate::robot::Robot;
 use crate::server::index::Server;
 use crate::server::pipeline::index::Pipeline;
 use crate::server::pipeline::languages::h5::{H5FileHandler, H5_INSTALLED_CMDS};
-use crate::server::pipeline::props::{PipelineCommandStatus, PipelineRunnableStageStep, PipelineRuntime, PipelineRuntimeSnapshot, PipelineRuntimeVariable, PipelineStageTask, PipelineStatus, PipelineStep, PipelineStepComponent, PipelineTag};
+use crate::server::pipeline::props::{
+    PipelineCommandStatus, PipelineRunnableStageStep, PipelineRuntime, PipelineRuntimeCommit, PipelineRuntimeSnapshot, PipelineRuntimeVariable, PipelineStageTask, PipelineStatus, PipelineStep, PipelineStepComponent, PipelineTag,
+};
+use crate::server::pipeline::runnable::approval::PipelineApproval;
+use crate::server::pipeline::runnable::cache::PipelineCache;
+use crate::server::pipeline::runnable::compose::PipelineCompose;
+use crate::server::pipeline::runnable::kubernetes::PipelineKubernetes;
+use crate::server::pipeline::runnable::matrix::PipelineMatrix;
+use crate::server::pipeline::runnable::stats::PipelineStatistics;
 use crate::server::pipeline::runnable::PipelineRunnable;
 use docker::docker::DockerHandler;
 use handlers::utils::Utils;
 use log::{error, info};
 use sftp::config::Upload;
 use sftp::upload::SftpUpload;
+use std::collections::HashMap;
+use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Instant;
 // use images_compressor::compressor::{Compressor, CompressorArgs};
 // use images_compressor::factor::Factor;
 use docker::DockerConfig;
 use minimize::minify::Minimize;
 use regex::Regex;
 use tauri::AppHandle;
+use uuid::Uuid;
 
 const DIR_NAME: &str = "projects";
+const MATRIX_DIR_NAME: &str = "matrix";
+
+// 通知中的日志摘录行数
+const NOTICE_LOG_LINES: usize = 20;
+
+// docker 步骤默认的 kubernetes 命名空间
+const DEFAULT_KUBERNETES_NAMESPACE: &str = "devops";
+
+// 运行输出变量类型及描述
+const OUTPUT_VARIABLE_GENRE: &str = "output";
+const OUTPUT_VARIABLE_DESCRIPTION: &str = "运行输出变量";
+
+// 临时文件目录, 存放渲染后的 Dockerfile、清单等, 不写入项目目录
+const TEMP_DIR_NAME: &str = "temp";
 
 pub struct PipelineRunnableStage;
 
@@ -37,6 +68,42 @@ pub struct PipelineRunnableResult {
     pub(crate) pipeline: Option<Pipeline>,
 }
 
+/// 临时文件, 内容可能包含密钥等敏感信息, 释放时删除
+pub struct PipelineTempFile {
+    pub(crate) path: PathBuf,
+}
+
+impl PipelineTempFile {
+    /// 在配置目录下创建临时文件, 文件名为 `{uuid}.{suffix}`
+    pub(crate) fn create(suffix: &str, content: &str) -> Result<Self, String> {
+        let dir = Helper::get_project_config_dir(vec![String::from(TEMP_DIR_NAME)])?;
+        let dir = match dir {
+            Some(dir) => dir,
+            None => return Err(Error::convert_string("get temp dir failed !")),
+        };
+
+        let path = dir.join(format!("{}.{}", Uuid::new_v4(), suffix));
+        std::fs::write(&path, content).map_err(|err| Error::Error(format!("write temp file `{}` error: {}", path.to_string_lossy(), err)).to_string())?;
+
+        // 只允许当前用户读取
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600)).map_err(|err| Error::Error(err.to_string()).to_string())?;
+        }
+
+        Ok(Self { path })
+    }
+}
+
+impl Drop for PipelineTempFile {
+    fn drop(&mut self) {
+        if let Err(err) = std::fs::remove_file(&self.path) {
+            error!("remove temp file {:#?} error: {:#?}", self.path, err);
+        }
+    }
+}
+
 impl PipelineRunnableStage {
     /// 执行 stage
     pub(crate) async fn exec(app: &AppHandle, task: &PipelineStageTask, installed_commands: &Vec<String>) -> Pipeline {
@@ -127,17 +194,39 @@ impl PipelineRunnableStage {
         let runtime = pipe.runtime.clone();
         let mut has_error: bool = false;
         let mut error_step: Option<PipelineRunnableStageStep> = None;
-        for step in steps.iter() {
+        let mut approval_step: Option<PipelineRunnableStageStep> = None;
+
+        // 根据历史步骤耗时估算进度
+        let estimates = PipelineStatistics::get_step_estimates(&pipe.id).await;
+        let run_now = Instant::now();
+
+        // 审批通过后继续运行, 已运行时长包含审批前的部分
+        let previous_duration_ms = PipelineApproval::get_previous_duration_ms(&task.runtime);
+        for (index, step) in steps.iter().enumerate() {
             // 设置运行步骤
             let run = runtime.clone();
             if let Some(mut run) = run {
                 run.stage.stage_index = step.stage_index;
                 run.stage.group_index = step.group_index;
                 run.stage.step_index = step.step_index;
+                PipelineLogger::set_step(&pipe.server_id, &pipe.id, run.order.unwrap_or(1), step.stage_index, step.group_index, step.step_index);
+
+                let progress = PipelineStatistics::get_progress(&estimates, &steps, index, previous_duration_ms + run_now.elapsed().as_millis() as u64);
+                PipelineStatistics::set_running_eta(&task.id, progress.eta_ms);
+                run.progress = Some(progress);
                 pipe.runtime = Some(run);
             }
 
+            // 人工审批, 暂停运行并释放线程池位置
+            if matches!(step.step.module, PipelineCommandStatus::Approval) && !PipelineApproval::is_approved(&pipe, index) {
+                approval_step = Some(step.clone());
+                break;
+            }
+
+            let start_now = Instant::now();
             let result = Self::exec_step(app, &pipe, step, installed_commands.clone()).await;
+            let success = matches!(&result, Ok(result) if result.success && result.pipeline.is_some());
+            PipelineRunnable::save_step_duration(&pipe, step, success, start_now.elapsed().as_millis() as u64).await;
             match result {
                 Ok(result) => {
                     if !result.success || result.pipeline.is_none() {
@@ -164,12 +253,26 @@ impl PipelineRunnableStage {
             }
         }
 
+        if let Some(approval_step) = approval_step {
+            let pipe = PipelineApproval::wait(app, &pipe, &approval_step).await;
+            PipelineLogger::remove_step(&pipe.server_id, &pipe.id, pipe.runtime.as_ref().and_then(|runtime| runtime.order).unwrap_or(1));
+            PipelineStatistics::remove_running_eta(&task.id);
+            return pipe;
+        }
+
         // 插入日志
         info!("insert result to log ...");
         let last_step = steps.get(steps.len() - 1);
 
         let mut runtime = pipe.runtime.unwrap_or(PipelineRuntime::default());
         runtime.status = if has_error { PipelineStatus::Failed } else { PipelineStatus::Success };
+        if let Some(progress) = runtime.progress.as_mut() {
+            progress.elapsed_ms = previous_duration_ms + run_now.elapsed().as_millis() as u64;
+            progress.eta_ms = 0;
+            if !has_error {
+                progress.percent = 100;
+            }
+        }
 
         info!("error_step: {:#?}", error_step);
         if let Some(error_step) = error_step.clone() {
@@ -195,6 +298,34 @@ impl PipelineRunnableStage {
         let success = error_step.clone().is_none();
         let msg = format!("exec task {} !", if success { "success".to_string() } else { "failed".to_string() });
         PipelineRunnable::exec_end_log(app, &pipe, success, &msg).await;
+
+        // 失败时, 后续有通知步骤则发送失败通知
+        let mut notice_emailed = success && steps.iter().any(|step| matches!(step.step.module, PipelineCommandStatus::Notice) && Self::is_email_notice(&step.step));
+        if let Some(error_step) = &error_step {
+            let notice_step = steps
+                .iter()
+                .skip_while(|step| step.stage_index != error_step.stage_index || step.group_index != error_step.group_index || step.step_index != error_step.step_index)
+                .find(|step| matches!(step.step.module, PipelineCommandStatus::Notice));
+            if let Some(notice_step) = notice_step {
+                let message = Self::get_notice_message(&pipe, Some(error_step));
+                Self::send_robot_notice(app, &pipe, &message).await;
+                if Self::is_email_notice(&notice_step.step) {
+                    Self::send_email_notice(app, &pipe, &Self::get_notice_recipients(&pipe, &notice_step.step), &message).await;
+                    notice_emailed = true;
+                }
+            }
+        }
+
+        // 流水线邮件通知, 通知步骤已发送过邮件时不再重复发送
+        let email_notice = &pipe.options.email_notice;
+        if !notice_emailed && ((success && email_notice.on_success) || (!success && email_notice.on_failure)) {
+            let message = Self::get_notice_message(&pipe, error_step.as_ref());
+            Self::send_email_notice(app, &pipe, &email_notice.recipients, &message).await;
+        }
+
+        PipelineLogger::remove_step(&pipe.server_id, &pipe.id, pipe.runtime.as_ref().and_then(|runtime| runtime.order).unwrap_or(1));
+        PipelineRunnable::retain_log(&pipe.server_id, &pipe.id).await;
+        PipelineStatistics::remove_running_eta(&task.id);
         return pipe.clone();
     }
 
@@ -217,6 +348,9 @@ impl PipelineRunnableStage {
             PipelineCommandStatus::Deploy => Self::exec_step_deploy(app, &pipeline, stage).await,
             PipelineCommandStatus::Docker => Self::exec_step_docker(app, &pipeline, stage).await,
             PipelineCommandStatus::Notice => Self::exec_step_notice(app, &pipeline, stage).await,
+            PipelineCommandStatus::Approval => PipelineApproval::exec_step(app, &pipeline, stage).await,
+            PipelineCommandStatus::Kubernetes => PipelineKubernetes::exec_step(app, &pipeline, stage).await,
+            PipelineCommandStatus::Compose => PipelineCompose::exec_step(app, &pipeline, stage).await,
         };
     }
 
@@ -235,16 +369,41 @@ impl PipelineRunnableStage {
             let msg = format!("{} not a remote project, pull {} ", &pack_name, &basic.path);
             let mut runtime = runtime.clone();
             runtime.status = PipelineStatus::Success;
+            Self::save_commit(app, &mut runtime, pipeline, Path::new(&basic.path)).await;
             pipe.runtime = Some(runtime.clone());
             let pipe = PipelineRunnable::exec_end_log(app, &pipe, true, &msg).await;
             return Ok(PipelineRunnableResult { success: pipe.is_some(), msg, pipeline: pipe });
         }
 
-        let dir = Self::get_project_path(&pipeline.server_id, &pipeline.id)?;
+        let dir = Self::get_project_path(pipeline)?;
+        let components = &step.components;
+
+        // 私有仓库凭证
+        let credential = match pipeline.options.credential.get_git_credential() {
+            Ok(credential) => credential,
+            Err(err) => {
+                let msg = format!("{} get git credential failed, {}", &pack_name, err);
+                let mut pipe = pipeline.clone();
+                let mut runtime = runtime.clone();
+                runtime.status = PipelineStatus::Failed;
+                pipe.runtime = Some(runtime.clone());
+
+                PipelineRunnable::exec_end_log(app, &pipe, false, &msg).await;
+                return Ok(PipelineRunnableResult { success: false, msg, pipeline: None });
+            }
+        };
+
         let config = GitConfig {
             url: basic.path.clone(),
-            branch: String::new(),
+            branch: runtime.snapshot.branch.clone(),
+            revision: runtime.snapshot.revision.trim().to_string(),
             dir: dir.to_string_lossy().to_string(),
+            shallow: Self::get_bool_from_components(components, "shallow"),
+            partial: Self::get_bool_from_components(components, "partial"),
+            fresh: Self::get_bool_from_components(components, "freshClone"),
+            submodules: Self::get_bool_from_components(components, "submodules"),
+            lfs: Self::get_bool_from_components(components, "lfs"),
+            credential,
         };
 
         let server_id_cloned = Arc::new(pipeline.server_id.clone());
@@ -253,12 +412,16 @@ impl PipelineRunnableStage {
         // 代码拉取
         let app_cloned = Arc::new(app.clone());
         let success = GitHandler::pull(&config, move |msg| {
-            PipelineRunnable::save_log(&*app_cloned, msg, &*server_id_cloned, &*id_cloned, runtime.order.unwrap_or(1));
+            PipelineRunnable::save_output_log(&*app_cloned, msg, &*server_id_cloned, &*id_cloned, runtime.order.unwrap_or(1));
         })?;
 
         let mut pipe = pipeline.clone();
         let mut runtime = runtime.clone();
         runtime.status = if success { PipelineStatus::Success } else { PipelineStatus::Failed };
+        if success {
+            let project_name = GitHandler::get_project_name_by_git(&basic.path);
+            Self::save_commit(app, &mut runtime, pipeline, &dir.join(&project_name)).await;
+        }
         pipe.runtime = Some(runtime);
 
         let msg = format!("{} pull {} ", &pack_name, &basic.path);
@@ -277,6 +440,29 @@ impl PipelineRunnableStage {
         return Ok(error_result);
     }
 
+    /// 记录本次运行构建的提交
+    async fn save_commit(app: &AppHandle, runtime: &mut PipelineRuntime, pipeline: &Pipeline, project_path: &Path) {
+        let commit = match GitHandler::get_commit(project_path) {
+            Some(commit) => commit,
+            None => return,
+        };
+
+        let order = runtime.order.unwrap_or(1);
+        let msg = format!("build commit: {}, author: {}, message: {}", &commit.sha, &commit.author, &commit.message);
+        PipelineRunnable::save_log(app, &msg, &pipeline.server_id, &pipeline.id, order);
+
+        runtime.commit = PipelineRuntimeCommit {
+            sha: commit.sha,
+            author: commit.author,
+            message: commit.message,
+        };
+
+        let runtime_id = runtime.id.clone().unwrap_or(String::new());
+        if let Err(err) = PipelineRunnable::update_commit(&runtime_id, &runtime.commit).await {
+            error!("save runtime commit error: {}", err);
+        }
+    }
+
     /// H5 依赖安装
     async fn exec_step_h5_install(app: &AppHandle, pipeline: &Pipeline, stage_step: &PipelineRunnableStageStep) -> Result<PipelineRunnableResult, String> {
         let step = &stage_step.step;
@@ -286,6 +472,21 @@ impl PipelineRunnableStage {
 
         PipelineRunnable::save_log(app, &format!("exec step {} ...", pack_name), &pipeline.server_id, &pipeline.id, runtime.order.unwrap_or(1));
 
+        // 指定的 node 版本未安装时直接失败
+        let bin_dir = match Self::get_node_bin_dir(app, pipeline) {
+            Ok(bin_dir) => bin_dir,
+            Err(err) => {
+                let msg = format!("{}, {}", err, &pack_name);
+                let mut pipe = pipeline.clone();
+                let mut runtime = runtime.clone();
+                runtime.status = PipelineStatus::Failed;
+                pipe.runtime = Some(runtime.clone());
+
+                PipelineRunnable::exec_end_log(app, &pipe, false, &msg).await;
+                return Ok(PipelineRunnableResult { success: false, msg, pipeline: None });
+            }
+        };
+
         let url = &basic.path;
         let project_name = GitHandler::get_project_name_by_git(&url);
 
@@ -294,7 +495,7 @@ impl PipelineRunnableStage {
             info!("url is remote !");
 
             // 远程项目
-            let dir = Self::get_project_path(&pipeline.server_id, &pipeline.id)?;
+            let dir = Self::get_project_path(pipeline)?;
 
             project_dir = dir.join(&project_name);
             if !project_dir.exists() {
@@ -311,8 +512,7 @@ impl PipelineRunnableStage {
             project_dir = PathBuf::from(url);
         }
 
-        let order = runtime.order.unwrap_or(1);
-        return Self::install_h5_project(app, pipeline, &runtime, project_dir, &project_name, order, &pack_name).await;
+        return Self::install_h5_project(app, pipeline, &runtime, project_dir, &project_name, &pack_name, bin_dir).await;
     }
 
     /// 项目打包
@@ -444,7 +644,7 @@ impl PipelineRunnableStage {
                 let id_cloned = Arc::new(pipeline.id.clone());
                 let app_cloned = Arc::new(app.clone());
                 let success = Minimize::exec(&args, move |msg| {
-                    PipelineRunnable::save_log(&*app_cloned, msg, &*server_id_cloned, &*id_cloned, order);
+                    PipelineRunnable::save_output_log(&*app_cloned, msg, &*server_id_cloned, &*id_cloned, order);
                 });
 
                 if !success {
@@ -604,7 +804,7 @@ impl PipelineRunnableStage {
                 let id_cloned = Arc::new(pipeline.id.clone());
                 let app_cloned = Arc::new(app.clone());
                 let success = Compressor::new(args).compress(move |msg|{
-                    PipelineRunnable::save_log(&*app_cloned, msg, &*server_id_cloned, &*id_cloned, order);
+                    PipelineRunnable::save_output_log(&*app_cloned, msg, &*server_id_cloned, &*id_cloned, order);
                 })?;
 
                 if !success {
@@ -756,12 +956,100 @@ impl PipelineRunnableStage {
 
         // 向前端发送通知, 通知弹框
         EventEmitter::log_step_notice(app, Some(get_success_response_by_value(pipe.clone()).unwrap_or(get_error_response("exec step notice error !"))));
+
+        // 发送机器人、邮件通知, 发送失败不影响运行结果
+        if let Some(pipe) = &pipe {
+            let message = Self::get_notice_message(pipe, None);
+            Self::send_robot_notice(app, pipe, &message).await;
+            if Self::is_email_notice(step) {
+                Self::send_email_notice(app, pipe, &Self::get_notice_recipients(pipe, step), &message).await;
+            }
+        }
+
         return Ok(PipelineRunnableResult { success: true, msg, pipeline: pipe });
     }
 
-    /// 获取目录
-    fn get_project_path(server_id: &str, id: &str) -> Result<PathBuf, String> {
-        let dir = Helper::get_project_config_dir(vec![server_id.to_string(), id.to_string(), String::from(DIR_NAME)])?;
+    /// 运行结果通知内容
+    fn get_notice_message(pipeline: &Pipeline, failed_step: Option<&PipelineRunnableStageStep>) -> RobotMessage {
+        let runtime = pipeline.runtime.clone().unwrap_or(PipelineRuntime::default());
+        let order = runtime.order.unwrap_or(1);
+
+        // 日志摘录, 取最后几行
+        let lines = PipelineLogger::read_lines(&pipeline.server_id, &pipeline.id, order).unwrap_or(Vec::new());
+        let log = lines[lines.len().saturating_sub(NOTICE_LOG_LINES)..].to_vec();
+
+        RobotMessage {
+            pipeline: pipeline.basic.name.clone(),
+            order,
+            status: PipelineStatus::got(if failed_step.is_some() { PipelineStatus::Failed } else { PipelineStatus::Success }),
+            duration: runtime.progress.as_ref().map(|progress| progress.elapsed_ms).unwrap_or(0),
+            branch: runtime.snapshot.branch.clone(),
+            failed_step: failed_step.map(|step| step.step.label.clone()),
+            log,
+        }
+    }
+
+    /// 发送运行结果到机器人 webhook
+    async fn send_robot_notice(app: &AppHandle, pipeline: &Pipeline, message: &RobotMessage) {
+        let robot = match Robot::get_config() {
+            Some(robot) => robot,
+            None => {
+                PipelineRunnable::save_log(app, "no robot webhook configured, skip send notice !", &pipeline.server_id, &pipeline.id, message.order);
+                return;
+            }
+        };
+
+        let msg = match RobotWebhook::send(&robot, message).await {
+            Ok(_) => String::from("send robot notice success !"),
+            Err(err) => format!("send robot notice failed: {}", err),
+        };
+
+        PipelineRunnable::save_log(app, &msg, &pipeline.server_id, &pipeline.id, message.order);
+    }
+
+    /// 发送运行结果邮件
+    async fn send_email_notice(app: &AppHandle, pipeline: &Pipeline, recipients: &Vec<String>, message: &RobotMessage) {
+        if recipients.is_empty() {
+            PipelineRunnable::save_log(app, "no email recipients configured, skip send email !", &pipeline.server_id, &pipeline.id, message.order);
+            return;
+        }
+
+        let msg = match MailHandler::send_message(recipients, message).await {
+            Ok(_) => format!("send email notice to {} success !", recipients.join(", ")),
+            Err(err) => format!("send email notice failed: {}", err),
+        };
+
+        PipelineRunnable::save_log(app, &msg, &pipeline.server_id, &pipeline.id, message.order);
+    }
+
+    /// 通知步骤是否发送邮件
+    fn is_email_notice(step: &PipelineStep) -> bool {
+        Self::get_bool_from_components(&step.components, "email")
+    }
+
+    /// 通知步骤收件人, 未配置时使用流水线的收件人
+    fn get_notice_recipients(pipeline: &Pipeline, step: &PipelineStep) -> Vec<String> {
+        let component = step.components.iter().find(|com| com.prop.as_str() == "recipients");
+        if let Some(component) = component {
+            let recipients = MailHandler::split_recipients(&component.value);
+            if !recipients.is_empty() {
+                return recipients;
+            }
+        }
+
+        pipeline.options.email_notice.recipients.clone()
+    }
+
+    /// 获取目录, 矩阵构建时每个变量组合使用单独的目录
+    pub(crate) fn get_project_path(pipeline: &Pipeline) -> Result<PathBuf, String> {
+        let mut names = vec![pipeline.server_id.to_string(), pipeline.id.to_string(), String::from(DIR_NAME)];
+        if let Some(runtime) = &pipeline.runtime {
+            if let Some(matrix) = &runtime.matrix {
+                names = vec![pipeline.server_id.to_string(), pipeline.id.to_string(), String::from(MATRIX_DIR_NAME), PipelineMatrix::get_workspace_name(matrix)];
+            }
+        }
+
+        let dir = Helper::get_project_config_dir(names)?;
         if let Some(dir) = dir {
             return Ok(dir);
         }
@@ -769,6 +1057,69 @@ impl PipelineRunnableStage {
         return Err(Error::convert_string("get project path failed !"));
     }
 
+    /// 工作目录, 远程项目使用拉取后的目录
+    pub(crate) fn get_work_dir(pipeline: &Pipeline) -> Result<PathBuf, String> {
+        let path = &pipeline.basic.path;
+        if GitHandler::is_remote_url(path) {
+            return Self::get_project_path(pipeline);
+        }
+
+        Ok(PathBuf::from(path))
+    }
+
+    /// 读取模板, 为项目中的文件路径时读取文件内容, 否则为模板内容
+    pub(crate) fn read_template(dir: &Path, template: &str) -> Result<String, String> {
+        let template = template.trim();
+        if !template.contains('\n') {
+            let file_path = dir.join(template);
+            if file_path.is_file() {
+                return std::fs::read_to_string(&file_path).map_err(|err| Error::Error(format!("read template `{}` error: {}", file_path.to_string_lossy(), err)).to_string());
+            }
+        }
+
+        Ok(template.to_string())
+    }
+
+    /// 模板变量: 运行变量, 以及 docker 步骤输出的 image(完整镜像地址, 如 `address/namespace/image:version`)、imageName、version
+    pub(crate) fn get_template_variables(runtime: &PipelineRuntime) -> HashMap<String, String> {
+        let variables = &runtime.snapshot.runnable_variables;
+        let mut map: HashMap<String, String> = variables.iter().map(|variable| (variable.name.clone(), variable.value.clone())).collect();
+        let image_name = Self::get_value_from_variables(variables, "dockerImage");
+        let mut image_ref = Self::get_value_from_variables(variables, "dockerImageRef");
+        if image_ref.is_empty() {
+            image_ref = image_name.clone();
+        }
+
+        map.insert(String::from("image"), image_ref.clone());
+        map.insert(String::from("imageRef"), image_ref);
+        map.insert(String::from("imageName"), image_name);
+        map.insert(String::from("version"), Self::get_value_from_variables(variables, "dockerVersion"));
+        map
+    }
+
+    /// 替换模板中的 `$name` 和 `${name}`, 未定义的变量保持不变
+    pub(crate) fn render_template(template: &str, variables: &HashMap<String, String>) -> String {
+        let re = Regex::new(r"\$\{(\w+)\}|\$(\w+)").unwrap();
+        re.replace_all(template, |caps: &regex::Captures| {
+            let name = caps.get(1).or(caps.get(2)).map(|name| name.as_str()).unwrap_or("");
+            variables.get(name).cloned().unwrap_or(caps[0].to_string())
+        })
+        .to_string()
+    }
+
+    /// 获取快照中指定 node 版本的 bin 目录
+    fn get_node_bin_dir(app: &AppHandle, pipeline: &Pipeline) -> Result<Option<String>, String> {
+        let runtime = pipeline.runtime.clone().unwrap_or(PipelineRuntime::default());
+        let node = &runtime.snapshot.node;
+        let bin_dir = NodeHandler::get_bin_dir(node)?;
+        if let Some(bin_dir) = &bin_dir {
+            let msg = format!("use node version: {}, bin dir: {}", node, bin_dir);
+            PipelineRunnable::save_log(app, &msg, &pipeline.server_id, &pipeline.id, runtime.order.unwrap_or(1));
+        }
+
+        Ok(bin_dir)
+    }
+
     /// 执行 H5 打包
     async fn exec_step_h5_pack(app: &AppHandle, pipeline: &Pipeline, installed_commands: Vec<String>, dir: &str, step: &PipelineStep) -> Result<PipelineRunnableResult, String> {
         let pack_name = &format!("【H5 {}】", &step.label);
@@ -792,6 +1143,17 @@ impl PipelineRunnableStage {
             pipeline: None,
         };
 
+        // 指定的 node 版本未安装时直接失败
+        let bin_dir = match Self::get_node_bin_dir(app, pipeline) {
+            Ok(bin_dir) => bin_dir,
+            Err(err) => {
+                error_msg = format!("{}, {}", err, pack_name);
+                error_result.msg = error_msg.clone();
+                PipelineRunnable::exec_end_log(app, &pipe, false, &error_msg).await;
+                return Ok(error_result);
+            }
+        };
+
         // make
         if let Some(make) = make {
             if !make.is_empty() {
@@ -810,8 +1172,8 @@ impl PipelineRunnableStage {
                 let server_id_cloned = Arc::new(pipeline.server_id.clone());
                 let id_cloned = Arc::new(pipeline.id.clone());
                 let app_cloned = Arc::new(app.clone());
-                let success = Helper::exec_command(&make, &dir, move |msg| {
-                    PipelineRunnable::save_log(&*app_cloned, msg, &*server_id_cloned, &*id_cloned, order);
+                let success = Helper::exec_command_by_path(&make, &dir, bin_dir.clone(), move |msg| {
+                    PipelineRunnable::save_output_log(&*app_cloned, msg, &*server_id_cloned, &*id_cloned, order);
                 });
 
                 runtime.status = if success { PipelineStatus::Success } else { PipelineStatus::Failed };
@@ -868,8 +1230,8 @@ impl PipelineRunnableStage {
         let server_id_cloned = Arc::new(pipeline.server_id.clone());
         let id_cloned = Arc::new(pipeline.id.clone());
         let app_cloned = Arc::new(app.clone());
-        let success = Helper::exec_command(&run_command, &dir, move |msg| {
-            PipelineRunnable::save_log(&*app_cloned, msg, &*server_id_cloned, &*id_cloned, order);
+        let success = Helper::exec_command_by_path(&run_command, &dir, bin_dir, move |msg| {
+            PipelineRunnable::save_output_log(&*app_cloned, msg, &*server_id_cloned, &*id_cloned, order);
         });
 
         runtime.status = if success { PipelineStatus::Success } else { PipelineStatus::Failed };
@@ -887,29 +1249,58 @@ impl PipelineRunnableStage {
     }
 
     /// H5 项目安装依赖
-    async fn install_h5_project(app: &AppHandle, pipeline: &Pipeline, runtime: &PipelineRuntime, project_path: PathBuf, project_name: &str, order: u32, pack_name: &str) -> Result<PipelineRunnableResult, String> {
+    async fn install_h5_project(app: &AppHandle, pipeline: &Pipeline, runtime: &PipelineRuntime, project_path: PathBuf, project_name: &str, pack_name: &str, bin_dir: Option<String>) -> Result<PipelineRunnableResult, String> {
+        let order = runtime.order.unwrap_or(1);
         if !project_path.exists() {
             let msg = format!("install h5 project dependencies failed, project dir: {:#?} not exists !", project_path);
             error!("{}", msg);
             return Err(Error::convert_string(&msg));
         }
 
-        let (cmds, _) = Self::get_h5_install_cmd(app, project_path.clone(), project_name, &pipeline.server_id, &pipeline.id, order)?;
+        let (cmds, install_command, lock_file) = Self::get_h5_install_cmd(app, project_path.clone(), project_name, &pipeline.server_id, &pipeline.id, order)?;
         if cmds.is_empty() {
             let msg = "can not found any commands in os !";
             error!("{}", msg);
             return Err(Error::convert_string(msg));
         }
 
+        // 根据 lock 文件恢复依赖缓存
+        let mut cache_key: Option<String> = None;
+        match &lock_file {
+            Some(lock_file) => cache_key = PipelineCache::get_key(lock_file, &install_command, &runtime.snapshot.node),
+            None => Self::send_log(app, "no lock file found, skip dependency cache", &pipeline.server_id, &pipeline.id, order),
+        }
+
+        if let Some(key) = &cache_key {
+            let msg = match PipelineCache::restore(&pipeline.server_id, &pipeline.id, key, &project_path) {
+                Ok(true) => format!("restore dependency cache {} success", key),
+                Ok(false) => format!("dependency cache {} not found", key),
+                Err(err) => format!("restore dependency cache {} error: {}", key, err),
+            };
+            Self::send_log(app, &msg, &pipeline.server_id, &pipeline.id, order);
+        }
+
         let server_id_cloned = Arc::new(pipeline.server_id.clone());
         let id_cloned = Arc::new(pipeline.id.clone());
 
         let command = cmds.join(" && ");
         let app_cloned = Arc::new(app.clone());
-        let success = Helper::exec_command(&command, &project_path.to_string_lossy().to_string(), move |msg| {
-            PipelineRunnable::save_log(&*app_cloned, msg, &*server_id_cloned, &*id_cloned, order);
+        let success = Helper::exec_command_by_path(&command, &project_path.to_string_lossy().to_string(), bin_dir, move |msg| {
+            PipelineRunnable::save_output_log(&*app_cloned, msg, &*server_id_cloned, &*id_cloned, order);
         });
 
+        // 安装成功后保存依赖缓存
+        if success {
+            if let Some(key) = &cache_key {
+                let msg = match PipelineCache::save(&pipeline.server_id, &pipeline.id, key, &project_path) {
+                    Ok(true) => format!("save dependency cache {} success", key),
+                    Ok(false) => format!("dependency cache {} already exists", key),
+                    Err(err) => format!("save dependency cache {} error: {}", key, err),
+                };
+                Self::send_log(app, &msg, &pipeline.server_id, &pipeline.id, order);
+            }
+        }
+
         let mut run = runtime.clone();
         run.status = if success { PipelineStatus::Success } else { PipelineStatus::Failed };
         let mut pipe = pipeline.clone();
@@ -932,7 +1323,7 @@ impl PipelineRunnableStage {
     }
 
     /// 获取 H5 安装的命令，动态智能判断
-    fn get_h5_install_cmd(app: &AppHandle, project_path: PathBuf, project_name: &str, server_id: &str, id: &str, order: u32) -> Result<(Vec<String>, String), String> {
+    fn get_h5_install_cmd(app: &AppHandle, project_path: PathBuf, project_name: &str, server_id: &str, id: &str, order: u32) -> Result<(Vec<String>, String, Option<PathBuf>), String> {
         let installed_commands = H5FileHandler::get_installed_commands();
         if installed_commands.is_empty() {
             let msg = "`yarn`、`pnpm`、`cnpm`、`npm` not found in the os !";
@@ -951,7 +1342,7 @@ impl PipelineRunnableStage {
         // 1. 判断是否有 pnpm-lock.yaml, yarn.lock, package-lock.json
         // 1.1 pnpm-lock.yaml
         let mut path = project_path.clone();
-        path.set_file_name(files.get(0).unwrap());
+        path.push(files.get(0).unwrap());
         if path.exists() {
             Self::send_log(app, &format!("project {} have `pnpm-lock.yaml`, use `pnpm install`", project_name), server_id, id, order);
 
@@ -963,7 +1354,7 @@ impl PipelineRunnableStage {
 
             Self::send_log(app, &format!("run `{} install`", H5_INSTALLED_CMDS[2]), server_id, id, order);
             cmds.push(format!("{} install", H5_INSTALLED_CMDS[2]));
-            return Ok((cmds, H5_INSTALLED_CMDS[2].to_string()));
+            return Ok((cmds, H5_INSTALLED_CMDS[2].to_string(), Some(path)));
         }
 
         // 1.2 yarn.lock
@@ -980,7 +1371,7 @@ impl PipelineRunnableStage {
 
             Self::send_log(app, &format!("run `{} install`", H5_INSTALLED_CMDS[1]), server_id, id, order);
             cmds.push(format!("{} install", H5_INSTALLED_CMDS[1]));
-            return Ok((cmds, H5_INSTALLED_CMDS[1].to_string()));
+            return Ok((cmds, H5_INSTALLED_CMDS[1].to_string(), Some(path)));
         }
 
         // 1.3 package-lock.json
@@ -997,7 +1388,7 @@ impl PipelineRunnableStage {
 
             Self::send_log(app, &format!("run `{} install`", H5_INSTALLED_CMDS[3]), server_id, id, order);
             cmds.push(format!("{} install", H5_INSTALLED_CMDS[3]));
-            return Ok((cmds, H5_INSTALLED_CMDS[3].to_string()));
+            return Ok((cmds, H5_INSTALLED_CMDS[3].to_string(), Some(path)));
         }
 
         // 2. 判断项目中是否包含 `.npmrc` 文件
@@ -1014,29 +1405,39 @@ impl PipelineRunnableStage {
 
             Self::send_log(app, &format!("run `{} install`", H5_INSTALLED_CMDS[2]), server_id, id, order);
             cmds.push(format!("{} install", H5_INSTALLED_CMDS[2]));
-            return Ok((cmds, H5_INSTALLED_CMDS[2].to_string()));
+            return Ok((cmds, H5_INSTALLED_CMDS[2].to_string(), None));
         }
 
         // 3. 动态智能判断, 判断 cnpm yarn npm
         if installed_commands.contains(&H5_INSTALLED_CMDS[3].to_string()) {
             Self::send_log(app, &format!("project {} dynamic use `cnpm install`", project_name), server_id, id, order);
             cmds.push(format!("{} install", H5_INSTALLED_CMDS[3]));
-            return Ok((cmds, H5_INSTALLED_CMDS[3].to_string()));
+            return Ok((cmds, H5_INSTALLED_CMDS[3].to_string(), None));
         }
 
         if installed_commands.contains(&H5_INSTALLED_CMDS[1].to_string()) {
             Self::send_log(app, &format!("project {} dynamic use `yarn install`", project_name), server_id, id, order);
             cmds.push(format!("{} install", H5_INSTALLED_CMDS[1]));
-            return Ok((cmds, H5_INSTALLED_CMDS[1].to_string()));
+            return Ok((cmds, H5_INSTALLED_CMDS[1].to_string(), None));
         }
 
         if installed_commands.contains(&H5_INSTALLED_CMDS[0].to_string()) {
             Self::send_log(app, &format!("project {} dynamic npm `npm install`", project_name), server_id, id, order);
             cmds.push(format!("{} install", H5_INSTALLED_CMDS[0]));
-            return Ok((cmds, H5_INSTALLED_CMDS[0].to_string()));
+            return Ok((cmds, H5_INSTALLED_CMDS[0].to_string(), None));
+        }
+
+        Ok((cmds, String::new(), None))
+    }
+
+    /// 从组件中取 `Yes` | `No` 的值
+    pub(crate) fn get_bool_from_components(components: &Vec<PipelineStepComponent>, prop_name: &str) -> bool {
+        let component = components.iter().find(|com| com.prop.as_str() == prop_name);
+        if let Some(component) = component {
+            return component.value.trim().to_lowercase().as_str() == "yes";
         }
 
-        Ok((cmds, String::new()))
+        false
     }
 
     /// 从选中的 variables 取值
@@ -1053,6 +1454,26 @@ impl PipelineRunnableStage {
         return String::new();
     }
 
+    /// 设置变量, 已存在时更新值, 如运行输出变量供下游流水线使用
+    pub(crate) fn set_value_to_variables(variables: &mut Vec<PipelineRuntimeVariable>, prop_name: &str, value: &str, genre: &str, description: &str) {
+        let variable = variables.iter_mut().find(|variable| variable.name.as_str() == prop_name);
+        if let Some(variable) = variable {
+            variable.value = value.to_string();
+            return;
+        }
+
+        variables.push(PipelineRuntimeVariable {
+            order: variables.len() as u32 + 1,
+            name: prop_name.to_string(),
+            value: value.to_string(),
+            genre: genre.to_string(),
+            require: String::from("No"),
+            disabled: String::from("No"),
+            description: description.to_string(),
+            ..Default::default()
+        });
+    }
+
     /// 获取项目打包目录
     fn get_project_pack_dir(stage_step: &PipelineRunnableStageStep, pipeline: &Pipeline, project_name: &str) -> Result<String, String> {
         // 取 packDir
@@ -1070,7 +1491,7 @@ impl PipelineRunnableStage {
 
         // 获取远程地址
         if GitHandler::is_remote_url(&basic.path) {
-            let project_dir = Self::get_project_path(&pipeline.server_id, &pipeline.id)?;
+            let project_dir = Self::get_project_path(pipeline)?;
             let project_dir = project_dir.join(&project_name).join(&pack_dir);
             if !project_dir.exists() {
                 return Err(Error::convert_string(&format!("project dir: {:#?} not exists !", project_dir)));
@@ -1162,12 +1583,6 @@ impl DockerHelper {
         let snapshot = &runtime.snapshot;
 
         let basic = &runtime.basic;
-        let result = PipelineRunnableResult {
-            success: true,
-            msg: "".to_string(),
-            pipeline: Some(pipeline.clone()),
-        };
-
         if basic.is_none() {
             return Err(Error::convert_string("run pipeline failed, `runtime basic filed` is empty!"));
         }
@@ -1175,6 +1590,14 @@ impl DockerHelper {
         let order = runtime.order.unwrap_or(1);
         let basic = basic.clone().unwrap();
 
+        // 本地构建, 不需要服务器, docker 命令为阻塞调用, 放到阻塞线程中执行
+        if PipelineRunnableStage::get_bool_from_components(&stage_step.step.components, "docker.local") {
+            let app_cloned = app.clone();
+            let pipeline_cloned = pipeline.clone();
+            let stage_step_cloned = stage_step.clone();
+            return async_std::task::spawn_blocking(move || Self::exec_local(&app_cloned, &pipeline_cloned, &stage_step_cloned)).await;
+        }
+
         // 查找服务器信息
         let mut server = Server::default();
         server.id = pipeline.server_id.clone();
@@ -1196,7 +1619,7 @@ impl DockerHelper {
         PipelineRunnable::save_log(app, &format!("docker config: {:#?}", docker_config), &pipeline.server_id, &pipeline.id, order);
 
         docker_config.deploy_dir = PipelineRunnableStage::get_deploy_dir(&stage_step, &snapshot);
-        docker_config.kubernetes_namespace = String::from("devops");
+        docker_config.kubernetes_namespace = Self::get_kubernetes_namespace(stage_step);
         docker_config.dir = basic.path.clone();
 
         let order = runtime.order.unwrap_or(1);
@@ -1206,7 +1629,7 @@ impl DockerHelper {
         let app_cloned = Arc::new(app.clone());
 
         let success = DockerHandler::exec(&docker_config, &serve, move |msg| {
-            PipelineRunnable::save_log(&*app_cloned, msg, &*server_id_cloned, &*id_cloned, order);
+            PipelineRunnable::save_output_log(&*app_cloned, msg, &*server_id_cloned, &*id_cloned, order);
         })
         .await?;
 
@@ -1214,7 +1637,148 @@ impl DockerHelper {
             return Err(Error::convert_string("package docker error!!"));
         }
 
-        return Ok(result.clone());
+        // 输出镜像及版本, 供下游流水线使用
+        let mut pipe = pipeline.clone();
+        let mut run = runtime.clone();
+        Self::set_output_variables(&mut run, &docker_config);
+        pipe.runtime = Some(run);
+
+        return Ok(PipelineRunnableResult {
+            success: true,
+            msg: "".to_string(),
+            pipeline: Some(pipe),
+        });
+    }
+
+    /// kubernetes 命名空间, 未配置时使用 `devops`
+    fn get_kubernetes_namespace(stage_step: &PipelineRunnableStageStep) -> String {
+        let component = stage_step.step.components.iter().find(|com| com.prop.as_str() == "docker.kubernetes.namespace");
+        if let Some(component) = component {
+            if !component.value.trim().is_empty() {
+                return component.value.trim().to_string();
+            }
+        }
+
+        String::from(DEFAULT_KUBERNETES_NAMESPACE)
+    }
+
+    /// 使用本地 docker 命令构建镜像, 按需登录并推送
+    fn exec_local(app: &AppHandle, pipeline: &Pipeline, stage_step: &PipelineRunnableStageStep) -> Result<PipelineRunnableResult, String> {
+        let runtime = &pipeline.clone().runtime.unwrap_or(PipelineRuntime::default());
+        let snapshot = &runtime.snapshot;
+        let order = runtime.order.unwrap_or(1);
+
+        let docker_config = Self::exec_docker_config(pipeline, stage_step, snapshot);
+        if docker_config.image.is_empty() {
+            return Err(Error::convert_string("local docker build failed, `docker.image` is empty!"));
+        }
+
+        let dir = PipelineRunnableStage::get_work_dir(pipeline)?;
+        let temp_file = Self::get_local_dockerfile(&dir, &docker_config.dockerfile)?;
+        let dockerfile = match &temp_file {
+            Some(temp_file) => temp_file.path.to_string_lossy().to_string(),
+            None => dir.join("Dockerfile").to_string_lossy().to_string(),
+        };
+        let tag = Self::get_local_tag(&docker_config);
+        let dir = dir.to_string_lossy().to_string();
+        PipelineRunnable::save_log(app, &format!("local docker build image: {}, dockerfile: {}", &tag, &dockerfile), &pipeline.server_id, &pipeline.id, order);
+
+        let exec_command = |command: &str| {
+            let server_id_cloned = Arc::new(pipeline.server_id.clone());
+            let id_cloned = Arc::new(pipeline.id.clone());
+            let app_cloned = Arc::new(app.clone());
+            Helper::exec_command_by_path(command, &dir, None, move |msg| {
+                PipelineRunnable::save_output_log(&*app_cloned, msg, &*server_id_cloned, &*id_cloned, order);
+            })
+        };
+
+        // 构建
+        let mut build_command = format!("docker build -f \"{}\" -t \"{}\"", &dockerfile, &tag);
+        if !docker_config.platform.trim().is_empty() {
+            build_command.push_str(&format!(" --platform {}", docker_config.platform.trim()));
+        }
+        build_command.push_str(" .");
+
+        if !exec_command(&build_command) {
+            return Err(Error::convert_string("local docker build error!!"));
+        }
+
+        // 推送
+        if docker_config.need_push.trim().to_lowercase() == "yes" {
+            if !docker_config.user.is_empty() {
+                Self::local_login(&docker_config)?;
+                PipelineRunnable::save_log(app, &format!("docker login {} success !", &docker_config.address), &pipeline.server_id, &pipeline.id, order);
+            }
+
+            if !exec_command(&format!("docker push \"{}\"", &tag)) {
+                return Err(Error::convert_string("local docker push error!!"));
+            }
+        }
+
+        // 输出镜像及版本, 供下游流水线使用
+        let mut pipe = pipeline.clone();
+        let mut run = runtime.clone();
+        Self::set_output_variables(&mut run, &docker_config);
+        pipe.runtime = Some(run);
+
+        return Ok(PipelineRunnableResult {
+            success: true,
+            msg: "".to_string(),
+            pipeline: Some(pipe),
+        });
+    }
+
+    /// 获取本地 Dockerfile, 未配置 `docker.dockerfile` 时使用项目中的 Dockerfile(返回 None), 否则读取模板后写入临时文件, 构建结束后删除
+    fn get_local_dockerfile(dir: &Path, dockerfile: &str) -> Result<Option<PipelineTempFile>, String> {
+        if dockerfile.trim().is_empty() {
+            return Ok(None);
+        }
+
+        let content = PipelineRunnableStage::read_template(dir, dockerfile)?;
+        Ok(Some(PipelineTempFile::create("Dockerfile", &content)?))
+    }
+
+    /// 输出镜像名称、版本及推送的完整镜像地址
+    fn set_output_variables(runtime: &mut PipelineRuntime, docker_config: &DockerConfig) {
+        let variables = &mut runtime.snapshot.runnable_variables;
+        PipelineRunnableStage::set_value_to_variables(variables, "dockerImage", &docker_config.image, OUTPUT_VARIABLE_GENRE, OUTPUT_VARIABLE_DESCRIPTION);
+        PipelineRunnableStage::set_value_to_variables(variables, "dockerVersion", &docker_config.version, OUTPUT_VARIABLE_GENRE, OUTPUT_VARIABLE_DESCRIPTION);
+        PipelineRunnableStage::set_value_to_variables(variables, "dockerImageRef", &Self::get_local_tag(docker_config), OUTPUT_VARIABLE_GENRE, OUTPUT_VARIABLE_DESCRIPTION);
+    }
+
+    /// 镜像标签: address/namespace/image:version
+    fn get_local_tag(docker_config: &DockerConfig) -> String {
+        let names: Vec<&str> = vec![docker_config.address.trim().trim_end_matches('/'), docker_config.namespace.trim().trim_matches('/'), docker_config.image.trim()]
+            .into_iter()
+            .filter(|name| !name.is_empty())
+            .collect();
+        format!("{}:{}", names.join("/"), docker_config.version.trim())
+    }
+
+    /// 登录镜像仓库, 密码通过标准输入传递, 不写入日志
+    fn local_login(docker_config: &DockerConfig) -> Result<(), String> {
+        let mut command = std::process::Command::new("docker");
+        command.arg("login").arg("-u").arg(&docker_config.user).arg("--password-stdin");
+        if !docker_config.address.trim().is_empty() {
+            command.arg(docker_config.address.trim());
+        }
+
+        let mut child = command
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .spawn()
+            .map_err(|err| Error::Error(format!("docker login error: {}", err)).to_string())?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(docker_config.password.as_bytes()).map_err(|err| Error::Error(format!("docker login error: {}", err)).to_string())?;
+        }
+
+        let output = child.wait_with_output().map_err(|err| Error::Error(format!("docker login error: {}", err)).to_string())?;
+        if !output.status.success() {
+            return Err(Error::convert_string(&format!("docker login error: {}", String::from_utf8_lossy(&output.stderr).trim())));
+        }
+
+        Ok(())
     }
 
     /// 获取 docker 配置
@@ -1312,3 +1876,51 @@ impl DockerHelper {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_variable(name: &str, value: &str) -> PipelineRuntimeVariable {
+        PipelineRuntimeVariable {
+            name: name.to_string(),
+            value: value.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_render_template() {
+        let mut variables: HashMap<String, String> = HashMap::new();
+        variables.insert(String::from("image"), String::from("registry.example.com/devops/web:1.0.0"));
+        variables.insert(String::from("replicas"), String::from("2"));
+
+        let template = "image: $image\nreplicas: ${replicas}\nport: $PORT\nhome: ${HOME}\nprice: $$5";
+        let content = PipelineRunnableStage::render_template(template, &variables);
+        assert_eq!(content, "image: registry.example.com/devops/web:1.0.0\nreplicas: 2\nport: $PORT\nhome: ${HOME}\nprice: $$5");
+        assert_eq!(PipelineRunnableStage::render_template("", &variables), "");
+    }
+
+    #[test]
+    fn test_get_template_variables() {
+        let mut runtime = PipelineRuntime::default();
+        runtime.snapshot.runnable_variables = vec![
+            get_variable("env", "test"),
+            get_variable("dockerImage", "web"),
+            get_variable("dockerVersion", "1.0.0"),
+            get_variable("dockerImageRef", "registry.example.com/devops/web:1.0.0"),
+        ];
+
+        let variables = PipelineRunnableStage::get_template_variables(&runtime);
+        assert_eq!(variables.get("env").map(|value| value.as_str()), Some("test"));
+        assert_eq!(variables.get("image").map(|value| value.as_str()), Some("registry.example.com/devops/web:1.0.0"));
+        assert_eq!(variables.get("imageRef").map(|value| value.as_str()), Some("registry.example.com/devops/web:1.0.0"));
+        assert_eq!(variables.get("imageName").map(|value| value.as_str()), Some("web"));
+        assert_eq!(variables.get("version").map(|value| value.as_str()), Some("1.0.0"));
+
+        // 没有完整镜像地址时使用镜像名称
+        runtime.snapshot.runnable_variables.pop();
+        let variables = PipelineRunnableStage::get_template_variables(&runtime);
+        assert_eq!(variables.get("image").map(|value| value.as_str()), Some("web"));
+    }
+}
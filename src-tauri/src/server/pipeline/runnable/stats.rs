@@ -0,0 +1,403 @@
+//! 流水线运行统计, 成功率、耗时及趋势
+
+use crate::database::helper::DBHelper;
+use crate::prepare::{get_error_response, get_success_response_by_value, HttpResponse};
+use crate::server::pipeline::props::{PipelineDailyStats, PipelineRunStats, PipelineRunnableStageStep, PipelineRuntimeProgress, PipelineStatsQueryForm, PipelineStatus, PipelineStepStats};
+use lazy_static::lazy_static;
+use log::error;
+use sqlx::mysql::MySqlArguments;
+use sqlx::query::Query;
+use sqlx::{MySql, Row};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+// 每条流水线返回的最慢步骤数
+const SLOWEST_STEP_COUNT: usize = 5;
+
+// 估算耗时时取最近几次成功的运行
+const ESTIMATE_RUN_COUNT: usize = 10;
+
+lazy_static! {
+    // 正在运行任务的预计剩余时长, key: runtime_id, value: 毫秒
+    static ref RUNNING_ETA: Mutex<HashMap<String, u64>> = Mutex::new(HashMap::new());
+}
+
+pub struct PipelineStatistics;
+
+/// 步骤累计数据
+#[derive(Default)]
+struct StepTotal {
+    stats: PipelineStepStats,
+    duration: u64,
+}
+
+/// 每日累计数据
+#[derive(Default)]
+struct DailyTotal {
+    stats: PipelineDailyStats,
+    duration: u64,
+    count: u64,
+}
+
+impl PipelineStatistics {
+    /// 获取运行统计
+    pub(crate) async fn get_stats(form: &PipelineStatsQueryForm) -> Result<HttpResponse, String> {
+        if form.server_id.is_empty() {
+            return Ok(get_error_response("查询运行统计失败, `serverId` 不能为空"));
+        }
+
+        let mut list = Self::get_run_stats(form).await?;
+        let mut steps = Self::get_step_stats(form).await?;
+        for stats in list.iter_mut() {
+            if let Some(steps) = steps.remove(&stats.id) {
+                stats.slowest_steps = steps;
+            }
+        }
+
+        get_success_response_by_value(list)
+    }
+
+    /// 按流水线统计运行次数、成功率、耗时及每日趋势
+    async fn get_run_stats(form: &PipelineStatsQueryForm) -> Result<Vec<PipelineRunStats>, String> {
+        let sql = format!(
+            r#"
+            SELECT
+                r.id,
+                r.pipeline_id,
+                r.`status`,
+                CAST( r.duration_ms AS UNSIGNED ) AS duration_ms,
+                r.start_time,
+                r.parent_runtime_id,
+                b.`name`
+            FROM pipeline_runtime r
+            INNER JOIN pipeline p ON p.id = r.pipeline_id
+            LEFT JOIN pipeline_basic b ON b.pipeline_id = r.pipeline_id
+            WHERE r.`status` IN ('Success', 'Failed', 'Stop') {}
+            ORDER BY r.start_time
+        "#,
+            Self::get_condition_sql(form)
+        );
+
+        let rows = DBHelper::execute_rows(Self::bind_condition(sqlx::query::<MySql>(&sql), form)).await?;
+
+        // 矩阵构建的父运行记录没有耗时, 取子运行记录中最长的
+        let mut children_durations: HashMap<String, u64> = HashMap::new();
+        for row in rows.iter() {
+            let parent_id: String = row.try_get("parent_runtime_id").unwrap_or(String::new());
+            let duration: Option<u64> = row.try_get("duration_ms").unwrap_or(None);
+            if !parent_id.is_empty() {
+                let max = children_durations.entry(parent_id).or_insert(0);
+                *max = (*max).max(duration.unwrap_or(0));
+            }
+        }
+
+        let mut map: HashMap<String, PipelineRunStats> = HashMap::new();
+        let mut durations: HashMap<String, Vec<u64>> = HashMap::new();
+        let mut daily: HashMap<String, Vec<DailyTotal>> = HashMap::new();
+        for row in rows.iter() {
+            let parent_id: String = row.try_get("parent_runtime_id").unwrap_or(String::new());
+            if !parent_id.is_empty() {
+                continue;
+            }
+
+            let id: String = row.try_get("id").unwrap_or(String::new());
+            let pipeline_id: String = row.try_get("pipeline_id").unwrap_or(String::new());
+            let status = PipelineStatus::get(&row.try_get::<String, _>("status").unwrap_or(String::new()));
+            let start_time: String = row.try_get("start_time").unwrap_or(String::new());
+            let duration: Option<u64> = row.try_get::<Option<u64>, _>("duration_ms").unwrap_or(None).or(children_durations.get(&id).cloned());
+
+            let stats = map.entry(pipeline_id.clone()).or_insert_with(|| PipelineRunStats {
+                id: pipeline_id.clone(),
+                name: row.try_get("name").unwrap_or(String::new()),
+                ..Default::default()
+            });
+
+            stats.total += 1;
+            match status {
+                PipelineStatus::Success => stats.success += 1,
+                PipelineStatus::Failed => stats.failed += 1,
+                _ => stats.stop += 1,
+            }
+
+            if let Some(duration) = duration {
+                durations.entry(pipeline_id.clone()).or_default().push(duration);
+            }
+
+            // 每日趋势, start_time 格式为 yyyy-MM-dd HH:mm:ss
+            let date = start_time.chars().take(10).collect::<String>();
+            let days = daily.entry(pipeline_id.clone()).or_default();
+            if days.last().map(|day| day.stats.date != date).unwrap_or(true) {
+                days.push(DailyTotal {
+                    stats: PipelineDailyStats { date: date.clone(), ..Default::default() },
+                    ..Default::default()
+                });
+            }
+
+            if let Some(day) = days.last_mut() {
+                day.stats.total += 1;
+                match status {
+                    PipelineStatus::Success => day.stats.success += 1,
+                    PipelineStatus::Failed => day.stats.failed += 1,
+                    _ => {}
+                }
+
+                if let Some(duration) = duration {
+                    day.duration += duration;
+                    day.count += 1;
+                }
+            }
+        }
+
+        let mut list: Vec<PipelineRunStats> = map.into_values().collect();
+        for stats in list.iter_mut() {
+            stats.success_rate = if stats.total == 0 { 0.0 } else { stats.success as f64 / stats.total as f64 };
+
+            if let Some(durations) = durations.get_mut(&stats.id) {
+                durations.sort();
+                stats.avg_duration = durations.iter().sum::<u64>() / durations.len() as u64;
+                stats.p95_duration = Self::get_percentile(durations, 0.95);
+            }
+
+            if let Some(days) = daily.remove(&stats.id) {
+                stats.trend = days
+                    .into_iter()
+                    .map(|mut day| {
+                        day.stats.avg_duration = if day.count == 0 { 0 } else { day.duration / day.count };
+                        day.stats
+                    })
+                    .collect();
+            }
+        }
+
+        list.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(list)
+    }
+
+    /// 按流水线统计平均耗时最长的步骤
+    async fn get_step_stats(form: &PipelineStatsQueryForm) -> Result<HashMap<String, Vec<PipelineStepStats>>, String> {
+        let sql = format!(
+            r#"
+            SELECT
+                s.pipeline_id,
+                CAST( s.stage_index AS UNSIGNED ) AS stage_index,
+                CAST( s.group_index AS UNSIGNED ) AS group_index,
+                CAST( s.step_index AS UNSIGNED ) AS step_index,
+                s.module,
+                s.label,
+                s.`status`,
+                CAST( s.duration_ms AS UNSIGNED ) AS duration_ms
+            FROM pipeline_runtime_step s
+            INNER JOIN pipeline_runtime r ON r.id = s.runtime_id
+            INNER JOIN pipeline p ON p.id = r.pipeline_id
+            WHERE 1 = 1 {}
+        "#,
+            Self::get_condition_sql(form)
+        );
+
+        let rows = DBHelper::execute_rows(Self::bind_condition(sqlx::query::<MySql>(&sql), form)).await?;
+
+        let mut map: HashMap<String, HashMap<(u32, u32, u32), StepTotal>> = HashMap::new();
+        for row in rows.iter() {
+            let pipeline_id: String = row.try_get("pipeline_id").unwrap_or(String::new());
+            let stage_index: u64 = row.try_get("stage_index").unwrap_or(0);
+            let group_index: u64 = row.try_get("group_index").unwrap_or(0);
+            let step_index: u64 = row.try_get("step_index").unwrap_or(0);
+            let duration: u64 = row.try_get::<Option<u64>, _>("duration_ms").unwrap_or(None).unwrap_or(0);
+            let status = PipelineStatus::get(&row.try_get::<String, _>("status").unwrap_or(String::new()));
+
+            let key = (stage_index as u32, group_index as u32, step_index as u32);
+            let total = map.entry(pipeline_id).or_default().entry(key).or_insert_with(|| StepTotal {
+                stats: PipelineStepStats {
+                    stage_index: key.0,
+                    group_index: key.1,
+                    step_index: key.2,
+                    ..Default::default()
+                },
+                duration: 0,
+            });
+
+            // 步骤名称可能修改过, 使用最新的
+            total.stats.module = row.try_get("module").unwrap_or(String::new());
+            total.stats.label = row.try_get("label").unwrap_or(String::new());
+            total.stats.total += 1;
+            if matches!(status, PipelineStatus::Failed) {
+                total.stats.failed += 1;
+            }
+
+            total.stats.max_duration = total.stats.max_duration.max(duration);
+            total.duration += duration;
+        }
+
+        let mut result: HashMap<String, Vec<PipelineStepStats>> = HashMap::new();
+        for (pipeline_id, steps) in map.into_iter() {
+            let mut steps: Vec<PipelineStepStats> = steps
+                .into_values()
+                .map(|mut total| {
+                    total.stats.avg_duration = total.duration / total.stats.total.max(1) as u64;
+                    total.stats
+                })
+                .collect();
+
+            steps.sort_by(|a, b| b.avg_duration.cmp(&a.avg_duration));
+            steps.truncate(SLOWEST_STEP_COUNT);
+            result.insert(pipeline_id, steps);
+        }
+
+        Ok(result)
+    }
+
+    /// 根据最近几次成功的运行, 获取每个步骤的平均耗时
+    pub(crate) async fn get_step_estimates(pipeline_id: &str) -> HashMap<(u32, u32, u32), u64> {
+        let query = sqlx::query::<MySql>(
+            r#"
+            SELECT
+                CAST( stage_index AS UNSIGNED ) AS stage_index,
+                CAST( group_index AS UNSIGNED ) AS group_index,
+                CAST( step_index AS UNSIGNED ) AS step_index,
+                CAST( duration_ms AS UNSIGNED ) AS duration_ms
+            FROM pipeline_runtime_step
+            WHERE pipeline_id = ? AND `status` = 'Success' AND duration_ms IS NOT NULL
+            ORDER BY create_time DESC
+            LIMIT 500
+        "#,
+        )
+        .bind(pipeline_id);
+
+        let rows = match DBHelper::execute_rows(query).await {
+            Ok(rows) => rows,
+            Err(err) => {
+                error!("get step estimates error: {}", err);
+                return HashMap::new();
+            }
+        };
+
+        let mut map: HashMap<(u32, u32, u32), Vec<u64>> = HashMap::new();
+        for row in rows.iter() {
+            let key = (
+                row.try_get::<u64, _>("stage_index").unwrap_or(0) as u32,
+                row.try_get::<u64, _>("group_index").unwrap_or(0) as u32,
+                row.try_get::<u64, _>("step_index").unwrap_or(0) as u32,
+            );
+
+            let durations = map.entry(key).or_default();
+            if durations.len() < ESTIMATE_RUN_COUNT {
+                durations.push(row.try_get("duration_ms").unwrap_or(0));
+            }
+        }
+
+        map.into_iter().map(|(key, durations)| (key, durations.iter().sum::<u64>() / durations.len() as u64)).collect()
+    }
+
+    /// 计算运行进度, current 为当前执行的步骤
+    pub(crate) fn get_progress(estimates: &HashMap<(u32, u32, u32), u64>, steps: &[PipelineRunnableStageStep], current: usize, elapsed_ms: u64) -> PipelineRuntimeProgress {
+        // 没有历史数据的步骤使用已知步骤的平均耗时
+        let default_estimate = if estimates.is_empty() { 0 } else { estimates.values().sum::<u64>() / estimates.len() as u64 };
+        let durations: Vec<u64> = steps.iter().map(|step| estimates.get(&(step.stage_index, step.group_index, step.step_index)).cloned().unwrap_or(default_estimate)).collect();
+
+        let total: u64 = durations.iter().sum();
+        let done: u64 = durations.iter().take(current).sum();
+        let percent = if total == 0 { current * 100 / steps.len().max(1) } else { (done * 100 / total) as usize };
+
+        PipelineRuntimeProgress {
+            percent: percent.min(100) as u32,
+            elapsed_ms,
+            eta_ms: total.saturating_sub(done),
+            queue_position: None,
+        }
+    }
+
+    /// 记录正在运行任务的预计剩余时长
+    pub(crate) fn set_running_eta(runtime_id: &str, eta_ms: u64) {
+        RUNNING_ETA.lock().unwrap().insert(runtime_id.to_string(), eta_ms);
+    }
+
+    /// 任务结束后清除
+    pub(crate) fn remove_running_eta(runtime_id: &str) {
+        RUNNING_ETA.lock().unwrap().remove(runtime_id);
+    }
+
+    /// 正在运行任务中最长的预计剩余时长
+    pub(crate) fn get_max_running_eta() -> u64 {
+        RUNNING_ETA.lock().unwrap().values().max().cloned().unwrap_or(0)
+    }
+
+    /// 获取每条流水线最近几次成功运行的平均耗时
+    pub(crate) async fn get_run_estimates(pipeline_ids: &[String]) -> HashMap<String, u64> {
+        let mut map: HashMap<String, u64> = HashMap::new();
+        for pipeline_id in pipeline_ids.iter() {
+            if map.contains_key(pipeline_id) {
+                continue;
+            }
+
+            let query = sqlx::query::<MySql>(
+                r#"
+                SELECT CAST( duration_ms AS UNSIGNED ) AS duration_ms
+                FROM pipeline_runtime
+                WHERE pipeline_id = ? AND `status` = 'Success' AND duration_ms IS NOT NULL
+                ORDER BY start_time DESC
+                LIMIT ?
+            "#,
+            )
+            .bind(pipeline_id)
+            .bind(ESTIMATE_RUN_COUNT as u32);
+
+            let durations: Vec<u64> = match DBHelper::execute_rows(query).await {
+                Ok(rows) => rows.iter().map(|row| row.try_get("duration_ms").unwrap_or(0)).collect(),
+                Err(err) => {
+                    error!("get run estimates error: {}", err);
+                    Vec::new()
+                }
+            };
+
+            let estimate = if durations.is_empty() { 0 } else { durations.iter().sum::<u64>() / durations.len() as u64 };
+            map.insert(pipeline_id.clone(), estimate);
+        }
+
+        map
+    }
+
+    /// 查询条件, 日期按 start_time 的前 10 位比较
+    fn get_condition_sql(form: &PipelineStatsQueryForm) -> String {
+        let mut sql = String::from(" AND p.server_id = ?");
+        if !form.id.is_empty() {
+            sql.push_str(" AND r.pipeline_id = ?");
+        }
+
+        if !form.start_date.is_empty() {
+            sql.push_str(" AND LEFT(r.start_time, 10) >= ?");
+        }
+
+        if !form.end_date.is_empty() {
+            sql.push_str(" AND LEFT(r.start_time, 10) <= ?");
+        }
+
+        sql
+    }
+
+    fn bind_condition<'a>(query: Query<'a, MySql, MySqlArguments>, form: &'a PipelineStatsQueryForm) -> Query<'a, MySql, MySqlArguments> {
+        let mut query = query.bind(&form.server_id);
+        if !form.id.is_empty() {
+            query = query.bind(&form.id);
+        }
+
+        if !form.start_date.is_empty() {
+            query = query.bind(&form.start_date);
+        }
+
+        if !form.end_date.is_empty() {
+            query = query.bind(&form.end_date);
+        }
+
+        query
+    }
+
+    /// 获取百分位数, list 已排序
+    fn get_percentile(list: &[u64], percentile: f64) -> u64 {
+        if list.is_empty() {
+            return 0;
+        }
+
+        let index = ((list.len() as f64) * percentile).ceil() as usize;
+        list[index.saturating_sub(1).min(list.len() - 1)]
+    }
+}
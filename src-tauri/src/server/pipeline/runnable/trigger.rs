@@ -0,0 +1,291 @@
+//! 下游流水线触发
+
+use crate::database::helper::DBHelper;
+use crate::error::Error;
+use crate::server::pipeline::index::Pipeline;
+use crate::server::pipeline::props::{PipelineDownstream, PipelineDownstreamTrigger, PipelineRuntime, PipelineRuntimeLink, PipelineRuntimeSnapshot, PipelineRuntimeVariable, PipelineStatus};
+use crate::server::pipeline::runnable::{PipelineRunnable, PipelineRunnableQueryForm};
+use log::{error, info};
+use sqlx::Row;
+use std::collections::HashSet;
+use tauri::AppHandle;
+
+pub struct PipelineTrigger;
+
+impl PipelineTrigger {
+    /// 运行结束后, 根据触发条件把下游流水线放入线程池
+    pub(crate) async fn exec_downstream(app: &AppHandle, pipeline: &Pipeline) {
+        let status = match &pipeline.status {
+            Some(status) => status.clone(),
+            None => return,
+        };
+
+        // 只在整个流水线运行结束时触发
+        if !matches!(status, PipelineStatus::Success | PipelineStatus::Failed) {
+            return;
+        }
+
+        let downstream_list = &pipeline.options.downstream;
+        if downstream_list.is_empty() {
+            return;
+        }
+
+        let runtime = pipeline.runtime.clone().unwrap_or(PipelineRuntime::default());
+
+        // 矩阵构建的子运行记录不触发, 由父运行记录汇总后触发
+        if runtime.parent_id.is_some() {
+            return;
+        }
+
+        let order = runtime.order.unwrap_or(1);
+        let chain = match Self::get_trigger_chain(pipeline, &runtime).await {
+            Ok(chain) => chain,
+            Err(err) => {
+                error!("get trigger chain error: {}", err);
+                return;
+            }
+        };
+
+        for downstream in downstream_list.iter() {
+            if !PipelineDownstreamTrigger::matched(&downstream.trigger, &status) {
+                info!("skip downstream pipeline: {}, trigger not matched !", &downstream.pipeline_id);
+                continue;
+            }
+
+            // 已在本次触发链路中, 不再触发, 防止循环触发
+            if chain.contains(&downstream.pipeline_id) {
+                let msg = format!("skip downstream pipeline {}, already in trigger chain !", &downstream.pipeline_id);
+                info!("{}", &msg);
+                PipelineRunnable::save_log(app, &msg, &pipeline.server_id, &pipeline.id, order);
+                continue;
+            }
+
+            let msg = match Self::exec(pipeline, &runtime, downstream).await {
+                Ok(msg) => msg,
+                Err(err) => {
+                    let msg = format!("trigger downstream pipeline {} error: {}", &downstream.pipeline_id, err);
+                    error!("{}", &msg);
+                    msg
+                }
+            };
+
+            PipelineRunnable::save_log(app, &msg, &pipeline.server_id, &pipeline.id, order);
+        }
+    }
+
+    /// 获取本次运行的上游触发链路中的所有流水线, 包含当前流水线
+    async fn get_trigger_chain(pipeline: &Pipeline, runtime: &PipelineRuntime) -> Result<HashSet<String>, String> {
+        let mut chain: HashSet<String> = HashSet::new();
+        chain.insert(pipeline.id.clone());
+
+        let mut visited: HashSet<String> = HashSet::new();
+        let mut runtime_id = runtime.trigger.as_ref().map(|trigger| trigger.runtime_id.clone()).unwrap_or(String::new());
+        while !runtime_id.is_empty() && visited.insert(runtime_id.clone()) {
+            let rows = DBHelper::execute_rows(sqlx::query("SELECT pipeline_id, trigger_runtime_id FROM pipeline_runtime WHERE id = ?").bind(&runtime_id)).await?;
+            let row = match rows.get(0) {
+                Some(row) => row,
+                None => break,
+            };
+
+            let pipeline_id: String = row.try_get("pipeline_id").unwrap_or(String::new());
+            chain.insert(pipeline_id);
+            runtime_id = row.try_get("trigger_runtime_id").unwrap_or(String::new());
+        }
+
+        Ok(chain)
+    }
+
+    /// 触发单条下游流水线
+    async fn exec(pipeline: &Pipeline, runtime: &PipelineRuntime, downstream: &PipelineDownstream) -> Result<String, String> {
+        let server_id = if downstream.server_id.is_empty() { pipeline.server_id.clone() } else { downstream.server_id.clone() };
+
+        let mut pipe = Pipeline::default();
+        pipe.id = downstream.pipeline_id.clone();
+        pipe.server_id = server_id.clone();
+
+        let list = Pipeline::get_pipeline_list(&pipe, None, false).await?;
+        let target = match list.get(0) {
+            Some(target) => target.clone(),
+            None => return Err(Error::convert_string("downstream pipeline not exists !")),
+        };
+
+        let mut props = PipelineRuntime::default();
+        props.pipeline_id = target.id.clone();
+        props.server_id = server_id;
+        props.tag = target.basic.tag.clone();
+        props.snapshot = Self::get_snapshot(&target).await?;
+        props.remark = format!("由流水线 {} #{} 触发", &pipeline.basic.name, runtime.order.unwrap_or(1));
+        props.trigger = Some(PipelineRuntimeLink {
+            runtime_id: runtime.id.clone().unwrap_or(String::new()),
+            pipeline_id: pipeline.id.clone(),
+            pipeline_name: pipeline.basic.name.clone(),
+            order: runtime.order,
+            status: runtime.status.clone(),
+        });
+
+        // 传递变量, 如 docker 镜像版本
+        let variables = &runtime.snapshot.runnable_variables;
+        for name in downstream.variables.iter() {
+            let variable = variables.iter().find(|variable| &variable.name == name);
+            let variable = match variable {
+                Some(variable) => variable,
+                None => {
+                    info!("downstream variable: {} not found in upstream runtime !", name);
+                    continue;
+                }
+            };
+
+            let runnable_variables = &mut props.snapshot.runnable_variables;
+            let order = runnable_variables.len() as u32 + 1;
+            match runnable_variables.iter_mut().find(|v| &v.name == name) {
+                Some(v) => v.value = variable.value.clone(),
+                None => runnable_variables.push(PipelineRuntimeVariable {
+                    id: None,
+                    snapshot_id: None,
+                    order,
+                    create_time: None,
+                    update_time: None,
+                    ..variable.clone()
+                }),
+            }
+        }
+
+        let response = PipelineRunnable::exec(&props).await?;
+        if response.code != 200 {
+            return Err(response.error);
+        }
+
+        Ok(format!("trigger downstream pipeline {} success !", &target.basic.name))
+    }
+
+    /// 获取下游流水线快照, 优先使用最后一次运行的快照
+    async fn get_snapshot(pipeline: &Pipeline) -> Result<PipelineRuntimeSnapshot, String> {
+        if let Some(last_run_id) = &pipeline.last_run_id {
+            if !last_run_id.is_empty() {
+                let result = PipelineRunnable::get_runtime_detail(
+                    pipeline,
+                    true,
+                    Some(PipelineRunnableQueryForm {
+                        status_list: vec![],
+                        runtime_id: Some(last_run_id.clone()),
+                        need_condition_last_run_id: None,
+                    }),
+                )
+                .await?;
+
+                if let Some(runtime) = result.runtime {
+                    let mut snapshot = runtime.snapshot.clone();
+                    snapshot.id = None;
+                    snapshot.runtime_id = String::new();
+                    snapshot.runnable_variables = snapshot
+                        .runnable_variables
+                        .iter()
+                        .map(|variable| PipelineRuntimeVariable {
+                            id: None,
+                            snapshot_id: None,
+                            ..variable.clone()
+                        })
+                        .collect();
+                    return Ok(snapshot);
+                }
+            }
+        }
+
+        // 从未运行过, 使用流水线变量
+        let mut snapshot = PipelineRuntimeSnapshot::default();
+        snapshot.runnable_variables = pipeline
+            .variables
+            .iter()
+            .map(|variable| PipelineRuntimeVariable {
+                id: None,
+                snapshot_id: None,
+                order: variable.order,
+                name: variable.name.clone(),
+                value: variable.value.clone(),
+                genre: variable.genre.clone(),
+                require: variable.require.clone(),
+                disabled: variable.disabled.clone(),
+                description: variable.description.clone(),
+                create_time: None,
+                update_time: None,
+            })
+            .collect();
+        Ok(snapshot)
+    }
+
+    /// 填充运行记录的上下游链路
+    pub(crate) async fn fill_runtime_links(list: &mut Vec<PipelineRuntime>) -> Result<(), String> {
+        if list.is_empty() {
+            return Ok(());
+        }
+
+        let mut ids: Vec<String> = Vec::new();
+        for runtime in list.iter() {
+            if let Some(id) = &runtime.id {
+                ids.push(id.clone());
+            }
+
+            if let Some(trigger) = &runtime.trigger {
+                ids.push(trigger.runtime_id.clone());
+            }
+        }
+
+        if ids.is_empty() {
+            return Ok(());
+        }
+
+        let placeholders = vec!["?"; ids.len()].join(", ");
+        let sql = format!(
+            r#"
+            SELECT
+                r.id AS runtime_id,
+                r.pipeline_id AS runtime_pipeline_id,
+                CAST( r.`order` AS UNSIGNED ) AS runtime_order,
+                r.`status` AS runtime_status,
+                r.trigger_runtime_id AS runtime_trigger_runtime_id,
+                b.`name` AS basic_name
+            FROM
+                pipeline_runtime r
+                LEFT JOIN pipeline_basic b ON b.pipeline_id = r.pipeline_id
+            WHERE
+                r.id IN ({}) OR r.trigger_runtime_id IN ({})
+            ORDER BY r.create_time ASC
+        "#,
+            placeholders, placeholders
+        );
+
+        let mut query = sqlx::query(&sql);
+        for id in ids.iter().chain(ids.iter()) {
+            query = query.bind(id);
+        }
+
+        let rows = DBHelper::execute_rows(query).await?;
+        for row in rows.iter() {
+            let status_str: String = row.try_get("runtime_status").unwrap_or(String::new());
+            let link = PipelineRuntimeLink {
+                runtime_id: row.try_get("runtime_id").unwrap_or(String::new()),
+                pipeline_id: row.try_get("runtime_pipeline_id").unwrap_or(String::new()),
+                pipeline_name: row.try_get("basic_name").unwrap_or(String::new()),
+                order: row.try_get("runtime_order").unwrap_or(None),
+                status: PipelineStatus::get(&status_str),
+            };
+
+            let trigger_runtime_id: String = row.try_get("runtime_trigger_runtime_id").unwrap_or(String::new());
+            for runtime in list.iter_mut() {
+                // 上游
+                if let Some(trigger) = runtime.trigger.as_mut() {
+                    if trigger.runtime_id == link.runtime_id {
+                        *trigger = link.clone();
+                    }
+                }
+
+                // 下游
+                if !trigger_runtime_id.is_empty() && runtime.id.as_ref() == Some(&trigger_runtime_id) {
+                    runtime.downstream.push(link.clone());
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,281 @@
+//! 流水线工作空间, 统计磁盘占用, 按配额及未运行天数清理
+
+use crate::database::helper::DBHelper;
+use crate::error::Error;
+use crate::helper::index::Helper;
+use crate::prepare::{get_success_response_by_value, HttpResponse};
+use crate::server::pipeline::props::{PipelineStatus, PipelineWorkspaceReport, PipelineWorkspaceUsage};
+use crate::server::pipeline::runnable::cache::PipelineCache;
+use crate::server::pipeline::runnable::PipelineRunnable;
+use crate::setting::Settings;
+use log::{error, info};
+use sqlx::Row;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+// 日志目录
+const LOG_DIR_NAME: &str = "logs";
+
+// 依赖缓存目录
+const CACHE_DIR_NAME: &str = "cache";
+
+// 定时清理间隔, 单位秒
+pub(crate) const WORKSPACE_CLEAN_SECONDS: u64 = 60 * 60;
+
+pub struct PipelineWorkspace;
+
+impl PipelineWorkspace {
+    /// 获取磁盘占用
+    pub(crate) async fn get_usage() -> Result<HttpResponse, String> {
+        let report = Self::get_report().await?;
+        get_success_response_by_value(report)
+    }
+
+    /// 清理工作空间: 已删除流水线的残留目录、过期日志、长时间未运行的流水线工作空间, 超出配额时按最后使用时间清理
+    pub(crate) async fn clean() -> Result<HttpResponse, String> {
+        info!("begin to clean pipeline workspace ...");
+        let report = Self::get_report().await?;
+        let root = Self::get_root()?;
+
+        let mut list: Vec<PipelineWorkspaceUsage> = Vec::new();
+        for usage in report.list.into_iter() {
+            // 残留目录
+            if usage.orphan {
+                info!("remove orphan pipeline dirs, server_id: {}, id: {}", &usage.server_id, &usage.id);
+                Self::remove_dir(&root.join(&usage.server_id).join(&usage.id));
+                Self::remove_dir(&root.join(LOG_DIR_NAME).join(&usage.server_id).join(&usage.id));
+                Self::remove_dir(&root.join(CACHE_DIR_NAME).join(&usage.server_id).join(&usage.id));
+                continue;
+            }
+
+            // 日志保留策略
+            PipelineRunnable::retain_log(&usage.server_id, &usage.id).await;
+            list.push(usage);
+        }
+
+        // 长时间未运行
+        let days = Self::get_clean_days();
+        let now = Self::get_now();
+        if days > 0 {
+            for usage in list.iter_mut() {
+                if usage.workspace == 0 || Self::is_running(usage) || now.saturating_sub(usage.last_used_time) < days * 24 * 60 * 60 {
+                    continue;
+                }
+
+                info!("pipeline `{}` not run for {} days, remove workspace ...", &usage.name, days);
+                Self::remove_dir(&root.join(&usage.server_id).join(&usage.id));
+                usage.total = usage.total.saturating_sub(usage.workspace);
+                usage.workspace = 0;
+            }
+        }
+
+        // 超出配额, 优先清理最久未使用的
+        let quota = Self::get_quota();
+        let mut total: u64 = list.iter().map(|usage| usage.total).sum();
+        if quota > 0 && total > quota {
+            list.sort_by(|a, b| a.last_used_time.cmp(&b.last_used_time));
+            for usage in list.iter() {
+                if total <= quota {
+                    break;
+                }
+
+                if Self::is_running(usage) || usage.workspace + usage.cache == 0 {
+                    continue;
+                }
+
+                info!("workspace exceeded quota, remove pipeline `{}` workspace and dependency cache ...", &usage.name);
+                Self::remove_dir(&root.join(&usage.server_id).join(&usage.id));
+                if let Err(err) = PipelineCache::purge(&usage.server_id, &usage.id) {
+                    error!("purge pipeline `{}` dependency cache error: {}", &usage.name, err);
+                }
+
+                total = total.saturating_sub(usage.workspace + usage.cache);
+            }
+        }
+
+        info!("clean pipeline workspace success !");
+        Self::get_usage().await
+    }
+
+    /// 删除流水线时清除工作空间及依赖缓存
+    pub(crate) fn delete(server_id: &str, id: &str) {
+        if server_id.is_empty() || id.is_empty() {
+            error!("delete pipeline workspace failed, `server_id` or `id` is empty !");
+            return;
+        }
+
+        let root = match Self::get_root() {
+            Ok(root) => root,
+            Err(err) => {
+                error!("delete pipeline workspace error: {}", err);
+                return;
+            }
+        };
+
+        Self::remove_dir(&root.join(server_id).join(id));
+        Self::remove_dir(&root.join(CACHE_DIR_NAME).join(server_id).join(id));
+    }
+
+    /// 统计工作空间、日志及依赖缓存目录
+    async fn get_report() -> Result<PipelineWorkspaceReport, String> {
+        let pipelines = Self::get_pipelines().await?;
+        let root = Self::get_root()?;
+
+        let mut map: HashMap<String, PipelineWorkspaceUsage> = HashMap::new();
+        let parents = vec![root.clone(), root.join(LOG_DIR_NAME), root.join(CACHE_DIR_NAME)];
+        for (index, parent) in parents.iter().enumerate() {
+            for server_dir in Self::get_uuid_dirs(parent).iter() {
+                for pipeline_dir in Self::get_uuid_dirs(server_dir).iter() {
+                    let server_id = Self::get_file_name(server_dir);
+                    let id = Self::get_file_name(pipeline_dir);
+                    let usage = map.entry(format!("{}/{}", server_id, id)).or_insert_with(|| {
+                        let mut usage = PipelineWorkspaceUsage::default();
+                        usage.server_id = server_id.clone();
+                        usage.id = id.clone();
+                        match pipelines.get(&id) {
+                            Some((name, status)) => {
+                                usage.name = name.clone();
+                                usage.status = status.clone();
+                            }
+                            // 查询不到任何流水线时(如新库或连错库)无法判断, 不标记为残留
+                            None => usage.orphan = !pipelines.is_empty(),
+                        }
+                        usage
+                    });
+
+                    let (size, modified) = Self::get_dir_info(pipeline_dir);
+                    match index {
+                        0 => usage.workspace = size,
+                        1 => usage.log = size,
+                        _ => usage.cache = size,
+                    }
+
+                    usage.total += size;
+                    usage.last_used_time = usage.last_used_time.max(modified);
+                }
+            }
+        }
+
+        let mut list: Vec<PipelineWorkspaceUsage> = map.into_values().collect();
+        list.sort_by(|a, b| b.total.cmp(&a.total));
+
+        let mut report = PipelineWorkspaceReport::default();
+        report.total = list.iter().map(|usage| usage.total).sum();
+        report.quota = Self::get_quota();
+        report.list = list;
+        Ok(report)
+    }
+
+    /// 查询所有流水线: id -> (名称, 状态)
+    async fn get_pipelines() -> Result<HashMap<String, (String, String)>, String> {
+        let query = sqlx::query("SELECT p.id, p.status, b.name FROM pipeline p LEFT JOIN pipeline_basic b ON b.pipeline_id = p.id");
+        let rows = DBHelper::execute_rows(query).await?;
+
+        let mut map: HashMap<String, (String, String)> = HashMap::new();
+        for row in rows.iter() {
+            let id: String = row.try_get("id").unwrap_or(String::new());
+            let status: String = row.try_get("status").unwrap_or(String::new());
+            let name: String = row.try_get("name").unwrap_or(String::new());
+            map.insert(id, (name, status));
+        }
+
+        Ok(map)
+    }
+
+    /// 是否正在运行、排队中或等待审批
+    fn is_running(usage: &PipelineWorkspaceUsage) -> bool {
+        matches!(PipelineStatus::get(&usage.status), PipelineStatus::Queue | PipelineStatus::Process | PipelineStatus::Waiting)
+    }
+
+    /// 配置根目录
+    fn get_root() -> Result<PathBuf, String> {
+        let dir = Helper::get_project_config_dir(vec![])?;
+        if let Some(dir) = dir {
+            return Ok(dir);
+        }
+
+        return Err(Error::convert_string("get pipeline workspace dir failed !"));
+    }
+
+    /// 工作空间配额, 单位字节, 未配置时为 0, 不限制
+    fn get_quota() -> u64 {
+        if let Some(settings) = Settings::get_settings() {
+            return settings.workspace_quota.trim().parse::<u64>().unwrap_or(0) * 1024 * 1024;
+        }
+
+        0
+    }
+
+    /// 未运行天数, 未配置时为 0, 不清理
+    fn get_clean_days() -> u64 {
+        if let Some(settings) = Settings::get_settings() {
+            return settings.workspace_clean_days.trim().parse::<u64>().unwrap_or(0);
+        }
+
+        0
+    }
+
+    /// 获取名称为 ID 的子目录, 服务器及流水线 ID 均为 uuid
+    fn get_uuid_dirs(dir: &Path) -> Vec<PathBuf> {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => return Vec::new(),
+        };
+
+        entries.flatten().map(|entry| entry.path()).filter(|path| path.is_dir() && Uuid::parse_str(&Self::get_file_name(path)).is_ok()).collect()
+    }
+
+    /// 统计目录大小及最后修改时间, 不跟随软链接
+    fn get_dir_info(dir: &Path) -> (u64, u64) {
+        let mut size: u64 = 0;
+        let mut modified: u64 = 0;
+
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => return (size, modified),
+        };
+
+        for entry in entries.flatten() {
+            let metadata = match fs::symlink_metadata(entry.path()) {
+                Ok(metadata) => metadata,
+                Err(_) => continue,
+            };
+
+            let time = metadata.modified().ok().and_then(|time| time.duration_since(UNIX_EPOCH).ok()).map(|time| time.as_secs()).unwrap_or(0);
+            modified = modified.max(time);
+
+            if metadata.is_dir() {
+                let (dir_size, dir_modified) = Self::get_dir_info(&entry.path());
+                size += dir_size;
+                modified = modified.max(dir_modified);
+            } else {
+                size += metadata.len();
+            }
+        }
+
+        (size, modified)
+    }
+
+    fn get_file_name(path: &Path) -> String {
+        path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or(String::new())
+    }
+
+    fn get_now() -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0)
+    }
+
+    /// 删除目录
+    fn remove_dir(dir: &Path) {
+        if !dir.exists() {
+            return;
+        }
+
+        info!("remove pipeline dir: {:#?}", dir);
+        if let Err(err) = fs::remove_dir_all(dir) {
+            error!("remove pipeline dir {:#?} error: {:#?}", dir, err);
+        }
+    }
+}
@@ -3,16 +3,24 @@
 */
 
 use crate::error::Error;
+use crate::helper::crypto::CryptoHandler;
 use crate::helper::index::Helper;
 use crate::prepare::{get_error_response, get_success_response, get_success_response_by_value, HttpResponse};
 use handlers::file::FileHandler;
+use lazy_static::lazy_static;
 use log::{error, info};
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
+use std::sync::RwLock;
 
 // 缓存目录
 const CACHE_FILE: &str = "settings.json";
 
+lazy_static! {
+    // 内存中的设置, 首次读取时从文件加载, 保存时刷新
+    static ref CACHED_SETTINGS: RwLock<Option<Settings>> = RwLock::new(None);
+}
+
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct Settings {
     #[serde(rename = "titleFontSize")]
@@ -38,6 +46,51 @@ pub struct Settings {
 
     #[serde(rename = "nodeJsDir")]
     pub(crate) node_js_dir: String,
+
+    #[serde(rename = "nodeToolchainsDir", default)]
+    pub(crate) node_toolchains_dir: String, // 多版本 nodeJs 目录, 子目录为版本号
+
+    #[serde(rename = "dependencyCacheSize", default)]
+    pub(crate) dependency_cache_size: String, // 依赖缓存总大小, 单位 MB
+
+    #[serde(rename = "workspaceQuota", default)]
+    pub(crate) workspace_quota: String, // 工作空间总大小, 单位 MB, 为空或 0 时不限制
+
+    #[serde(rename = "workspaceCleanDays", default)]
+    pub(crate) workspace_clean_days: String, // 流水线超过多少天未运行时清理工作空间, 为空或 0 时不清理
+
+    #[serde(rename = "logKeepCount", default)]
+    pub(crate) log_keep_count: String, // 每条流水线保留最近几次运行日志, 0 为不限制
+
+    #[serde(rename = "logKeepDays", default)]
+    pub(crate) log_keep_days: String, // 运行日志保留天数, 0 为不限制
+
+    #[serde(rename = "maxWorkerCount", default)]
+    pub(crate) max_worker_count: String, // 同时运行的流水线数量
+
+    #[serde(rename = "serverConcurrency", default)]
+    pub(crate) server_concurrency: String, // 同一服务器下同时运行的流水线数量, 0 为不限制
+
+    #[serde(rename = "smtpHost", default)]
+    pub(crate) smtp_host: String, // SMTP 服务器
+
+    #[serde(rename = "smtpPort", default)]
+    pub(crate) smtp_port: String, // SMTP 端口, 为空时按加密方式取默认端口
+
+    #[serde(rename = "smtpSecurity", default)]
+    pub(crate) smtp_security: String, // 加密方式: None / StartTls / Tls
+
+    #[serde(rename = "smtpUsername", default)]
+    pub(crate) smtp_username: String, // 登录用户名, 为空时不登录
+
+    #[serde(rename = "smtpPassword", default)]
+    pub(crate) smtp_password: String, // 登录密码, 加密保存
+
+    #[serde(rename = "smtpFrom", default)]
+    pub(crate) smtp_from: String, // 发件人, 为空时使用用户名
+
+    #[serde(rename = "desktopNotification", default)]
+    pub(crate) desktop_notification: String, // 运行结束时是否发送系统通知, 为空时开启
 }
 
 impl Settings {
@@ -63,6 +116,9 @@ impl Settings {
     }
 
     pub fn save(settings: &Settings) -> Result<HttpResponse, String> {
+        let mut settings = settings.clone();
+        settings.smtp_password = CryptoHandler::encrypt(&settings.smtp_password)?;
+
         let setting_file_path = Self::get_cache_file();
         if let Some(setting_file_path) = setting_file_path {
             let content = match serde_json::to_string_pretty(&settings) {
@@ -75,7 +131,11 @@ impl Settings {
 
             if let Some(content) = content {
                 match FileHandler::write_to_file_when_clear(&setting_file_path, &content) {
-                    Ok(_) => {}
+                    Ok(_) => {
+                        if let Ok(mut cached) = CACHED_SETTINGS.write() {
+                            *cached = Some(settings.clone());
+                        }
+                    }
                     Err(err) => {
                         error!("write to file `{}` error: {:#?}", setting_file_path, err);
                     }
@@ -127,6 +187,22 @@ impl Settings {
         None
     }
 
+    /// 获取内存中的设置, 用于频繁读取的场景(如线程池调度), 避免每次读取文件
+    pub(crate) fn get_cached_settings() -> Settings {
+        if let Ok(cached) = CACHED_SETTINGS.read() {
+            if let Some(settings) = cached.as_ref() {
+                return settings.clone();
+            }
+        }
+
+        let settings = Self::get_settings().unwrap_or(Settings::default());
+        if let Ok(mut cached) = CACHED_SETTINGS.write() {
+            *cached = Some(settings.clone());
+        }
+
+        settings
+    }
+
     pub fn get() -> Result<HttpResponse, String> {
         let settings = Self::get_settings();
         if let Some(settings) = settings {
@@ -65,7 +65,7 @@ impl CacheHelper {
         Ok(get_error_response("Failed to write cache config, no config dir found !"))
     }
 
-    fn get_config<T>(file_name: &str) -> Option<T>
+    pub(crate) fn get_config<T>(file_name: &str) -> Option<T>
     where
         T: DeserializeOwned + Serialize + Clone + Send + Sync + 'static,
     {